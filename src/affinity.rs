@@ -0,0 +1,214 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we restore the previous CPU affinity of the current
+/// thread before end of the current scope.
+#[must_use = "the previous CPU affinity is restored as soon as the guard is dropped"]
+pub struct TmpCpuAffinity {
+    // `None` once restored or kept
+    previous: Option<sys::Mask>,
+    // The affinity is the one of the thread which created the guard
+    _not_send: PhantomData<*const ()>,
+    policy: Policy,
+}
+
+/// Pin the current thread to the CPUs, by their index, and return a datastructure restoring the
+/// previous affinity when dropped, so a microbenchmark can pin itself for a scope only. It uses
+/// `sched_setaffinity` on Linux and `SetThreadAffinityMask` on Windows, which only handles the
+/// first 64 CPUs (32 on 32-bit targets). Threads spawned meanwhile inherit the affinity. The
+/// guard can't be sent to another thread, which would restore its own affinity instead.
+/// ```no_run
+/// {
+///     let _pinned = tmp_env::set_cpu_affinity([0]).expect("cannot set the CPU affinity");
+///     // The benchmark only runs on the first CPU
+/// }
+/// // Because guard is dropped the previous affinity is restored
+/// ```
+/// ```compile_fail
+/// let pinned = tmp_env::set_cpu_affinity([0]).expect("cannot set the CPU affinity");
+/// std::thread::spawn(move || drop(pinned));
+/// ```
+pub fn set_cpu_affinity<I: IntoIterator<Item = usize>>(
+    cpus: I,
+) -> Result<TmpCpuAffinity, std::io::Error> {
+    let mask = sys::mask(cpus)?;
+    let previous = sys::set(&mask)?;
+    Ok(TmpCpuAffinity {
+        previous: Some(previous),
+        _not_send: PhantomData,
+        policy: Policy::default(),
+    })
+}
+
+fn invalid_input(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+impl TmpCpuAffinity {
+    /// The CPUs the thread could run on before it was pinned, or `None` once it is restored or
+    /// kept
+    pub fn previous(&self) -> Option<Vec<usize>> {
+        self.previous.as_ref().map(sys::cpus)
+    }
+
+    /// Choose how the failure to restore the previous CPU affinity when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpCpuAffinity {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => {
+                sys::set(&previous)?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpCpuAffinity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.previous() {
+            Some(previous) => write!(f, "TmpCpuAffinity({:?})", previous),
+            None => write!(f, "TmpCpuAffinity"),
+        }
+    }
+}
+
+impl Drop for TmpCpuAffinity {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!("cannot restore the CPU affinity: {}", err))
+            });
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use super::invalid_input;
+
+    pub(super) type Mask = libc::cpu_set_t;
+
+    const CAPACITY: usize = std::mem::size_of::<Mask>() * 8;
+
+    pub(super) fn mask<I: IntoIterator<Item = usize>>(cpus: I) -> Result<Mask, std::io::Error> {
+        // SAFETY: cpu_set_t is a plain C struct for which all zeroes is an empty set
+        let mut mask: Mask = unsafe { std::mem::zeroed() };
+        let mut empty = true;
+        for cpu in cpus {
+            if cpu >= CAPACITY {
+                return Err(invalid_input("the CPU index is out of range"));
+            }
+            // SAFETY: the index is checked against the capacity of the set
+            unsafe { libc::CPU_SET(cpu, &mut mask) };
+            empty = false;
+        }
+        if empty {
+            return Err(invalid_input("no CPU is given"));
+        }
+        Ok(mask)
+    }
+
+    pub(super) fn get() -> Result<Mask, std::io::Error> {
+        // SAFETY: cpu_set_t is a plain C struct for which all zeroes is an empty set
+        let mut mask: Mask = unsafe { std::mem::zeroed() };
+        // SAFETY: the size given is the one of the set behind the pointer
+        if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<Mask>(), &mut mask) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(mask)
+    }
+
+    /// Set the mask of the current thread, returning the previous one
+    pub(super) fn set(mask: &Mask) -> Result<Mask, std::io::Error> {
+        let previous = get()?;
+        // SAFETY: the size given is the one of the set behind the pointer
+        if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<Mask>(), mask) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+
+    pub(super) fn cpus(mask: &Mask) -> Vec<usize> {
+        (0..CAPACITY)
+            .filter(|&cpu| {
+                // SAFETY: the index is below the capacity of the set
+                unsafe { libc::CPU_ISSET(cpu, mask) }
+            })
+            .collect()
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::invalid_input;
+    use windows_sys::Win32::System::Threading::{GetCurrentThread, SetThreadAffinityMask};
+
+    pub(super) type Mask = usize;
+
+    pub(super) fn mask<I: IntoIterator<Item = usize>>(cpus: I) -> Result<Mask, std::io::Error> {
+        let mut mask = 0;
+        for cpu in cpus {
+            if cpu >= usize::BITS as usize {
+                return Err(invalid_input("the CPU index is out of range"));
+            }
+            mask |= 1 << cpu;
+        }
+        if mask == 0 {
+            return Err(invalid_input("no CPU is given"));
+        }
+        Ok(mask)
+    }
+
+    /// Set the mask of the current thread, returning the previous one
+    pub(super) fn set(mask: &Mask) -> Result<Mask, std::io::Error> {
+        // SAFETY: the pseudo handle of the current thread is always valid
+        match unsafe { SetThreadAffinityMask(GetCurrentThread(), *mask) } {
+            0 => Err(std::io::Error::last_os_error()),
+            previous => Ok(previous),
+        }
+    }
+
+    pub(super) fn cpus(mask: &Mask) -> Vec<usize> {
+        (0..usize::BITS as usize)
+            .filter(|cpu| mask & (1 << cpu) != 0)
+            .collect()
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_affinity() {
+        let allowed = sys::cpus(&sys::get().unwrap());
+        let cpu = allowed[allowed.len() - 1];
+        {
+            let pinned = set_cpu_affinity([cpu]).expect("cannot set the CPU affinity");
+            assert_eq!(pinned.previous(), Some(allowed.clone()));
+            assert_eq!(sys::cpus(&sys::get().unwrap()), [cpu]);
+        }
+        assert_eq!(sys::cpus(&sys::get().unwrap()), allowed);
+        assert_eq!(
+            set_cpu_affinity(None).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+    }
+}
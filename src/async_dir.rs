@@ -0,0 +1,249 @@
+use std::{ffi::OsStr, process::Output};
+
+use crate::{
+    CommandExt, EnvChanges, Error, Sandbox, TempGuard, TmpDir, TmpDirBuilder, TmpFile, TmpHardLink,
+    TmpSqlite, TmpSymlink,
+};
+
+/// Same as [`create_temp_dir`](crate::create_temp_dir) but without blocking the tokio runtime
+/// ```
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let tmp_dir = tmp_env::create_temp_dir_async().await.expect("cannot create temp dir");
+/// assert!(tokio::fs::metadata(&*tmp_dir).await.unwrap().is_dir());
+/// tmp_dir.close_async().await.expect("cannot delete the tmp dir");
+/// # });
+/// ```
+pub async fn create_temp_dir_async() -> Result<TmpDir, std::io::Error> {
+    TmpDir::builder().create_async().await
+}
+
+impl TmpDirBuilder {
+    /// Same as [`TmpDirBuilder::create`] but the directory is created on the blocking thread pool
+    /// of tokio, like `tokio::fs` does, instead of blocking the runtime
+    pub async fn create_async(&self) -> Result<TmpDir, std::io::Error> {
+        let builder = self.clone();
+        tokio::task::spawn_blocking(move || builder.create())
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+impl TmpDir {
+    /// Same as [`TmpDir::close`] but on the blocking thread pool of tokio so deleting a large temporary directory
+    /// doesn't block the runtime. Dropping a [`TmpDir`] in an async context blocks the runtime
+    /// while deleting, prefer this method or [`TmpDir::close_in_background`].
+    pub async fn close_async(self) -> Result<(), std::io::Error> {
+        // Like `tokio::fs` does, but going through the same deletion as `TmpDir::close`
+        self.close_in_background()
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    /// Delete the temporary directory on the blocking thread pool of tokio without waiting for
+    /// it, the returned handle can be awaited to get the result
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let tmp_dir = tmp_env::create_temp_dir_async().await.expect("cannot create temp dir");
+    /// let path = tmp_dir.to_path_buf();
+    /// tmp_dir.close_in_background().await.unwrap().expect("cannot delete the tmp dir");
+    /// assert!(!path.exists());
+    /// # });
+    /// ```
+    pub fn close_in_background(self) -> tokio::task::JoinHandle<Result<(), std::io::Error>> {
+        tokio::task::spawn_blocking(move || self.close())
+    }
+}
+
+/// Restore the guard on the blocking thread pool of tokio, like `tokio::fs` does
+async fn restore_blocking<G: TempGuard + Send + 'static>(mut guard: G) -> Result<(), Error> {
+    tokio::task::spawn_blocking(move || guard.restore_now())
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+impl TmpDir {
+    /// Same as [`TempGuard::restore_now`] but the directory is deleted (or shredded) on the
+    /// blocking thread pool of tokio, so async tests can await their teardown instead of blocking
+    /// the runtime when dropped
+    /// ```
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let tmp_dir = tmp_env::create_temp_dir_async().await.expect("cannot create temp dir");
+    /// let path = tmp_dir.to_path_buf();
+    /// tmp_dir.restore_async().await.expect("cannot delete the tmp dir");
+    /// assert!(!path.exists());
+    /// # });
+    /// ```
+    pub async fn restore_async(self) -> Result<(), Error> {
+        restore_blocking(self).await
+    }
+}
+
+impl TmpFile {
+    /// Same as [`TempGuard::restore_now`] but the file is deleted on the blocking thread pool of
+    /// tokio instead of blocking the runtime when dropped
+    pub async fn restore_async(self) -> Result<(), Error> {
+        restore_blocking(self).await
+    }
+}
+
+impl TmpSqlite {
+    /// Same as [`TempGuard::restore_now`] but the database is deleted on the blocking thread pool
+    /// of tokio instead of blocking the runtime when dropped
+    pub async fn restore_async(self) -> Result<(), Error> {
+        restore_blocking(self).await
+    }
+}
+
+impl TmpSymlink {
+    /// Same as [`TempGuard::restore_now`] but the symlink is removed on the blocking thread pool
+    /// of tokio instead of blocking the runtime when dropped
+    pub async fn restore_async(self) -> Result<(), Error> {
+        restore_blocking(self).await
+    }
+}
+
+impl TmpHardLink {
+    /// Same as [`TempGuard::restore_now`] but the hard link is removed on the blocking thread
+    /// pool of tokio instead of blocking the runtime when dropped
+    pub async fn restore_async(self) -> Result<(), Error> {
+        restore_blocking(self).await
+    }
+}
+
+impl CommandExt for tokio::process::Command {
+    fn apply_tmp_env(&mut self, changes: &EnvChanges) -> Result<&mut Self, Error> {
+        self.as_std_mut().apply_tmp_env(changes)?;
+        Ok(self)
+    }
+}
+
+impl Sandbox {
+    /// Same as [`Sandbox::command`] but for a `tokio::process::Command`
+    pub fn tokio_command<S: AsRef<OsStr>>(
+        &self,
+        program: S,
+    ) -> Result<tokio::process::Command, Error> {
+        Ok(self.command(program)?.into())
+    }
+
+    /// Run the program with the arguments inside the sandbox (see [`Sandbox::command`]) and wait
+    /// for its exit status and output without blocking the runtime
+    /// ```no_run
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// let sandbox = tmp_env::Sandbox::new().expect("cannot create sandbox");
+    /// let output = sandbox
+    ///     .output_async("app", ["--version"])
+    ///     .await
+    ///     .expect("cannot run the command");
+    /// assert!(output.status.success());
+    /// # });
+    /// ```
+    pub async fn output_async<S, I, A>(&self, program: S, args: I) -> Result<Output, Error>
+    where
+        S: AsRef<OsStr>,
+        I: IntoIterator<Item = A>,
+        A: AsRef<OsStr>,
+    {
+        let mut command = self.tokio_command(program)?;
+        Ok(command.args(args).output().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmp_dir_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let parent = crate::create_temp_dir().expect("cannot create temp dir");
+            let tmp_dir = TmpDir::builder()
+                .parent(&*parent)
+                .prefix("async")
+                .create_async()
+                .await
+                .expect("cannot create temp dir");
+            assert!(tmp_dir.starts_with(&*parent));
+            tmp_dir.write("sub/file", "content").unwrap();
+            let path = tmp_dir.to_path_buf();
+            tmp_dir
+                .close_async()
+                .await
+                .expect("cannot delete the tmp dir");
+            assert!(!path.exists());
+
+            let tmp_dir = create_temp_dir_async()
+                .await
+                .expect("cannot create temp dir");
+            let path = tmp_dir.to_path_buf();
+            tmp_dir
+                .close_in_background()
+                .await
+                .unwrap()
+                .expect("cannot delete the tmp dir");
+            assert!(!path.exists());
+        });
+    }
+
+    #[test]
+    fn test_restore_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let tmp_dir = TmpDir::builder()
+                .secure_delete(true)
+                .create_async()
+                .await
+                .expect("cannot create temp dir");
+            tmp_dir.write("file", "secret").unwrap();
+            let tmp_file = TmpFile::builder()
+                .parent(&*tmp_dir)
+                .create()
+                .expect("cannot create temp file");
+            let file_path = tmp_file.to_path_buf();
+            tmp_file
+                .restore_async()
+                .await
+                .expect("cannot delete the tmp file");
+            assert!(!file_path.exists());
+            let path = tmp_dir.to_path_buf();
+            tmp_dir
+                .restore_async()
+                .await
+                .expect("cannot delete the tmp dir");
+            assert!(!path.exists());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sandbox_output_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let sandbox = Sandbox::new()
+                .expect("cannot create sandbox")
+                .env(EnvChanges::new().set_var("TEST_SANDBOX_ASYNC", "value"));
+            let output = sandbox
+                .output_async("sh", ["-c", "echo $TEST_SANDBOX_ASYNC $HOME"])
+                .await
+                .unwrap();
+            let expected = format!("value {}\n", sandbox.home().display());
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+            let mut command = tokio::process::Command::new("sh");
+            command
+                .apply_tmp_env(&EnvChanges::new().set_var("TEST_SANDBOX_ASYNC", "child"))
+                .unwrap()
+                .args(["-c", "echo $TEST_SANDBOX_ASYNC"]);
+            assert_eq!(command.output().await.unwrap().stdout, b"child\n");
+            assert!(std::env::var("TEST_SANDBOX_ASYNC").is_err());
+        });
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::Write,
+    ops::Deref,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    create_temp_file, create_temp_file_with_content, trace, Error, Policy, TempGuard, TmpFile,
+};
+
+/// A helper datastructure for ensuring that we restore a standard stream redirected to a
+/// temporary file before end of the current scope. The temporary file is kept until the guard is
+/// dropped, so the captured content can still be read once the stream is restored.
+#[must_use = "the stream is restored as soon as the guard is dropped"]
+pub struct TmpCapture {
+    fd: RawFd,
+    // Duplicate of the original stream, `None` once restored or kept
+    saved: Option<File>,
+    file: TmpFile,
+    policy: Policy,
+}
+
+impl Deref for TmpCapture {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+impl Debug for TmpCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpCapture({}, {:?})", self.fd, self.file.path)
+    }
+}
+
+/// Redirect the file descriptor 1 to a temporary file and return a datastructure restoring it
+/// when dropped, to check the output of code writing to the standard output of the operating
+/// system (a C library, a child process inheriting it...) and not only with `print!`, which the
+/// test harness captures on its own. The output of the other threads is captured as well.
+/// ```
+/// use std::io::Write;
+///
+/// let capture = tmp_env::capture_stdout().expect("cannot capture stdout");
+/// std::io::stdout().write_all(b"captured").unwrap();
+/// // Because stdout is restored the captured content is complete
+/// let output = capture.finish().expect("cannot restore stdout");
+/// assert_eq!(output, b"captured");
+/// ```
+pub fn capture_stdout() -> Result<TmpCapture, std::io::Error> {
+    capture(libc::STDOUT_FILENO)
+}
+
+/// Redirect the file descriptor 2 to a temporary file and return a datastructure restoring it
+/// when dropped, see [`capture_stdout`]
+pub fn capture_stderr() -> Result<TmpCapture, std::io::Error> {
+    capture(libc::STDERR_FILENO)
+}
+
+fn capture(fd: RawFd) -> Result<TmpCapture, std::io::Error> {
+    let file = create_temp_file()?;
+    flush(fd);
+    let saved = redirect(fd, file.as_file())?;
+    Ok(TmpCapture {
+        fd,
+        saved: Some(saved),
+        file,
+        policy: Policy::default(),
+    })
+}
+
+/// Make `fd` refer to the same file as `target`, returning a duplicate of the original `fd`
+pub(crate) fn redirect<T: AsRawFd>(fd: RawFd, target: &T) -> Result<File, std::io::Error> {
+    // SAFETY: duplicating a file descriptor has no memory requirements
+    let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if saved == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the duplicate was just created and is owned by nobody else
+    let saved = unsafe { File::from_raw_fd(saved) };
+    // SAFETY: dup2 only changes which file the descriptor refers to
+    if unsafe { libc::dup2(target.as_raw_fd(), fd) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(saved)
+}
+
+/// Make `fd` refer to the original file duplicated by [`redirect`] again
+pub(crate) fn restore(fd: RawFd, saved: File) -> Result<(), Error> {
+    // SAFETY: dup2 only changes which file the descriptor refers to
+    if unsafe { libc::dup2(saved.as_raw_fd(), fd) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Write what the standard library buffers for the stream before switching it
+fn flush(fd: RawFd) {
+    let _ = if fd == libc::STDOUT_FILENO {
+        std::io::stdout().flush()
+    } else {
+        std::io::stderr().flush()
+    };
+}
+
+impl TmpCapture {
+    /// The content captured so far
+    pub fn contents(&self) -> Result<Vec<u8>, std::io::Error> {
+        if self.saved.is_some() {
+            flush(self.fd);
+        }
+        std::fs::read(&*self.file)
+    }
+
+    /// Restore the stream and return the whole captured content
+    pub fn finish(mut self) -> Result<Vec<u8>, Error> {
+        self.restore_now()?;
+        Ok(self.contents()?)
+    }
+
+    /// Choose how the failure to restore the stream when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpCapture {
+    /// Leave the stream redirected to the temporary file, which is kept as well
+    fn keep(&mut self) {
+        if self.saved.take().is_some() {
+            TempGuard::keep(&mut self.file);
+        }
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.saved.take() {
+            Some(saved) => {
+                flush(self.fd);
+                restore(self.fd, saved)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+}
+
+impl Drop for TmpCapture {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!(
+                    "cannot restore the file descriptor {}: {}",
+                    self.fd, err
+                ))
+            });
+        }
+    }
+}
+
+/// A helper datastructure for ensuring that we restore the standard input redirected to a file
+/// before end of the current scope.
+#[must_use = "the standard input is restored as soon as the guard is dropped"]
+pub struct TmpStdin {
+    // Duplicate of the original standard input, `None` once restored or kept
+    saved: Option<File>,
+    // Temporary file holding the content given to `redirect_stdin`
+    file: Option<TmpFile>,
+    policy: Policy,
+}
+
+impl Debug for TmpStdin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "TmpStdin({:?})", file.path),
+            None => write!(f, "TmpStdin"),
+        }
+    }
+}
+
+/// Redirect the file descriptor 0 to a temporary file containing `content` and return a
+/// datastructure restoring it when dropped, so an interactive prompt can be tested without
+/// spawning a child process. What [`std::io::stdin`] already buffered is still read first.
+/// ```
+/// let _stdin = tmp_env::redirect_stdin("yes\n").expect("cannot redirect stdin");
+/// let mut answer = String::new();
+/// std::io::stdin().read_line(&mut answer).unwrap();
+/// assert_eq!(answer, "yes\n");
+/// ```
+pub fn redirect_stdin<C: AsRef<[u8]>>(content: C) -> Result<TmpStdin, std::io::Error> {
+    let file = create_temp_file_with_content(content)?;
+    let mut stdin = redirect_stdin_from(&*file)?;
+    stdin.file = Some(file);
+    Ok(stdin)
+}
+
+/// Redirect the file descriptor 0 to the file and return a datastructure restoring it when
+/// dropped, see [`redirect_stdin`]
+pub fn redirect_stdin_from<P: AsRef<Path>>(path: P) -> Result<TmpStdin, std::io::Error> {
+    let saved = redirect(libc::STDIN_FILENO, &File::open(path)?)?;
+    Ok(TmpStdin {
+        saved: Some(saved),
+        file: None,
+        policy: Policy::default(),
+    })
+}
+
+impl TmpStdin {
+    /// Choose how the failure to restore the standard input when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpStdin {
+    /// Leave the standard input redirected to the file, a temporary file is kept as well
+    fn keep(&mut self) {
+        if self.saved.take().is_some() {
+            if let Some(file) = &mut self.file {
+                TempGuard::keep(file);
+            }
+        }
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.saved.take() {
+            Some(saved) => restore(libc::STDIN_FILENO, saved),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.saved.is_some()
+    }
+}
+
+impl Drop for TmpStdin {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!("cannot restore the standard input: {}", err))
+            });
+        }
+    }
+}
+
+/// A helper datastructure for ensuring that we restore a file descriptor redirected with
+/// [`redirect_fd`] before end of the current scope.
+#[must_use = "the file descriptor is restored as soon as the guard is dropped"]
+pub struct TmpRedirect {
+    fd: RawFd,
+    // `None` once restored or kept
+    previous: Option<Previous>,
+    policy: Policy,
+}
+
+enum Previous {
+    /// Duplicate of the original file descriptor
+    Open(File),
+    /// The file descriptor wasn't opened, it is closed again when restored
+    Closed,
+}
+
+impl Debug for TmpRedirect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpRedirect({})", self.fd)
+    }
+}
+
+/// Make the file descriptor refer to the same file as `target` with `dup2` and return a
+/// datastructure restoring it when dropped, for the conventions passing descriptors beyond the
+/// standard streams (like a listening socket on fd 3 for systemd socket activation). The file
+/// descriptor doesn't need to be opened, it is closed again when restored, and unlike `target`
+/// it is inherited by child processes.
+/// ```
+/// let tmp_file = tmp_env::create_temp_file().expect("cannot create temp file");
+/// {
+///     let _fd3 = tmp_env::redirect_fd(3, tmp_file.as_file()).expect("cannot redirect fd 3");
+///     // Code writing to fd 3 writes to the temporary file
+/// }
+/// // Because guard is dropped fd 3 is restored
+/// ```
+pub fn redirect_fd<T: AsRawFd>(fd: RawFd, target: &T) -> Result<TmpRedirect, std::io::Error> {
+    // SAFETY: querying the flags of a descriptor has no memory requirements
+    let previous = if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        // SAFETY: dup2 only changes which file the descriptor refers to
+        if unsafe { libc::dup2(target.as_raw_fd(), fd) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Previous::Closed
+    } else {
+        Previous::Open(redirect(fd, target)?)
+    };
+    Ok(TmpRedirect {
+        fd,
+        previous: Some(previous),
+        policy: Policy::default(),
+    })
+}
+
+impl TmpRedirect {
+    /// The redirected file descriptor
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Choose how the failure to restore the file descriptor when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpRedirect {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(Previous::Open(saved)) => restore(self.fd, saved),
+            Some(Previous::Closed) => {
+                // SAFETY: the descriptor was opened by this guard and nothing else owns it
+                if unsafe { libc::close(self.fd) } == -1 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Drop for TmpRedirect {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!(
+                    "cannot restore the file descriptor {}: {}",
+                    self.fd, err
+                ))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_capture() {
+        let capture = capture_stderr().expect("cannot capture stderr");
+        let message = b"tmp_env capture test\n";
+        // SAFETY: the message is readable for the length given
+        unsafe { libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len()) };
+        let path = capture.to_path_buf();
+        // Other tests may write to stderr meanwhile
+        let output = capture.finish().unwrap();
+        assert!(output
+            .windows(message.len())
+            .any(|window| window == message));
+        assert!(!path.exists());
+
+        let mut answer = String::new();
+        {
+            let _stdin = redirect_stdin("yes\n").expect("cannot redirect stdin");
+            std::io::stdin().read_line(&mut answer).unwrap();
+        }
+        assert_eq!(answer, "yes\n");
+    }
+
+    #[test]
+    fn test_redirect_fd() {
+        // SAFETY: the content is readable for the length given
+        let write = |fd: RawFd, content: &[u8]| unsafe {
+            libc::write(fd, content.as_ptr().cast(), content.len())
+        };
+        let original = create_temp_file().unwrap();
+        let target = create_temp_file().unwrap();
+        let fd = original.as_file().as_raw_fd();
+        {
+            let redirected = redirect_fd(fd, target.as_file()).unwrap();
+            assert_eq!(redirected.fd(), fd);
+            write(fd, b"target");
+        }
+        write(fd, b"original");
+        assert_eq!(std::fs::read(&*target).unwrap(), b"target");
+        assert_eq!(std::fs::read(&*original).unwrap(), b"original");
+
+        // A descriptor above the ones opened by the other tests
+        let closed = 1000;
+        let mut redirected = redirect_fd(closed, target.as_file()).unwrap();
+        // SAFETY: querying the flags of a descriptor has no memory requirements
+        assert_ne!(unsafe { libc::fcntl(closed, libc::F_GETFD) }, -1);
+        redirected.restore_now().unwrap();
+        // SAFETY: querying the flags of a descriptor has no memory requirements
+        assert_eq!(unsafe { libc::fcntl(closed, libc::F_GETFD) }, -1);
+
+        // Closed by someone else meanwhile, it can't be closed again
+        static FAILED: AtomicBool = AtomicBool::new(false);
+        let redirected = redirect_fd(closed, target.as_file())
+            .unwrap()
+            .on_restore_failure(Policy::callback(|_| FAILED.store(true, Ordering::SeqCst)));
+        // SAFETY: the descriptor is not owned by any File of the test
+        unsafe { libc::close(closed) };
+        drop(redirected);
+        assert!(FAILED.load(Ordering::SeqCst));
+    }
+}
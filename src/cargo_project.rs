@@ -0,0 +1,249 @@
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{create_temp_dir, TmpDir};
+
+/// A helper datastructure for ensuring that we delete the temporary cargo project created before
+/// end of the current scope.
+#[must_use = "the temporary project is deleted as soon as the guard is dropped"]
+pub struct TmpCargoProject {
+    dir: TmpDir,
+}
+
+impl Deref for TmpCargoProject {
+    type Target = TmpDir;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dir
+    }
+}
+
+impl Debug for TmpCargoProject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.dir.path)
+    }
+}
+
+impl TmpCargoProject {
+    /// Path of the `Cargo.toml` of the project
+    pub fn manifest_path(&self) -> PathBuf {
+        self.dir.join("Cargo.toml")
+    }
+}
+
+/// Create a temporary directory containing a minimal binary crate named `name` (a `Cargo.toml`
+/// and a `src/main.rs` printing "Hello, world!"), see [`CargoProjectBuilder`] to configure it.
+/// It returns a datastructure to keep the project, when dropped the project is removed.
+/// ```
+/// let project = tmp_env::create_temp_cargo_project("demo").expect("cannot create project");
+/// assert!(project.manifest_path().is_file());
+/// assert!(project.join("src").join("main.rs").is_file());
+/// ```
+pub fn create_temp_cargo_project(name: &str) -> Result<TmpCargoProject, std::io::Error> {
+    CargoProjectBuilder::new(name).create()
+}
+
+/// A builder to configure the temporary cargo project created
+/// ```
+/// let project = tmp_env::CargoProjectBuilder::new("my-macro")
+///     .lib(true)
+///     .edition("2018")
+///     .dependency("syn", "2")
+///     .create()
+///     .expect("cannot create project");
+/// let manifest = project.read_to_string("Cargo.toml").unwrap();
+/// assert!(manifest.contains("syn = \"2\""));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CargoProjectBuilder {
+    name: String,
+    lib: bool,
+    edition: String,
+    dependencies: Vec<(String, Dependency)>,
+}
+
+#[derive(Debug, Clone)]
+enum Dependency {
+    Version(String),
+    Path(PathBuf),
+}
+
+impl CargoProjectBuilder {
+    /// Configure a binary crate named `name` using the 2021 edition without any dependency
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            lib: false,
+            edition: "2021".to_string(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Create a library crate (`src/lib.rs`) instead of a binary crate (`src/main.rs`)
+    pub fn lib(mut self, lib: bool) -> Self {
+        self.lib = lib;
+        self
+    }
+
+    /// Rust edition of the crate
+    pub fn edition<S: Into<String>>(mut self, edition: S) -> Self {
+        self.edition = edition.into();
+        self
+    }
+
+    /// Depend on the crate `name` from crates.io with this version requirement
+    pub fn dependency<N: Into<String>, V: AsRef<str>>(mut self, name: N, version: V) -> Self {
+        self.dependencies.push((
+            name.into(),
+            Dependency::Version(version.as_ref().to_string()),
+        ));
+        self
+    }
+
+    /// Depend on the local crate `name` at `path`, for example the crate under test. Creating the
+    /// project fails if the path is not valid UTF-8, which TOML cannot represent.
+    pub fn path_dependency<N: Into<String>, P: AsRef<Path>>(mut self, name: N, path: P) -> Self {
+        self.dependencies
+            .push((name.into(), Dependency::Path(path.as_ref().to_path_buf())));
+        self
+    }
+
+    /// Create the temporary cargo project
+    pub fn create(&self) -> Result<TmpCargoProject, std::io::Error> {
+        let mut manifest = format!(
+            "[package]\nname = {}\nversion = \"0.1.0\"\nedition = {}\n\n[dependencies]\n",
+            toml_string(&self.name),
+            toml_string(&self.edition)
+        );
+        for (name, dependency) in &self.dependencies {
+            let spec = match dependency {
+                Dependency::Version(version) => toml_string(version),
+                Dependency::Path(path) => {
+                    let path = path.to_str().ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("the path {:?} of {} is not valid UTF-8", path, name),
+                        )
+                    })?;
+                    format!("{{ path = {} }}", toml_string(path))
+                }
+            };
+            manifest.push_str(&format!("{} = {}\n", toml_key(name), spec));
+        }
+        // Never be part of a workspace the temporary directory could be nested in
+        manifest.push_str("\n[workspace]\n");
+        let dir = create_temp_dir()?;
+        dir.write("Cargo.toml", manifest)?;
+        if self.lib {
+            dir.write(
+                "src/lib.rs",
+                "pub fn add(left: u64, right: u64) -> u64 {\n    left + right\n}\n",
+            )?;
+        } else {
+            dir.write(
+                "src/main.rs",
+                "fn main() {\n    println!(\"Hello, world!\");\n}\n",
+            )?;
+        }
+
+        Ok(TmpCargoProject { dir })
+    }
+}
+
+/// Quote the value as a TOML basic string, escaping the quotes, the backslashes (like the Windows
+/// path separators) and the control characters
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Keep the key bare when TOML allows it, like the names of the crates
+fn toml_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_cargo_project() {
+        let project = CargoProjectBuilder::new("tmp-project")
+            .lib(true)
+            .edition("2018")
+            .dependency("rand", "0.8")
+            .path_dependency("tmp_env", env!("CARGO_MANIFEST_DIR"))
+            .create()
+            .expect("cannot create project");
+        assert!(project.join("src/lib.rs").is_file());
+        assert!(!project.join("src/main.rs").exists());
+        let manifest = project.read_to_string("Cargo.toml").unwrap();
+        assert!(manifest.contains("name = \"tmp-project\""));
+        assert!(manifest.contains("edition = \"2018\""));
+        assert!(manifest.contains("rand = \"0.8\""));
+        assert!(manifest.contains(&format!(
+            "tmp_env = {{ path = {} }}",
+            toml_string(env!("CARGO_MANIFEST_DIR"))
+        )));
+
+        let project = create_temp_cargo_project("hello").expect("cannot create project");
+        let output = std::process::Command::new(env!("CARGO"))
+            .args([
+                "metadata",
+                "--format-version",
+                "1",
+                "--no-deps",
+                "--offline",
+            ])
+            .arg("--manifest-path")
+            .arg(project.manifest_path())
+            .output()
+            .expect("cannot run cargo");
+        assert!(output.status.success(), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("\"name\":\"hello\""));
+    }
+
+    #[test]
+    fn test_toml_escaping() {
+        assert_eq!(
+            toml_string("C:\\dir \"quoted\"\n\u{1}é"),
+            r#""C:\\dir \"quoted\"\n\u0001é""#
+        );
+        assert_eq!(toml_key("tmp_env"), "tmp_env");
+        assert_eq!(toml_key("a.b"), "\"a.b\"");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let err = CargoProjectBuilder::new("invalid")
+                .path_dependency("dep", std::ffi::OsStr::from_bytes(b"/tmp/\xff"))
+                .create()
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    process::{Command, ExitStatus, Output},
+};
+
+use crate::{random_path, CommandExt, EnvChanges, Error};
+
+/// The variable telling the child run by [`run_in_child`] which call runs the function
+const CHILD_VAR: &str = "TMP_ENV_RUN_IN_CHILD";
+/// The variable giving the child the file it writes once it reached the call
+const REACHED_VAR: &str = "TMP_ENV_RUN_IN_CHILD_REACHED";
+
+/// Run the command with the changes applied to its environment only, and wait for its exit
+/// status and output. The environment and current directory of the current process are left
+/// untouched, even while the command runs.
+/// ```no_run
+/// let changes = tmp_env::EnvChanges::new().set_var("APP_MODE", "test");
+/// let output = tmp_env::run_command_isolated(&mut std::process::Command::new("app"), &changes)
+///     .expect("cannot run the command");
+/// assert!(output.status.success());
+/// ```
+pub fn run_command_isolated(command: &mut Command, changes: &EnvChanges) -> Result<Output, Error> {
+    Ok(command.apply_tmp_env(changes)?.output()?)
+}
+
+/// Run the function in a child process with the changes applied to the child only, and wait for
+/// its exit status. It is successful if the function returns, its panics exit with the code 101
+/// like a failed test. The environment and current directory of the current process are left
+/// untouched, even if other threads read them meanwhile.
+///
+/// The child runs the current executable again, with the same arguments (on the thread of a
+/// test, only the current test with `--exact --nocapture`), until it reaches this call, which
+/// then runs the function and exits: the code before the call runs in the child as well, and the
+/// other calls of [`run_in_child`] it reaches return a successful status without doing anything.
+/// It fails if the child exits without reaching this call. The output of the child is printed
+/// through [`print!`] and [`eprint!`], so the test harness captures it like the output of the
+/// test.
+/// ```
+/// let changes = tmp_env::EnvChanges::new().set_var("TEST_RUN_IN_CHILD", "value");
+/// let status = tmp_env::run_in_child(&changes, || {
+///     assert_eq!(std::env::var("TEST_RUN_IN_CHILD").unwrap(), "value");
+/// })
+/// .expect("cannot run the child");
+/// assert!(status.success());
+/// assert!(std::env::var("TEST_RUN_IN_CHILD").is_err());
+/// ```
+#[track_caller]
+pub fn run_in_child<F: FnOnce()>(changes: &EnvChanges, f: F) -> Result<ExitStatus, Error> {
+    let caller = std::panic::Location::caller().to_string();
+    if let Some(target) = std::env::var_os(CHILD_VAR) {
+        if target == *caller {
+            if let Some(reached) = std::env::var_os(REACHED_VAR) {
+                let _ = std::fs::write(reached, &caller);
+            }
+            let code = match catch_unwind(AssertUnwindSafe(f)) {
+                Ok(()) => 0,
+                Err(_) => 101,
+            };
+            std::process::exit(code);
+        }
+        // The child of another call
+        return Ok(ExitStatus::default());
+    }
+    let mut command = Command::new(std::env::current_exe()?);
+    // The test harness names the thread of a test after it
+    match std::thread::current().name().filter(|name| *name != "main") {
+        Some(test) => command.args([
+            test,
+            "--exact",
+            "--nocapture",
+            "--include-ignored",
+            "--test-threads=1",
+            "--quiet",
+        ]),
+        None => command.args(std::env::args_os().skip(1)),
+    };
+    let reached =
+        std::env::temp_dir().join(random_path(".tmp_env-child-".as_ref(), "".as_ref(), 10));
+    let output = command
+        .apply_tmp_env(changes)?
+        .env(CHILD_VAR, &caller)
+        .env(REACHED_VAR, &reached)
+        .output();
+    let is_reached = std::fs::remove_file(&reached).is_ok();
+    let output = output?;
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    if !is_reached {
+        return Err(std::io::Error::other(format!(
+            "the child exited with {} without reaching the call at {}",
+            output.status, caller
+        ))
+        .into());
+    }
+    Ok(output.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_current_dir;
+
+    #[test]
+    fn test_run_in_child() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        let changes = EnvChanges::new()
+            .set_var("TEST_RUN_IN_CHILD_SET", "value")
+            .current_dir("src");
+        let status = run_in_child(&changes, || {
+            assert_eq!(std::env::var("TEST_RUN_IN_CHILD_SET").unwrap(), "value");
+            assert!(std::env::current_dir().unwrap().ends_with("src"));
+        })
+        .unwrap();
+        assert!(status.success());
+        let status = run_in_child(&EnvChanges::new(), || panic!("failure in the child")).unwrap();
+        assert_eq!(status.code(), Some(101));
+        assert!(std::env::var("TEST_RUN_IN_CHILD_SET").is_err());
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+
+        #[cfg(unix)]
+        {
+            let output = run_command_isolated(
+                Command::new("sh").args(["-c", "echo $TEST_RUN_IN_CHILD_SET"]),
+                &changes,
+            )
+            .unwrap();
+            assert_eq!(output.stdout, b"value\n");
+        }
+    }
+
+    #[test]
+    fn test_run_in_child_from_thread() {
+        // Without the name of the test the child runs the same tests as the current process
+        let status = std::thread::spawn(|| {
+            run_in_child(&EnvChanges::new(), || std::process::exit(7)).unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(status.code(), Some(7));
+    }
+
+    #[test]
+    fn test_run_in_child_not_reached() {
+        // The child takes the other branch
+        if std::env::var_os(CHILD_VAR).is_some() {
+            return;
+        }
+        let err = run_in_child(&EnvChanges::new(), || {}).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    ffi::CString,
+    fmt::Debug,
+    fs::File,
+    ops::Deref,
+    os::unix::{ffi::OsStrExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we leave the root directory entered with
+/// [`chroot_temp`] before end of the current scope.
+#[must_use = "the previous root is restored as soon as the guard is dropped"]
+pub struct TmpChroot {
+    /// The new root, as seen from the previous one
+    root: PathBuf,
+    // The previous root and current directory, `None` once restored or kept
+    previous: Option<(File, File)>,
+    policy: Policy,
+}
+
+impl Deref for TmpChroot {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.root
+    }
+}
+
+impl Debug for TmpChroot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpChroot({:?})", self.root)
+    }
+}
+
+/// Change the root directory of the process to `dir`, usually a temporary directory prepared with
+/// the expected layout, and enter it, then return a datastructure going back to the previous root
+/// and current directory when dropped, for packaging and installer tests which must see an
+/// isolated filesystem. The previous root is kept opened to escape the new one, which needs the
+/// privilege to `chroot` again (`CAP_SYS_CHROOT` on Linux) when dropped.
+///
+/// The root directory is shared by all the threads of the process, run the code needing it in
+/// [`crate::run_in_child`] so the other tests keep seeing the whole filesystem.
+/// ```no_run
+/// let root = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// std::fs::create_dir_all(root.join("etc")).unwrap();
+/// {
+///     let _chroot = tmp_env::chroot_temp(&*root).expect("cannot change the root");
+///     assert!(std::path::Path::new("/etc").read_dir().unwrap().next().is_none());
+/// }
+/// // Because guard is dropped the previous root is restored
+/// ```
+pub fn chroot_temp<P: AsRef<Path>>(dir: P) -> Result<TmpChroot, std::io::Error> {
+    let root = std::fs::canonicalize(dir)?;
+    let path = CString::new(root.as_os_str().as_bytes())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let previous_root = File::open("/")?;
+    let previous_dir = File::open(".")?;
+    // SAFETY: the path is NUL terminated
+    if unsafe { libc::chroot(path.as_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let chroot = TmpChroot {
+        root,
+        previous: Some((previous_root, previous_dir)),
+        policy: Policy::default(),
+    };
+    // Without entering it the current directory would stay outside the new root
+    std::env::set_current_dir("/")?;
+    Ok(chroot)
+}
+
+impl TmpChroot {
+    /// Choose how the failure to leave the root directory when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpChroot {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some((previous_root, previous_dir)) => {
+                // Escape to the previous root, then make it the root again
+                // SAFETY: the descriptors stay open and the path is NUL terminated
+                if unsafe { libc::fchdir(previous_root.as_raw_fd()) } != 0
+                    || unsafe { libc::chroot(b".\0".as_ptr().cast()) } != 0
+                    || unsafe { libc::fchdir(previous_dir.as_raw_fd()) } != 0
+                {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Drop for TmpChroot {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!(
+                    "cannot leave the root directory {:?}: {}",
+                    self.root, err
+                ))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, run_in_child, EnvChanges};
+
+    #[test]
+    fn test_chroot_temp() {
+        // The root directory is shared by the threads running the other tests
+        let status = run_in_child(&EnvChanges::new(), || {
+            let original_dir = std::env::current_dir().unwrap();
+            let root = create_temp_dir().unwrap();
+            std::fs::write(root.join("marker"), "").unwrap();
+            let canonical_root = std::fs::canonicalize(&*root).unwrap();
+            match chroot_temp(&*root) {
+                Ok(chroot) => {
+                    assert_eq!(*chroot, canonical_root);
+                    assert!(Path::new("/marker").exists());
+                    assert_eq!(std::env::current_dir().unwrap(), Path::new("/"));
+                }
+                Err(err) => assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied),
+            }
+            assert!(root.join("marker").exists());
+            assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        })
+        .unwrap();
+        assert!(status.success());
+    }
+}
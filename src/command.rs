@@ -0,0 +1,261 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{set_current_dir, try_remove_var, try_set_var, Error, TmpEnvSet};
+
+/// The variables of a whole environment
+pub(crate) type Vars = Vec<(OsString, OsString)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EnvChange {
+    SetVar(OsString, OsString),
+    RemoveVar(OsString),
+    PrependPath(OsString, PathBuf),
+    CurrentDir(PathBuf),
+}
+
+/// A declarative list of changes to the environment, applied either to the current process with
+/// [`EnvChanges::apply`] or to a child process with [`CommandExt::apply_tmp_env`] without
+/// touching the environment of the current process
+/// ```
+/// let changes = tmp_env::EnvChanges::new()
+///     .set_var("TEST_ENV_CHANGES", "value")
+///     .remove_var("TEST_ENV_CHANGES_REMOVED")
+///     .prepend_path("PATH", "/opt/tools/bin");
+/// {
+///     let _guards = changes.apply().expect("cannot apply the changes");
+///     assert_eq!(std::env::var("TEST_ENV_CHANGES").unwrap(), "value");
+/// }
+/// assert!(std::env::var("TEST_ENV_CHANGES").is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvChanges {
+    changes: Vec<EnvChange>,
+}
+
+impl EnvChanges {
+    /// Create an empty list of changes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the environment variable to the value
+    pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, value: V) -> Self {
+        self.changes.push(EnvChange::SetVar(
+            key.as_ref().to_owned(),
+            value.as_ref().to_owned(),
+        ));
+        self
+    }
+
+    /// Remove the environment variable
+    pub fn remove_var<K: AsRef<OsStr>>(mut self, key: K) -> Self {
+        self.changes
+            .push(EnvChange::RemoveVar(key.as_ref().to_owned()));
+        self
+    }
+
+    /// Put the path before the current entries of a list of paths like `PATH`, joined with the
+    /// separator of the platform
+    pub fn prepend_path<K: AsRef<OsStr>, P: AsRef<Path>>(mut self, key: K, path: P) -> Self {
+        self.changes.push(EnvChange::PrependPath(
+            key.as_ref().to_owned(),
+            path.as_ref().to_owned(),
+        ));
+        self
+    }
+
+    /// Switch to the directory, relative to the current directory
+    pub fn current_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.changes
+            .push(EnvChange::CurrentDir(path.as_ref().to_owned()));
+        self
+    }
+
+    /// Add the changes of `other` after these ones
+    pub(crate) fn extend(&mut self, other: &EnvChanges) {
+        self.changes.extend(other.changes.iter().cloned());
+    }
+
+    /// Whether no change has been added
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply the changes to the current process in the order they were added, returning the
+    /// guards undoing them when dropped. Nothing stays changed if one of them fails.
+    #[track_caller]
+    pub fn apply(&self) -> Result<TmpEnvSet, Error> {
+        let mut guards = TmpEnvSet::new();
+        for change in &self.changes {
+            match change {
+                EnvChange::SetVar(key, value) => {
+                    guards.push(try_set_var(key, value)?);
+                }
+                EnvChange::RemoveVar(key) => {
+                    guards.push(try_remove_var(key)?);
+                }
+                EnvChange::PrependPath(key, path) => {
+                    let value = prepend_path(std::env::var_os(key), path)?;
+                    guards.push(try_set_var(key, value)?);
+                }
+                EnvChange::CurrentDir(path) => {
+                    guards.push(set_current_dir(path)?);
+                }
+            }
+        }
+        Ok(guards)
+    }
+
+    /// The whole environment resulting from the changes applied to `vars`, and the current
+    /// directory if it is changed, without applying them
+    pub(crate) fn resolve(&self, mut vars: Vars) -> Result<(Vars, Option<PathBuf>), Error> {
+        let mut current_dir: Option<PathBuf> = None;
+        for change in &self.changes {
+            match change {
+                EnvChange::SetVar(key, value) => {
+                    crate::error::check_var(key, Some(value))?;
+                    vars.retain(|(name, _)| name != key);
+                    vars.push((key.clone(), value.clone()));
+                }
+                EnvChange::RemoveVar(key) => {
+                    crate::error::check_var(key, None)?;
+                    vars.retain(|(name, _)| name != key);
+                }
+                EnvChange::PrependPath(key, path) => {
+                    let current = vars
+                        .iter()
+                        .position(|(name, _)| name == key)
+                        .map(|index| vars.remove(index).1);
+                    let value = prepend_path(current, path)?;
+                    crate::error::check_var(key, Some(&value))?;
+                    vars.push((key.clone(), value));
+                }
+                EnvChange::CurrentDir(path) => {
+                    let base = match current_dir {
+                        Some(current_dir) => current_dir,
+                        None => std::env::current_dir()?,
+                    };
+                    current_dir = Some(base.join(path));
+                }
+            }
+        }
+        Ok((vars, current_dir))
+    }
+}
+
+fn prepend_path(current: Option<OsString>, path: &Path) -> Result<OsString, Error> {
+    let mut paths = vec![path.to_path_buf()];
+    if let Some(current) = current.filter(|current| !current.is_empty()) {
+        paths.extend(std::env::split_paths(&current));
+    }
+    std::env::join_paths(paths)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err).into())
+}
+
+/// Apply [`EnvChanges`] to a [`Command`], for the child process only
+pub trait CommandExt {
+    /// Translate the changes into [`Command::env`], [`Command::env_remove`] and
+    /// [`Command::current_dir`] calls. The paths are prepended to the value already given to
+    /// the command, or else to the value of the current process.
+    /// ```
+    /// use tmp_env::CommandExt;
+    ///
+    /// let changes = tmp_env::EnvChanges::new().set_var("TEST_COMMAND_EXT", "value");
+    /// let mut command = std::process::Command::new("env");
+    /// command.apply_tmp_env(&changes).expect("cannot apply the changes");
+    /// // The current process is untouched
+    /// assert!(std::env::var("TEST_COMMAND_EXT").is_err());
+    /// ```
+    fn apply_tmp_env(&mut self, changes: &EnvChanges) -> Result<&mut Self, Error>;
+}
+
+impl CommandExt for Command {
+    fn apply_tmp_env(&mut self, changes: &EnvChanges) -> Result<&mut Self, Error> {
+        for change in &changes.changes {
+            match change {
+                EnvChange::SetVar(key, value) => {
+                    crate::error::check_var(key, Some(value))?;
+                    self.env(key, value);
+                }
+                EnvChange::RemoveVar(key) => {
+                    crate::error::check_var(key, None)?;
+                    self.env_remove(key);
+                }
+                EnvChange::PrependPath(key, path) => {
+                    let current = self
+                        .get_envs()
+                        .find(|(name, _)| name == key)
+                        .map_or_else(|| std::env::var_os(key), |(_, value)| value.map(Into::into));
+                    let value = prepend_path(current, path)?;
+                    crate::error::check_var(key, Some(&value))?;
+                    self.env(key, value);
+                }
+                EnvChange::CurrentDir(path) => {
+                    let current = self.get_current_dir().map(Path::to_path_buf);
+                    match current {
+                        Some(current) => self.current_dir(current.join(path)),
+                        None => self.current_dir(path),
+                    };
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_current_dir;
+
+    #[test]
+    fn test_command_ext() {
+        let _lock = lock_current_dir();
+        std::env::set_var("TEST_COMMAND_EXT_PATH", "/usr/bin");
+        let changes = EnvChanges::new()
+            .set_var("TEST_COMMAND_EXT_SET", "value")
+            .remove_var("TEST_COMMAND_EXT_PATH")
+            .prepend_path("TEST_COMMAND_EXT_PATH", "/opt/bin")
+            .prepend_path("TEST_COMMAND_EXT_OTHER", "/opt/other")
+            .current_dir("src");
+        let mut command = Command::new("program");
+        command.current_dir("/base");
+        command.apply_tmp_env(&changes).unwrap();
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&(
+            OsStr::new("TEST_COMMAND_EXT_SET"),
+            Some(OsStr::new("value"))
+        )));
+        assert!(envs.contains(&(
+            OsStr::new("TEST_COMMAND_EXT_PATH"),
+            Some(OsStr::new("/opt/bin"))
+        )));
+        assert!(envs.contains(&(
+            OsStr::new("TEST_COMMAND_EXT_OTHER"),
+            Some(OsStr::new("/opt/other"))
+        )));
+        assert_eq!(command.get_current_dir(), Some(Path::new("/base/src")));
+        assert!(std::env::var("TEST_COMMAND_EXT_SET").is_err());
+
+        let original_dir = std::env::current_dir().unwrap();
+        let prepend = EnvChanges::new().prepend_path("TEST_COMMAND_EXT_PATH", "/opt/bin");
+        {
+            let _guards = changes.apply().unwrap();
+            assert_eq!(std::env::var("TEST_COMMAND_EXT_SET").unwrap(), "value");
+            assert_eq!(std::env::current_dir().unwrap(), original_dir.join("src"));
+        }
+        {
+            let _guards = prepend.apply().unwrap();
+            let expected = std::env::join_paths(["/opt/bin", "/usr/bin"]).unwrap();
+            assert_eq!(std::env::var_os("TEST_COMMAND_EXT_PATH").unwrap(), expected);
+        }
+        assert_eq!(std::env::var("TEST_COMMAND_EXT_PATH").unwrap(), "/usr/bin");
+        assert!(std::env::var("TEST_COMMAND_EXT_SET").is_err());
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        std::env::remove_var("TEST_COMMAND_EXT_PATH");
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{walk::Walk, TmpDir};
+
+/// Options to recursively compare an actual directory with an expected one (for example a golden
+/// directory committed with the tests), see [`compare_dirs`]. The modification times are ignored
+/// by default.
+/// ```
+/// let expected = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// expected.write("out/a.txt", "line\n").unwrap();
+/// let actual = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// actual.write("out/a.txt", "line\r\n").unwrap();
+/// actual.write("out/debug.log", "noise").unwrap();
+/// let mismatch = tmp_env::CompareOptions::new()
+///     .ignore_line_endings(true)
+///     .ignore("*.log")
+///     .compare(&*actual, &*expected)
+///     .expect("cannot compare");
+/// assert!(mismatch.is_empty(), "{}", mismatch);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CompareOptions {
+    ignore_line_endings: bool,
+    compare_timestamps: bool,
+    ignored: Vec<String>,
+}
+
+impl CompareOptions {
+    /// Same as [`CompareOptions::default`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Consider `\r\n` and `\n` as the same in the content of the files
+    pub fn ignore_line_endings(mut self, ignore_line_endings: bool) -> Self {
+        self.ignore_line_endings = ignore_line_endings;
+        self
+    }
+
+    /// Also compare the modification times of the files
+    pub fn compare_timestamps(mut self, compare_timestamps: bool) -> Self {
+        self.compare_timestamps = compare_timestamps;
+        self
+    }
+
+    /// Ignore the entries (and the content of the directories) matching this glob pattern, on
+    /// both sides. `*` matches anything but `/`, `**` matches anything and `?` one character.
+    /// Patterns without `/` are matched against the names of the entries, other patterns
+    /// against their paths relative to the compared directories, with `/` as separator.
+    pub fn ignore<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.ignored.push(pattern.into());
+        self
+    }
+
+    /// Recursively compare `actual` with `expected`, symlinks are compared but never followed
+    pub fn compare<A: AsRef<Path>, E: AsRef<Path>>(
+        &self,
+        actual: A,
+        expected: E,
+    ) -> Result<DirMismatch, std::io::Error> {
+        let actual_entries = self.entries(actual.as_ref())?;
+        let expected_entries = self.entries(expected.as_ref())?;
+        let mut mismatch = DirMismatch::default();
+        for (relative, actual_path) in &actual_entries {
+            match expected_entries.get(relative) {
+                None => mismatch.unexpected.push(PathBuf::from(relative)),
+                Some(expected_path) => {
+                    if !self.same_entry(actual_path, expected_path)? {
+                        mismatch.different.push(PathBuf::from(relative));
+                    }
+                }
+            }
+        }
+        mismatch.missing = expected_entries
+            .keys()
+            .filter(|relative| !actual_entries.contains_key(*relative))
+            .map(PathBuf::from)
+            .collect();
+
+        Ok(mismatch)
+    }
+
+    fn entries(&self, dir: &Path) -> Result<BTreeMap<String, PathBuf>, std::io::Error> {
+        let mut entries = BTreeMap::new();
+        for entry in Walk::new(dir) {
+            let path = entry?.path();
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let relative: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let ignored = (1..=relative.len()).any(|len| {
+                let ancestor = relative[..len].join("/");
+                self.ignored.iter().any(|pattern| {
+                    let target = if pattern.contains('/') {
+                        &ancestor
+                    } else {
+                        &relative[len - 1]
+                    };
+                    glob_match(pattern.as_bytes(), target.as_bytes())
+                })
+            });
+            if !ignored {
+                entries.insert(relative.join("/"), path);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn same_entry(&self, actual: &Path, expected: &Path) -> Result<bool, std::io::Error> {
+        let actual_metadata = std::fs::symlink_metadata(actual)?;
+        let expected_metadata = std::fs::symlink_metadata(expected)?;
+        let (actual_type, expected_type) =
+            (actual_metadata.file_type(), expected_metadata.file_type());
+        if actual_type.is_symlink() || expected_type.is_symlink() {
+            return Ok(actual_type.is_symlink()
+                && expected_type.is_symlink()
+                && std::fs::read_link(actual)? == std::fs::read_link(expected)?);
+        }
+        if actual_type.is_dir() || expected_type.is_dir() {
+            return Ok(actual_type.is_dir() && expected_type.is_dir());
+        }
+        if self.compare_timestamps && actual_metadata.modified()? != expected_metadata.modified()? {
+            return Ok(false);
+        }
+        let (mut actual, mut expected) = (std::fs::read(actual)?, std::fs::read(expected)?);
+        if self.ignore_line_endings {
+            actual = normalize_line_endings(actual);
+            expected = normalize_line_endings(expected);
+        }
+
+        Ok(actual == expected)
+    }
+}
+
+/// The differences between two directories found by [`CompareOptions::compare`], with the paths
+/// relative to the compared directories
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DirMismatch {
+    /// Entries only in the expected directory
+    pub missing: Vec<PathBuf>,
+    /// Entries only in the actual directory
+    pub unexpected: Vec<PathBuf>,
+    /// Entries in both directories but with a different content, kind or symlink target
+    pub different: Vec<PathBuf>,
+}
+
+impl DirMismatch {
+    /// Returns `true` if the directories are the same
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.different.is_empty()
+    }
+}
+
+impl Display for DirMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "the directories are the same");
+        }
+        for (label, paths) in &[
+            ("missing", &self.missing),
+            ("unexpected", &self.unexpected),
+            ("different", &self.different),
+        ] {
+            for path in paths.iter() {
+                writeln!(f, "{}: {}", label, path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recursively compare `actual` with `expected` with the default [`CompareOptions`]
+pub fn compare_dirs<A: AsRef<Path>, E: AsRef<Path>>(
+    actual: A,
+    expected: E,
+) -> Result<DirMismatch, std::io::Error> {
+    CompareOptions::default().compare(actual, expected)
+}
+
+/// Panic with the list of differences if the content of the temporary directory is not the same
+/// as the content of `expected`, see [`compare_dirs`]
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// tmp_dir.write("Cargo.toml", "[package]").unwrap();
+/// let expected = tmp_env::create_temp_dir_from(&*tmp_dir).expect("cannot copy temp dir");
+/// tmp_env::assert_dir_eq(&tmp_dir, &*expected);
+/// ```
+#[track_caller]
+pub fn assert_dir_eq<P: AsRef<Path>>(tmp_dir: &TmpDir, expected: P) {
+    let expected = expected.as_ref();
+    match compare_dirs(&tmp_dir.path, expected) {
+        Ok(mismatch) if mismatch.is_empty() => {}
+        Ok(mismatch) => panic!(
+            "{:?} is not the same as {:?}:\n{}",
+            tmp_dir, expected, mismatch
+        ),
+        Err(err) => panic!("cannot compare {:?} with {:?}: {}", tmp_dir, expected, err),
+    }
+}
+
+fn normalize_line_endings(content: Vec<u8>) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(byte) = bytes.next() {
+        if *byte == b'\r' && bytes.peek() == Some(&&b'\n') {
+            continue;
+        }
+        normalized.push(*byte);
+    }
+    normalized
+}
+
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // Also let `**/` match nothing
+            let rest_after_slash = rest.strip_prefix(b"/").unwrap_or(rest);
+            glob_match(rest_after_slash, text)
+                || (0..=text.len()).any(|skip| glob_match(rest, &text[skip..]))
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|skip| *skip == 0 || text[skip - 1] != b'/')
+            .any(|skip| glob_match(rest, &text[skip..])),
+        [b'?', rest @ ..] => match text {
+            [first, text @ ..] if *first != b'/' => glob_match(rest, text),
+            _ => false,
+        },
+        [expected, rest @ ..] => match text {
+            [first, text @ ..] if first == expected => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_temp_dir;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.log", b"debug.log"));
+        assert!(!glob_match(b"*.log", b"out/debug.log"));
+        assert!(glob_match(b"out/*.log", b"out/debug.log"));
+        assert!(glob_match(b"**/*.log", b"debug.log"));
+        assert!(glob_match(b"**/*.log", b"a/b/debug.log"));
+        assert!(glob_match(b"a/**", b"a/b/c"));
+        assert!(glob_match(b"file?.txt", b"file1.txt"));
+        assert!(!glob_match(b"file?.txt", b"file10.txt"));
+        assert!(!glob_match(b"target", b"targets"));
+    }
+
+    #[test]
+    fn test_compare_dirs() {
+        let expected = create_temp_dir().expect("cannot create temp dir");
+        expected.write("same.txt", "same").unwrap();
+        expected.write("crlf.txt", "a\nb\n").unwrap();
+        expected.write("sub/missing.txt", "missing").unwrap();
+        expected.write("kind", "file").unwrap();
+        let actual = create_temp_dir().expect("cannot create temp dir");
+        actual.write("same.txt", "same").unwrap();
+        actual.write("crlf.txt", "a\r\nb\r\n").unwrap();
+        actual.create_dir("sub").unwrap();
+        actual.write("unexpected.txt", "unexpected").unwrap();
+        actual.write("kind/file", "now a dir").unwrap();
+        actual.write("target/debug/build.log", "ignored").unwrap();
+
+        let mismatch = CompareOptions::new()
+            .ignore("target")
+            .compare(&*actual, &*expected)
+            .unwrap();
+        assert_eq!(mismatch.missing, vec![PathBuf::from("sub/missing.txt")]);
+        assert_eq!(
+            mismatch.unexpected,
+            vec![PathBuf::from("kind/file"), PathBuf::from("unexpected.txt")]
+        );
+        assert_eq!(
+            mismatch.different,
+            vec![PathBuf::from("crlf.txt"), PathBuf::from("kind")]
+        );
+        assert!(mismatch.to_string().contains("missing: sub"));
+
+        let mismatch = CompareOptions::new()
+            .ignore_line_endings(true)
+            .ignore("**/*.log")
+            .ignore("kind")
+            .ignore("unexpected.txt")
+            .compare(&*actual, &*expected)
+            .unwrap();
+        assert_eq!(mismatch.different, Vec::<PathBuf>::new());
+        assert_eq!(
+            mismatch.unexpected,
+            vec![PathBuf::from("target"), PathBuf::from("target/debug")]
+        );
+
+        expected.write("sub/missing.txt", "missing").unwrap();
+        actual.write("sub/missing.txt", "missing").unwrap();
+        assert!(compare_dirs(&*expected, &*expected).unwrap().is_empty());
+        let result = std::panic::catch_unwind(|| assert_dir_eq(&actual, &*expected));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+
+use crate::{TmpFile, TmpFileBuilder};
+
+/// Format of the configuration files created by [`create_temp_config`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// TOML, with the `toml` extension
+    Toml,
+    /// Pretty printed JSON, with the `json` extension
+    Json,
+    /// YAML, with the `yaml` extension
+    Yaml,
+}
+
+impl Format {
+    /// Extension of the files in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Toml => "toml",
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+        }
+    }
+
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, std::io::Error> {
+        let res = match self {
+            Format::Toml => toml::to_string(value).map_err(|err| err.to_string()),
+            Format::Json => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string()),
+        };
+        res.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Create a temporary file in the temporary directory of your operating system containing
+/// `value` serialized in the given format, with the matching extension. It returns a
+/// datastructure to keep the file, when dropped the file is removed. Use
+/// [`TmpFileBuilder::create_config`] to choose where it is created.
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// let mut config = BTreeMap::new();
+/// config.insert("port", 8080);
+/// let file = tmp_env::create_temp_config(&config, tmp_env::Format::Toml)
+///     .expect("cannot create config file");
+/// assert_eq!(file.extension().unwrap(), "toml");
+/// assert_eq!(std::fs::read_to_string(&*file).unwrap(), "port = 8080\n");
+/// ```
+pub fn create_temp_config<T: Serialize + ?Sized>(
+    value: &T,
+    format: Format,
+) -> Result<TmpFile, std::io::Error> {
+    TmpFile::builder().create_config(value, format)
+}
+
+impl TmpFileBuilder {
+    /// Create the temporary file containing `value` serialized in the given format, the extension
+    /// of the format is used unless an extension has been set, see [`create_temp_config`]
+    pub fn create_config<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+        format: Format,
+    ) -> Result<TmpFile, std::io::Error> {
+        let content = format.serialize(value)?;
+        let builder = match &self.extension {
+            Some(_) => self.clone(),
+            None => self.clone().extension(format.extension()),
+        };
+
+        builder.create_with_content(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_create_temp_config() {
+        let mut config = BTreeMap::new();
+        config.insert("name".to_string(), vec!["a".to_string(), "b".to_string()]);
+        config.insert("other".to_string(), Vec::new());
+
+        let toml_file = create_temp_config(&config, Format::Toml).unwrap();
+        assert_eq!(toml_file.extension().unwrap(), "toml");
+        let parsed: BTreeMap<String, Vec<String>> =
+            toml::from_str(&std::fs::read_to_string(&*toml_file).unwrap()).unwrap();
+        assert_eq!(parsed, config);
+
+        let json_file = create_temp_config(&config, Format::Json).unwrap();
+        assert_eq!(json_file.extension().unwrap(), "json");
+        let parsed: BTreeMap<String, Vec<String>> =
+            serde_json::from_str(&std::fs::read_to_string(&*json_file).unwrap()).unwrap();
+        assert_eq!(parsed, config);
+
+        let parent = crate::create_temp_dir().expect("cannot create temp dir");
+        let yaml_file = TmpFile::builder()
+            .parent(&*parent)
+            .stem("config")
+            .extension("yml")
+            .create_config(&config, Format::Yaml)
+            .unwrap();
+        assert_eq!(*yaml_file, parent.join("config.yml"));
+        let parsed: BTreeMap<String, Vec<String>> =
+            serde_yaml::from_str(&std::fs::read_to_string(&*yaml_file).unwrap()).unwrap();
+        assert_eq!(parsed, config);
+
+        // TOML documents must be tables
+        let err = create_temp_config(&vec![1, 2], Format::Toml).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
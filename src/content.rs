@@ -0,0 +1,310 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    counters::{count, Counter},
+    long_path, TmpDir,
+};
+
+impl TmpDir {
+    /// Resolve a path relative to the temporary directory, refusing absolute paths and paths
+    /// escaping the temporary directory with `..`
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// assert_eq!(tmp_dir.path_of("sub/../a.txt").unwrap(), tmp_dir.join("a.txt"));
+    /// assert!(tmp_dir.path_of("../a.txt").is_err());
+    /// ```
+    pub fn path_of<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, std::io::Error> {
+        resolve(&self.path, path.as_ref())
+    }
+
+    /// Write the content in the file at the given relative path, creating the missing parent
+    /// directories, and return its full path
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("sub/a.txt", "content").expect("cannot write file");
+    /// assert_eq!(tmp_dir.read_to_string("sub/a.txt").unwrap(), "content");
+    /// ```
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        path: P,
+        content: C,
+    ) -> Result<PathBuf, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        std::fs::write(&path, content)?;
+
+        Ok(path)
+    }
+
+    /// Create (or truncate) the file at the given relative path, creating the missing parent
+    /// directories
+    pub fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<File, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        File::create(path)
+    }
+
+    /// Recursively create the directory at the given relative path and return its full path
+    pub fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, std::io::Error> {
+        let path = self.path_of(path)?;
+        std::fs::create_dir_all(&path)?;
+
+        Ok(path)
+    }
+
+    /// Create the sub directory at the given relative path and return a guard removing just that
+    /// subtree when dropped, which can be dropped before the parent temporary directory. It fails
+    /// with [`std::io::ErrorKind::AlreadyExists`] if the directory exists, so the content created
+    /// by someone else is never removed. The missing parent directories are created but not
+    /// removed.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// {
+    ///     let phase = tmp_dir.subdir("phase1").expect("cannot create sub dir");
+    ///     phase.write("a.txt", "content").expect("cannot write file");
+    /// }
+    /// assert!(!tmp_dir.join("phase1").exists());
+    /// assert!(tmp_dir.exists());
+    /// ```
+    pub fn subdir<P: AsRef<Path>>(&self, path: P) -> Result<TmpDir, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        std::fs::create_dir(&path)?;
+        count(Counter::DirsCreated, 1);
+        let mut subdir = TmpDir::new(path);
+        subdir.secure_delete = self.secure_delete;
+        subdir.retry = self.retry;
+        subdir.delete_threads = self.delete_threads;
+        subdir.deferred = self.deferred;
+        subdir.policy = self.policy.clone();
+
+        Ok(subdir)
+    }
+
+    /// Create (or truncate) the file at the given relative path with a size of `size` bytes,
+    /// creating the missing parent directories, and return its full path. The file is only
+    /// extended so it is sparse on filesystems supporting it, use
+    /// [`create_filled_file_of_size`](TmpDir::create_filled_file_of_size) to really allocate the
+    /// data.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// let path = tmp_dir.create_file_of_size("big.bin", 64 << 20).expect("cannot create file");
+    /// assert_eq!(std::fs::metadata(path).unwrap().len(), 64 << 20);
+    /// ```
+    pub fn create_file_of_size<P: AsRef<Path>>(
+        &self,
+        path: P,
+        size: u64,
+    ) -> Result<PathBuf, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        File::create(&path)?.set_len(size)?;
+
+        Ok(path)
+    }
+
+    /// Same as [`create_file_of_size`](TmpDir::create_file_of_size) but the file is filled with
+    /// zeros written out, so the data is really allocated on disk
+    pub fn create_filled_file_of_size<P: AsRef<Path>>(
+        &self,
+        path: P,
+        size: u64,
+    ) -> Result<PathBuf, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        let mut file = File::create(&path)?;
+        std::io::copy(&mut std::io::repeat(0).take(size), &mut file)?;
+
+        Ok(path)
+    }
+
+    /// Create (or truncate) the file at the given relative path with `size` bytes of pseudo-random
+    /// content, creating the missing parent directories, and return its full path. The content
+    /// only depends on `seed` (and `size`) so it is the same across runs, platforms and versions
+    /// of this crate.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.create_random_file("a.bin", 1024, 42).expect("cannot create file");
+    /// tmp_dir.create_random_file("b.bin", 1024, 42).expect("cannot create file");
+    /// assert_eq!(tmp_dir.read("a.bin").unwrap(), tmp_dir.read("b.bin").unwrap());
+    /// ```
+    pub fn create_random_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        size: u64,
+        seed: u64,
+    ) -> Result<PathBuf, std::io::Error> {
+        let path = self.path_of(path)?;
+        create_parent_dirs(&path)?;
+        let mut file = std::io::BufWriter::new(File::create(&path)?);
+        let mut state = seed;
+        let mut remaining = size;
+        while remaining > 0 {
+            let bytes = splitmix64(&mut state).to_le_bytes();
+            let len = remaining.min(bytes.len() as u64);
+            file.write_all(&bytes[..len as usize])?;
+            remaining -= len;
+        }
+        file.flush()?;
+
+        Ok(path)
+    }
+
+    /// Read the content of the file at the given relative path
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, std::io::Error> {
+        std::fs::read(self.path_of(path)?)
+    }
+
+    /// Read the content of the file at the given relative path as a string
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String, std::io::Error> {
+        std::fs::read_to_string(self.path_of(path)?)
+    }
+}
+
+/// SplitMix64, a tiny generator whose output is fully specified, unlike the generators of `rand`
+/// which can change between versions
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Resolve a path relative to `root`, see [`TmpDir::path_of`]
+pub(crate) fn resolve(root: &Path, path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if components.pop().is_some() => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{:?} escapes the tmp dir", path),
+                ))
+            }
+        }
+    }
+
+    let path = components
+        .into_iter()
+        .fold(root.to_path_buf(), |path, name| path.join(name));
+
+    Ok(long_path(&path).into_owned())
+}
+
+fn create_parent_dirs(path: &Path) -> Result<(), std::io::Error> {
+    match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_temp_dir;
+    use std::io::Write;
+
+    #[test]
+    fn test_tmp_dir_content() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let path = tmp_dir
+            .write("a/b/c.txt", "content")
+            .expect("cannot write file");
+        assert_eq!(path, tmp_dir.join("a").join("b").join("c.txt"));
+        assert_eq!(tmp_dir.read("a/b/c.txt").unwrap(), b"content");
+        assert_eq!(tmp_dir.read_to_string("./a/./b/c.txt").unwrap(), "content");
+
+        let dir = tmp_dir.create_dir("x/y").expect("cannot create dir");
+        assert!(dir.is_dir());
+        let mut file = tmp_dir.create_file("z/file").expect("cannot create file");
+        file.write_all(b"data").unwrap();
+        assert_eq!(tmp_dir.read_to_string("z/file").unwrap(), "data");
+
+        assert_eq!(tmp_dir.path_of("").unwrap(), *tmp_dir);
+        assert_eq!(tmp_dir.path_of("a/..").unwrap(), *tmp_dir);
+        for escaping in &["..", "a/../..", "../sibling", "/etc/passwd"] {
+            assert_eq!(
+                tmp_dir.path_of(escaping).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidInput
+            );
+            assert!(tmp_dir.write(escaping, "content").is_err());
+        }
+    }
+
+    #[test]
+    fn test_tmp_dir_subdir() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        tmp_dir.write("kept.txt", "content").unwrap();
+        let phase1 = tmp_dir.subdir("phases/1").expect("cannot create sub dir");
+        let nested = phase1.subdir("nested").expect("cannot create sub dir");
+        nested.write("a.txt", "content").unwrap();
+        assert_eq!(
+            tmp_dir.read_to_string("phases/1/nested/a.txt").unwrap(),
+            "content"
+        );
+        drop(nested);
+        assert!(!tmp_dir.join("phases/1/nested").exists());
+        assert!(tmp_dir.join("phases/1").is_dir());
+        drop(phase1);
+        assert!(!tmp_dir.join("phases/1").exists());
+        assert!(tmp_dir.join("phases").is_dir());
+        assert!(tmp_dir.join("kept.txt").is_file());
+        assert!(tmp_dir.subdir("../escaping").is_err());
+
+        tmp_dir.write("existing/kept.txt", "content").unwrap();
+        let err = tmp_dir.subdir("existing").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert!(tmp_dir.join("existing/kept.txt").is_file());
+    }
+
+    #[test]
+    fn test_tmp_dir_file_of_size() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let size = 8 * 1024 * 1024;
+        let sparse = tmp_dir
+            .create_file_of_size("a/sparse.bin", size)
+            .expect("cannot create file");
+        let filled = tmp_dir
+            .create_filled_file_of_size("a/filled.bin", size + 1)
+            .expect("cannot create file");
+        assert_eq!(std::fs::metadata(&sparse).unwrap().len(), size);
+        assert_eq!(std::fs::metadata(&filled).unwrap().len(), size + 1);
+        assert!(tmp_dir
+            .read("a/filled.bin")
+            .unwrap()
+            .iter()
+            .all(|b| *b == 0));
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert!(std::fs::metadata(&filled).unwrap().blocks() * 512 >= size);
+        }
+
+        tmp_dir.create_file_of_size("a/sparse.bin", 10).unwrap();
+        assert_eq!(tmp_dir.read("a/sparse.bin").unwrap(), vec![0; 10]);
+    }
+
+    #[test]
+    fn test_tmp_dir_random_file() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        tmp_dir.create_random_file("a.bin", 1001, 7).unwrap();
+        tmp_dir.create_random_file("b.bin", 1001, 7).unwrap();
+        tmp_dir.create_random_file("c.bin", 1001, 8).unwrap();
+        tmp_dir.create_random_file("empty.bin", 0, 7).unwrap();
+        let a = tmp_dir.read("a.bin").unwrap();
+        assert_eq!(a.len(), 1001);
+        assert_eq!(a, tmp_dir.read("b.bin").unwrap());
+        assert_ne!(a, tmp_dir.read("c.bin").unwrap());
+        assert!(tmp_dir.read("empty.bin").unwrap().is_empty());
+        // The content must never change for a given seed
+        assert_eq!(&a[..8], &super::splitmix64(&mut 7).to_le_bytes()[..]);
+        assert_eq!(super::splitmix64(&mut 0), 0xe220_a839_7b1d_cdaf);
+    }
+}
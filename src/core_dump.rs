@@ -0,0 +1,164 @@
+use std::fmt::Debug;
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we allow the core dumps again before end of the
+/// current scope.
+#[must_use = "the core dumps are allowed again as soon as the guard is dropped"]
+pub struct TmpCoreDumps {
+    // `None` once restored or kept
+    previous: Option<sys::Setting>,
+    policy: Policy,
+}
+
+/// Stop the crashes of the process from writing core dumps and return a datastructure restoring
+/// the previous setting when dropped, so the tests of crash handling don't fill the disk of the CI
+/// machines. It sets the soft `RLIMIT_CORE` limit to 0 on Unix, which child processes inherit,
+/// and adds `SEM_NOGPFAULTERRORBOX` to the error mode on Windows so Windows Error Reporting
+/// doesn't collect a dump.
+/// ```
+/// {
+///     let _no_core = tmp_env::suppress_core_dumps().expect("cannot suppress the core dumps");
+///     // A crash doesn't write a core file
+/// }
+/// // Because guard is dropped the core dumps are allowed again
+/// ```
+pub fn suppress_core_dumps() -> Result<TmpCoreDumps, std::io::Error> {
+    let previous = sys::suppress()?;
+    Ok(TmpCoreDumps {
+        previous: Some(previous),
+        policy: Policy::default(),
+    })
+}
+
+impl TmpCoreDumps {
+    /// Choose how the failure to allow the core dumps again when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpCoreDumps {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => Ok(sys::restore(previous)?),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpCoreDumps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpCoreDumps")
+    }
+}
+
+impl Drop for TmpCoreDumps {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!("cannot allow the core dumps again: {}", err))
+            });
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    /// The soft limit of the size of the core files
+    pub(super) type Setting = libc::rlim_t;
+
+    fn get() -> Result<libc::rlimit, std::io::Error> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: the limit is writable
+        if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(limit)
+    }
+
+    fn set(soft: Setting) -> Result<(), std::io::Error> {
+        // The hard limit is kept, so an unprivileged process can raise the soft one back
+        let limit = libc::rlimit {
+            rlim_cur: soft,
+            ..get()?
+        };
+        // SAFETY: the limit is readable
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn suppress() -> Result<Setting, std::io::Error> {
+        let previous = get()?.rlim_cur;
+        set(0)?;
+        Ok(previous)
+    }
+
+    pub(super) fn restore(previous: Setting) -> Result<(), std::io::Error> {
+        set(previous)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use windows_sys::Win32::System::Diagnostics::Debug::{
+        GetErrorMode, SetErrorMode, SEM_NOGPFAULTERRORBOX,
+    };
+
+    /// The error mode of the process
+    pub(super) type Setting = u32;
+
+    pub(super) fn suppress() -> Result<Setting, std::io::Error> {
+        // SAFETY: getting the error mode has no requirements
+        let previous = unsafe { GetErrorMode() };
+        // SAFETY: setting the error mode has no memory requirements
+        unsafe { SetErrorMode(previous | SEM_NOGPFAULTERRORBOX) };
+        Ok(previous)
+    }
+
+    pub(super) fn restore(previous: Setting) -> Result<(), std::io::Error> {
+        // SAFETY: setting the error mode has no memory requirements
+        unsafe { SetErrorMode(previous) };
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn core_limit() -> libc::rlim_t {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: the limit is writable
+        unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) };
+        limit.rlim_cur
+    }
+
+    #[test]
+    fn test_suppress_core_dumps() {
+        let previous = core_limit();
+        {
+            let _no_core = suppress_core_dumps().expect("cannot suppress the core dumps");
+            assert_eq!(core_limit(), 0);
+        }
+        assert_eq!(core_limit(), previous);
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static COUNTERS: [AtomicU64; 7] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+static MEASURE_REMOVED_BYTES: AtomicBool = AtomicBool::new(false);
+
+/// The number of temporary resources created and deleted by the process, see [`metrics`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// Temporary directories created, see [`create_temp_dir`](crate::create_temp_dir)
+    pub dirs_created: u64,
+    /// Temporary directories deleted
+    pub dirs_deleted: u64,
+    /// Temporary files created, see [`create_temp_file`](crate::create_temp_file)
+    pub files_created: u64,
+    /// Temporary files deleted
+    pub files_deleted: u64,
+    /// Size of the files deleted with the temporary directories and files, only measured with
+    /// [`measure_removed_bytes`]
+    pub bytes_removed: u64,
+    /// Environment variables set or removed, see [`set_var`](crate::set_var)
+    pub vars_changed: u64,
+    /// Guards which failed to undo their change when dropped, see
+    /// [`is_poisoned`](crate::is_poisoned)
+    pub restore_failures: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Counter {
+    DirsCreated,
+    DirsDeleted,
+    FilesCreated,
+    FilesDeleted,
+    BytesRemoved,
+    VarsChanged,
+    RestoreFailures,
+}
+
+impl Counter {
+    #[cfg(feature = "metrics")]
+    fn name(self) -> &'static str {
+        match self {
+            Counter::DirsCreated => "tmp_env_dirs_created",
+            Counter::DirsDeleted => "tmp_env_dirs_deleted",
+            Counter::FilesCreated => "tmp_env_files_created",
+            Counter::FilesDeleted => "tmp_env_files_deleted",
+            Counter::BytesRemoved => "tmp_env_bytes_removed",
+            Counter::VarsChanged => "tmp_env_vars_changed",
+            Counter::RestoreFailures => "tmp_env_restore_failures",
+        }
+    }
+}
+
+/// The counters of the temporary resources since the process started, so large test suites can
+/// track the cost of their cleanup and their leaks. With the `metrics` feature they are also
+/// exported to the recorder installed for the `metrics` crate, as `tmp_env_dirs_created`,
+/// `tmp_env_dirs_deleted`...
+/// ```
+/// let before = tmp_env::metrics();
+/// drop(tmp_env::create_temp_dir().expect("cannot create temp dir"));
+/// let after = tmp_env::metrics();
+/// assert!(after.dirs_created > before.dirs_created);
+/// assert!(after.dirs_deleted > before.dirs_deleted);
+/// ```
+pub fn metrics() -> Metrics {
+    let load = |counter: Counter| COUNTERS[counter as usize].load(Ordering::Relaxed);
+    Metrics {
+        dirs_created: load(Counter::DirsCreated),
+        dirs_deleted: load(Counter::DirsDeleted),
+        files_created: load(Counter::FilesCreated),
+        files_deleted: load(Counter::FilesDeleted),
+        bytes_removed: load(Counter::BytesRemoved),
+        vars_changed: load(Counter::VarsChanged),
+        restore_failures: load(Counter::RestoreFailures),
+    }
+}
+
+/// Measure the size of the temporary directories and files before deleting them, counted in
+/// [`Metrics::bytes_removed`]. Disabled by default because it walks the directories once more.
+/// ```
+/// tmp_env::measure_removed_bytes(true);
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// std::fs::write(tmp_dir.join("file"), [0; 16]).expect("cannot write file");
+/// let before = tmp_env::metrics().bytes_removed;
+/// drop(tmp_dir);
+/// assert!(tmp_env::metrics().bytes_removed >= before + 16);
+/// ```
+pub fn measure_removed_bytes(enabled: bool) {
+    MEASURE_REMOVED_BYTES.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn is_measuring_removed_bytes() -> bool {
+    MEASURE_REMOVED_BYTES.load(Ordering::Relaxed)
+}
+
+pub(crate) fn count(counter: Counter, value: u64) {
+    COUNTERS[counter as usize].fetch_add(value, Ordering::Relaxed);
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(counter.name()).increment(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_file, guard, set_var};
+
+    #[test]
+    fn test_metrics() {
+        let before = metrics();
+        drop(set_var("TEST_METRICS_VAR", "value"));
+        // The other tests don't expect the sizes to be measured
+        let _measure = guard(
+            || MEASURE_REMOVED_BYTES.swap(true, Ordering::Relaxed),
+            measure_removed_bytes,
+        );
+        let tmp_file = create_temp_file().expect("cannot create temp file");
+        std::fs::write(&*tmp_file, [0; 32]).unwrap();
+        drop(tmp_file);
+        let after = metrics();
+        assert!(after.vars_changed > before.vars_changed);
+        assert!(after.files_created > before.files_created);
+        assert!(after.files_deleted > before.files_deleted);
+        assert!(after.bytes_removed >= before.bytes_removed + 32);
+    }
+}
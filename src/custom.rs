@@ -0,0 +1,276 @@
+use std::{
+    any::Any,
+    fmt::Debug,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we undo a change made by [`guard`] before end of the
+/// current scope.
+#[must_use = "the change is undone as soon as the guard is dropped"]
+pub struct Guard<T, R: FnOnce(T)> {
+    state: Option<(T, R)>,
+    policy: Policy,
+}
+
+/// Apply a change to any other setting of the process (umask, locale, globals of a library) and
+/// return a datastructure undoing it with `restore`, which receives the value returned by
+/// `apply`, when dropped. It can be kept or restored early like the other guards, see
+/// [`TempGuard`].
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static LEVEL: AtomicUsize = AtomicUsize::new(1);
+/// {
+///     let _level = tmp_env::guard(
+///         || LEVEL.swap(3, Ordering::SeqCst),
+///         |previous| LEVEL.store(previous, Ordering::SeqCst),
+///     );
+///     assert_eq!(LEVEL.load(Ordering::SeqCst), 3);
+/// }
+/// // Because guard is dropped the previous level is restored
+/// assert_eq!(LEVEL.load(Ordering::SeqCst), 1);
+/// ```
+pub fn guard<T, A: FnOnce() -> T, R: FnOnce(T)>(apply: A, restore: R) -> Guard<T, R> {
+    Guard {
+        state: Some((apply(), restore)),
+        policy: Policy::default(),
+    }
+}
+
+impl<T, R: FnOnce(T)> Guard<T, R> {
+    /// Choose how the failure of `restore` when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl<T, R: FnOnce(T)> TempGuard for Guard<T, R> {
+    fn keep(&mut self) {
+        self.state = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.state.take() {
+            Some((value, restore)) => run_restore(|| restore(value)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.state.is_some()
+    }
+}
+
+impl<T: Debug, R: FnOnce(T)> Debug for Guard<T, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.state {
+            Some((value, _)) => write!(f, "Guard({:?})", value),
+            None => write!(f, "Guard"),
+        }
+    }
+}
+
+impl<T, R: FnOnce(T)> Drop for Guard<T, R> {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy
+                .handle(err, |err| trace::warn(format_args!("{}", err)));
+        }
+    }
+}
+
+/// A helper datastructure for ensuring that we write back the value of a setting before end of
+/// the current scope. The value is read with the getter when created and written back with the
+/// setter when dropped.
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static THREADS: AtomicUsize = AtomicUsize::new(8);
+/// {
+///     let threads = tmp_env::Restore::new(
+///         || THREADS.load(Ordering::SeqCst),
+///         |threads| THREADS.store(threads, Ordering::SeqCst),
+///     );
+///     threads.set(1);
+///     assert_eq!(THREADS.load(Ordering::SeqCst), 1);
+///     assert_eq!(threads.previous(), Some(&8));
+/// }
+/// // Because guard is dropped the previous value is written back
+/// assert_eq!(THREADS.load(Ordering::SeqCst), 8);
+/// ```
+#[must_use = "the value is written back as soon as the guard is dropped"]
+pub struct Restore<T, S: Fn(T)> {
+    previous: Option<T>,
+    set: S,
+    policy: Policy,
+}
+
+impl<T, S: Fn(T)> Restore<T, S> {
+    /// Read the current value of the setting with `get`, it is written back with `set` when
+    /// dropped
+    pub fn new<G: FnOnce() -> T>(get: G, set: S) -> Self {
+        Self {
+            previous: Some(get()),
+            set,
+            policy: Policy::default(),
+        }
+    }
+
+    /// Choose how the failure to write back the value when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Change the value of the setting with the setter
+    pub fn set(&self, value: T) {
+        (self.set)(value);
+    }
+
+    /// The value read when created, which is written back when dropped, or `None` once it is
+    /// restored or kept
+    pub fn previous(&self) -> Option<&T> {
+        self.previous.as_ref()
+    }
+}
+
+impl<T, S: Fn(T)> TempGuard for Restore<T, S> {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => run_restore(|| (self.set)(previous)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl<T: Debug, S: Fn(T)> Debug for Restore<T, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.previous {
+            Some(previous) => write!(f, "Restore({:?})", previous),
+            None => write!(f, "Restore"),
+        }
+    }
+}
+
+impl<T, S: Fn(T)> Drop for Restore<T, S> {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy
+                .handle(err, |err| trace::warn(format_args!("{}", err)));
+        }
+    }
+}
+
+/// Run the restoration given by the user. Its panic is returned as an error instead of aborting
+/// the process when the thread is already unwinding, or always with the `no-panic` feature.
+fn run_restore<F: FnOnce()>(restore: F) -> Result<(), Error> {
+    if cfg!(feature = "no-panic") || std::thread::panicking() {
+        catch_unwind(AssertUnwindSafe(restore)).map_err(|panic| {
+            std::io::Error::other(format!(
+                "the restoration panicked: {}",
+                panic_message(&*panic)
+            ))
+            .into()
+        })
+    } else {
+        restore();
+        Ok(())
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map_or("", String::as_str),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::RefCell,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    #[test]
+    fn test_guard() {
+        let setting = RefCell::new(vec!["original"]);
+        let set = |value| std::mem::replace(&mut *setting.borrow_mut(), value);
+        {
+            let guard = guard(|| set(vec!["changed"]), |previous| drop(set(previous)));
+            assert!(guard.is_active());
+            assert_eq!(format!("{:?}", guard), r#"Guard(["original"])"#);
+            assert_eq!(*setting.borrow(), ["changed"]);
+        }
+        assert_eq!(*setting.borrow(), ["original"]);
+
+        let mut restored = guard(|| set(vec!["changed"]), |previous| drop(set(previous)));
+        restored.restore_now().unwrap();
+        assert!(!restored.is_active());
+        assert_eq!(*setting.borrow(), ["original"]);
+        set(vec!["changed again"]);
+        drop(restored);
+        assert_eq!(*setting.borrow(), ["changed again"]);
+
+        let mut kept = guard(|| set(vec!["kept"]), |previous| drop(set(previous)));
+        kept.keep();
+        drop(kept);
+        assert_eq!(*setting.borrow(), ["kept"]);
+    }
+
+    #[test]
+    fn test_restore() {
+        let setting = RefCell::new(4);
+        {
+            let restore = Restore::new(|| *setting.borrow(), |value| *setting.borrow_mut() = value);
+            restore.set(1);
+            assert_eq!(*setting.borrow(), 1);
+            assert_eq!(restore.previous(), Some(&4));
+            assert_eq!(format!("{:?}", restore), "Restore(4)");
+        }
+        assert_eq!(*setting.borrow(), 4);
+
+        let mut kept = Restore::new(|| *setting.borrow(), |value| *setting.borrow_mut() = value);
+        kept.set(2);
+        kept.keep();
+        assert!(!kept.is_active());
+        assert!(kept.previous().is_none());
+        drop(kept);
+        assert_eq!(*setting.borrow(), 2);
+    }
+
+    #[test]
+    fn test_panicking_restore() {
+        static FAILED: AtomicBool = AtomicBool::new(false);
+        let restore = || {
+            let _guard = guard(|| (), |()| panic!("cannot restore"))
+                .on_restore_failure(Policy::callback(|_| FAILED.store(true, Ordering::SeqCst)));
+            panic!("test failure");
+        };
+        // The panic of the restoration doesn't abort the process while unwinding
+        let panic = std::panic::catch_unwind(restore).unwrap_err();
+        assert_eq!(panic_message(&*panic), "test failure");
+        assert!(FAILED.load(Ordering::SeqCst));
+
+        let mut restore = Restore::new(|| (), |()| panic!("cannot restore"));
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| restore.restore_now()));
+        if cfg!(feature = "no-panic") {
+            let err = res.unwrap().unwrap_err();
+            assert_eq!(err.to_string(), "the restoration panicked: cannot restore");
+        } else {
+            assert!(res.is_err());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    sync::{Condvar, Mutex, PoisonError},
+};
+
+use crate::TmpDir;
+
+/// Prefix of the name a temporary directory is renamed to before its deferred deletion
+const TRASH_PREFIX: &str = ".tmp_env-trash-";
+
+struct Queue {
+    pending: VecDeque<TmpDir>,
+    in_progress: usize,
+    worker: bool,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+    pending: VecDeque::new(),
+    in_progress: 0,
+    worker: false,
+});
+static QUEUE_CHANGED: Condvar = Condvar::new();
+
+/// Rename the temporary directory out of the way and queue its deletion for the background
+/// cleanup thread. Returns `false` if it can't be deferred and must be deleted right now.
+pub(crate) fn defer(tmp_dir: &mut TmpDir) -> bool {
+    let (parent, name) = match (tmp_dir.path.parent(), tmp_dir.path.file_name()) {
+        (Some(parent), Some(name)) => (parent, name),
+        _ => return false,
+    };
+    let mut trash_name = OsString::from(TRASH_PREFIX);
+    trash_name.push(name);
+    let trash = parent.join(trash_name);
+    // Renaming in the same directory never crosses filesystems
+    if std::fs::rename(&tmp_dir.path, &trash).is_err() {
+        return false;
+    }
+    let mut trashed = TmpDir::new(trash);
+    trashed.secure_delete = tmp_dir.secure_delete;
+    trashed.read_only = tmp_dir.read_only;
+    trashed.retry = tmp_dir.retry;
+    trashed.delete_threads = tmp_dir.delete_threads;
+    trashed.policy = tmp_dir.policy.clone();
+    std::mem::take(&mut tmp_dir.path);
+
+    let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+    queue.pending.push_back(trashed);
+    if !queue.worker {
+        let spawned = std::thread::Builder::new()
+            .name("tmp_env-cleanup".to_string())
+            .spawn(|| {
+                let _worker = Worker;
+                while delete_next(true) {}
+            });
+        queue.worker = spawned.is_ok();
+    }
+    QUEUE_CHANGED.notify_all();
+
+    true
+}
+
+/// Delete the next queued directory, waiting for one if `wait` is `true`. Returns `false` once
+/// there is nothing left to delete.
+fn delete_next(wait: bool) -> bool {
+    let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+    let tmp_dir = loop {
+        match queue.pending.pop_front() {
+            Some(tmp_dir) => break tmp_dir,
+            None if wait => {
+                queue = QUEUE_CHANGED
+                    .wait(queue)
+                    .unwrap_or_else(PoisonError::into_inner)
+            }
+            None => return false,
+        }
+    };
+    queue.in_progress += 1;
+    drop(queue);
+    let _in_progress = InProgress;
+    // Errors are reported like for any other temporary directory
+    drop(tmp_dir);
+
+    true
+}
+
+/// Count the directory being deleted as done, even if its policy panics
+struct InProgress;
+
+impl Drop for InProgress {
+    fn drop(&mut self) {
+        let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.in_progress -= 1;
+        QUEUE_CHANGED.notify_all();
+    }
+}
+
+/// Let the next deferred deletion start another background thread if this one dies
+struct Worker;
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        QUEUE.lock().unwrap_or_else(PoisonError::into_inner).worker = false;
+    }
+}
+
+/// Delete right now the temporary directories whose deletion has been deferred with
+/// [`TmpDirBuilder::deferred_delete`](crate::TmpDirBuilder::deferred_delete), and wait for the
+/// ones being deleted by the background cleanup thread. Call it before exiting the process (for
+/// example at the end of `main` or of a test harness) because the background thread is stopped
+/// abruptly on exit.
+/// ```
+/// let tmp_dir = tmp_env::TmpDir::builder()
+///     .deferred_delete(true)
+///     .create()
+///     .expect("cannot create temp dir");
+/// drop(tmp_dir); // Only renamed
+/// tmp_env::flush_cleanup(); // Now deleted
+/// ```
+pub fn flush_cleanup() {
+    while delete_next(false) {}
+    let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+    while queue.in_progress > 0 || !queue.pending.is_empty() {
+        queue = QUEUE_CHANGED
+            .wait(queue)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, Policy};
+    use std::{
+        panic::AssertUnwindSafe,
+        path::Path,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    // A file can't be deleted as a directory
+    fn undeletable_dir(parent: &Path, policy: Policy) -> TmpDir {
+        let tmp_dir = TmpDir::builder()
+            .parent(parent)
+            .deferred_delete(true)
+            .create()
+            .expect("cannot create temp dir")
+            .on_restore_failure(policy);
+        std::fs::remove_dir(&*tmp_dir).unwrap();
+        std::fs::write(&*tmp_dir, "").unwrap();
+        tmp_dir
+    }
+
+    #[test]
+    fn test_deferred_delete_failure() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        static FAILURES: AtomicUsize = AtomicUsize::new(0);
+        drop(undeletable_dir(
+            &parent,
+            Policy::callback(|_| {
+                FAILURES.fetch_add(1, Ordering::SeqCst);
+            }),
+        ));
+        flush_cleanup();
+        assert_eq!(FAILURES.load(Ordering::SeqCst), 1);
+
+        // The panic happens on the background thread or in `flush_cleanup`, which never hangs
+        drop(undeletable_dir(&parent, Policy::Panic));
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(flush_cleanup));
+        flush_cleanup();
+        let tmp_dir = TmpDir::builder()
+            .parent(&*parent)
+            .deferred_delete(true)
+            .create()
+            .expect("cannot create temp dir");
+        let path = tmp_dir.to_path_buf();
+        drop(tmp_dir);
+        flush_cleanup();
+        let mut trash = OsString::from(TRASH_PREFIX);
+        trash.push(path.file_name().unwrap());
+        assert!(!parent.join(trash).exists());
+    }
+
+    #[test]
+    fn test_tmp_dir_deferred_delete() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        let tmp_dir = TmpDir::builder()
+            .parent(&*parent)
+            .deferred_delete(true)
+            .create()
+            .expect("cannot create temp dir");
+        tmp_dir.write("a/b/c.txt", "content").unwrap();
+        let path = tmp_dir.to_path_buf();
+        drop(tmp_dir);
+        assert!(!path.exists());
+        flush_cleanup();
+        assert_eq!(std::fs::read_dir(&*parent).unwrap().count(), 0);
+
+        // Closing explicitly is never deferred
+        let tmp_dir = TmpDir::builder()
+            .parent(&*parent)
+            .deferred_delete(true)
+            .create()
+            .expect("cannot create temp dir");
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert_eq!(std::fs::read_dir(&*parent).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    static PLAN: RefCell<Option<Vec<PlannedChange>>> = const { RefCell::new(None) };
+}
+
+/// A change recorded instead of being applied by [`dry_run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlannedChange {
+    /// The environment variable would be set to this value, see [`set_var`](crate::set_var)
+    SetVar {
+        /// The name of the environment variable
+        key: OsString,
+        /// The value it would be set to
+        value: OsString,
+    },
+    /// The environment variable would be removed, see [`remove_var`](crate::remove_var)
+    RemoveVar {
+        /// The name of the environment variable
+        key: OsString,
+    },
+    /// The current directory would be switched to this path, see
+    /// [`set_current_dir`](crate::set_current_dir)
+    SetCurrentDir(PathBuf),
+    /// This temporary directory has been created, see [`create_temp_dir`](crate::create_temp_dir)
+    CreateDir(PathBuf),
+    /// This temporary file has been created, see [`create_temp_file`](crate::create_temp_file)
+    CreateFile(PathBuf),
+}
+
+impl Display for PlannedChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlannedChange::SetVar { key, value } => write!(f, "set {:?} to {:?}", key, value),
+            PlannedChange::RemoveVar { key } => write!(f, "remove {:?}", key),
+            PlannedChange::SetCurrentDir(path) => write!(f, "switch to {:?}", path),
+            PlannedChange::CreateDir(path) => write!(f, "create the directory {:?}", path),
+            PlannedChange::CreateFile(path) => write!(f, "create the file {:?}", path),
+        }
+    }
+}
+
+/// The changes recorded by [`dry_run`], in the order they were requested
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    changes: Vec<PlannedChange>,
+}
+
+impl Plan {
+    /// The changes recorded
+    pub fn changes(&self) -> &[PlannedChange] {
+        &self.changes
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the function in dry-run mode on the current thread: [`set_var`](crate::set_var),
+/// [`remove_var`](crate::remove_var) and [`set_current_dir`](crate::set_current_dir) (and the
+/// functions built on them) only record what they would change and return guards doing nothing,
+/// so the plan of a complex fixture builder can be displayed or verified. The temporary
+/// directories and files are still created, to give the fixture somewhere to write, and recorded
+/// as well.
+/// ```
+/// let ((), plan) = tmp_env::dry_run(|| {
+///     let _tmp_env = tmp_env::set_var("TEST_DRY_RUN", "value");
+///     assert!(std::env::var("TEST_DRY_RUN").is_err());
+/// });
+/// assert_eq!(
+///     plan.changes(),
+///     [tmp_env::PlannedChange::SetVar {
+///         key: "TEST_DRY_RUN".into(),
+///         value: "value".into(),
+///     }]
+/// );
+/// print!("{}", plan);
+/// ```
+pub fn dry_run<R, F: FnOnce() -> R>(f: F) -> (R, Plan) {
+    // Restores the outer dry run even if the function panics
+    struct Scope(Option<Vec<PlannedChange>>);
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            let outer = self.0.take();
+            PLAN.with(|plan| *plan.borrow_mut() = outer);
+        }
+    }
+
+    let scope = Scope(PLAN.with(|plan| plan.borrow_mut().replace(Vec::new())));
+    let res = f();
+    let changes = PLAN.with(|plan| plan.borrow_mut().take().unwrap_or_default());
+    drop(scope);
+    (res, Plan { changes })
+}
+
+/// Whether the current thread runs in [`dry_run`]
+pub fn is_dry_run() -> bool {
+    PLAN.with(|plan| plan.borrow().is_some())
+}
+
+/// Record the change if the current thread runs in dry-run mode
+pub(crate) fn record(change: PlannedChange) {
+    PLAN.with(|plan| {
+        if let Some(changes) = &mut *plan.borrow_mut() {
+            changes.push(change);
+        }
+    })
+}
+
+pub(crate) fn record_created(path: &Path, is_dir: bool) {
+    if is_dry_run() {
+        let path = path.to_path_buf();
+        record(if is_dir {
+            PlannedChange::CreateDir(path)
+        } else {
+            PlannedChange::CreateFile(path)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, lock_current_dir, remove_var, set_current_dir, set_var};
+
+    #[test]
+    fn test_dry_run() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        std::env::set_var("TEST_DRY_RUN_REMOVED", "kept");
+        let (tmp_dir, plan) = dry_run(|| {
+            assert!(is_dry_run());
+            let _env = set_var("TEST_DRY_RUN_SET", "value");
+            let _removed = remove_var("TEST_DRY_RUN_REMOVED");
+            let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+            let current_dir = set_current_dir(&*tmp_dir).expect("cannot set current dir");
+            assert_eq!(current_dir.current(), &*tmp_dir);
+            assert_eq!(std::env::current_dir().unwrap(), original_dir);
+            let ((), nested) = dry_run(|| drop(set_var("TEST_DRY_RUN_NESTED", "value")));
+            assert_eq!(nested.changes().len(), 1);
+            tmp_dir
+        });
+        assert!(!is_dry_run());
+        assert!(std::env::var("TEST_DRY_RUN_SET").is_err());
+        assert_eq!(std::env::var("TEST_DRY_RUN_REMOVED").unwrap(), "kept");
+        std::env::remove_var("TEST_DRY_RUN_REMOVED");
+        assert_eq!(
+            plan.changes(),
+            [
+                PlannedChange::SetVar {
+                    key: "TEST_DRY_RUN_SET".into(),
+                    value: "value".into()
+                },
+                PlannedChange::RemoveVar {
+                    key: "TEST_DRY_RUN_REMOVED".into()
+                },
+                PlannedChange::CreateDir(tmp_dir.to_path_buf()),
+                PlannedChange::SetCurrentDir(tmp_dir.to_path_buf()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, Once, PoisonError, TryLockError,
+    },
+};
+
+use crate::remove_tree;
+
+/// A change to undo if the process exits without dropping its guard
+enum Change {
+    Dir(PathBuf),
+    Var(OsString, Option<String>),
+}
+
+static CHANGES: Mutex<BTreeMap<u64, Change>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static INSTALL_HANDLERS: Once = Once::new();
+
+/// Register a temporary directory to delete on abnormal exit, until [`unregister`] is called
+pub(crate) fn register_dir(path: PathBuf) -> u64 {
+    register(Change::Dir(path))
+}
+
+/// Register an environment variable to restore on abnormal exit, until [`unregister`] is called
+pub(crate) fn register_var(key: OsString, previous_val: Option<String>) -> u64 {
+    register(Change::Var(key, previous_val))
+}
+
+fn register(change: Change) -> u64 {
+    INSTALL_HANDLERS.call_once(install_handlers);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    CHANGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(id, change);
+    id
+}
+
+/// The guard undoes its change (or keeps it), nothing to do on exit anymore. Returns `false` if
+/// the change has already been undone by [`emergency_cleanup`].
+pub(crate) fn unregister(id: u64) -> bool {
+    CHANGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id)
+        .is_some()
+}
+
+/// Undo right now, in the reverse order they were made, the changes of the guards created with
+/// [`TmpDirBuilder::cleanup_on_exit`](crate::TmpDirBuilder::cleanup_on_exit) and
+/// [`CurrentEnv::restore_on_exit`](crate::CurrentEnv::restore_on_exit) which are still alive.
+/// It is called automatically when the process exits with [`std::process::exit`] or is
+/// interrupted by `SIGINT` or `SIGTERM` (or a console control event on Windows), when the
+/// guards are not dropped. The guards then do nothing when dropped. On Unix the signal handler
+/// only wakes up a thread dedicated to the cleanup, started with the first opted in guard,
+/// which then hands the signal over to the handler installed before.
+/// ```
+/// let tmp_dir = tmp_env::TmpDir::builder()
+///     .cleanup_on_exit(true)
+///     .create()
+///     .expect("cannot create temp dir");
+/// let _env = tmp_env::set_var("TEST_EMERGENCY_CLEANUP", "value").restore_on_exit();
+/// tmp_env::emergency_cleanup();
+/// assert!(!tmp_dir.exists());
+/// assert!(std::env::var("TEST_EMERGENCY_CLEANUP").is_err());
+/// ```
+pub fn emergency_cleanup() {
+    let changes = match CHANGES.try_lock() {
+        Ok(mut changes) => std::mem::take(&mut *changes),
+        Err(TryLockError::Poisoned(poisoned)) => std::mem::take(&mut *poisoned.into_inner()),
+        // Interrupted by a signal while registering a change, better leave the changes than
+        // deadlock
+        Err(TryLockError::WouldBlock) => return,
+    };
+    for (_, change) in changes.into_iter().rev() {
+        match change {
+            Change::Dir(path) => {
+                let _ = remove_tree(&path);
+            }
+            Change::Var(key, Some(previous_val)) => std::env::set_var(key, previous_val),
+            Change::Var(key, None) => std::env::remove_var(key),
+        }
+    }
+}
+
+extern "C" fn at_exit() {
+    emergency_cleanup();
+}
+
+#[cfg(unix)]
+mod handlers {
+    use std::sync::{
+        atomic::{AtomicI32, Ordering},
+        mpsc,
+    };
+
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    /// The end of the pipe the signal handler writes the signals to, read by the cleanup thread
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    #[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "fuchsia"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__errno_location()
+    }
+
+    #[cfg(any(target_os = "android", target_os = "openbsd", target_os = "netbsd"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__errno()
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::__error()
+    }
+
+    #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+    unsafe fn errno() -> *mut libc::c_int {
+        libc::___errno()
+    }
+
+    /// Unknown location, errno isn't preserved
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "fuchsia",
+        target_os = "android",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "solaris",
+        target_os = "illumos"
+    )))]
+    unsafe fn errno() -> *mut libc::c_int {
+        std::ptr::null_mut()
+    }
+
+    /// Only forward the signal to the cleanup thread, the cleanup isn't async-signal-safe
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let byte = signal as u8;
+        // SAFETY: errno is local to the thread, and only read and written back by the handler
+        // around `write`, which is async-signal-safe and reads the byte living on the stack
+        unsafe {
+            let errno = errno();
+            let saved = errno.as_ref().copied();
+            libc::write(PIPE.load(Ordering::Relaxed), (&byte as *const u8).cast(), 1);
+            if let Some(saved) = saved {
+                *errno = saved;
+            }
+        }
+    }
+
+    /// Undo the changes when a signal is received, then hand the signal over to the action
+    /// replaced by our handler (by default dying from it as if we never handled it)
+    fn cleanup_thread(read: libc::c_int, previous_actions: Vec<(libc::c_int, libc::sigaction)>) {
+        loop {
+            let mut byte = 0u8;
+            // SAFETY: `read` is the read end of the pipe owned by this thread, filling one byte
+            match unsafe { libc::read(read, (&mut byte as *mut u8).cast(), 1) } {
+                1 => {}
+                -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {
+                    continue
+                }
+                _ => return,
+            }
+            super::emergency_cleanup();
+            let signal = libc::c_int::from(byte);
+            if let Some((_, previous)) = previous_actions.iter().find(|(s, _)| *s == signal) {
+                // SAFETY: `previous` is the action returned by `sigaction` when installing ours
+                unsafe {
+                    libc::sigaction(signal, previous, std::ptr::null_mut());
+                    libc::raise(signal);
+                }
+            }
+        }
+    }
+
+    /// Replace the actions of the signals by ours, returning the previous ones
+    fn install_signal_handlers() -> Vec<(libc::c_int, libc::sigaction)> {
+        let mut previous_actions = Vec::new();
+        for signal in SIGNALS {
+            // SAFETY: the actions are zero initialized plain C structs, fully set before use, and
+            // the handler only does async-signal-safe operations
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                let mut previous: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(signal, &action, &mut previous) == 0 {
+                    if previous.sa_sigaction == libc::SIG_IGN {
+                        // The signal is ignored on purpose (e.g. `nohup`), keep ignoring it
+                        libc::sigaction(signal, &previous, std::ptr::null_mut());
+                    } else {
+                        previous_actions.push((signal, previous));
+                    }
+                }
+            }
+        }
+        previous_actions
+    }
+
+    pub(super) fn install() {
+        // SAFETY: `at_exit` is a function without arguments living as long as the process
+        unsafe {
+            libc::atexit(super::at_exit);
+        }
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two file descriptors
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return;
+        }
+        let [read, write] = fds;
+        // SAFETY: both file descriptors have just been created
+        unsafe {
+            libc::fcntl(read, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(write, libc::F_SETFD, libc::FD_CLOEXEC);
+            // Never block the interrupted thread
+            libc::fcntl(write, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+        // The handlers are only installed once the thread handling the signals is running
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name(String::from("tmp_env cleanup"))
+            .spawn(move || {
+                if let Ok(previous_actions) = receiver.recv() {
+                    cleanup_thread(read, previous_actions);
+                }
+            });
+        if spawned.is_err() {
+            // SAFETY: nobody else uses the file descriptors
+            unsafe {
+                libc::close(read);
+                libc::close(write);
+            }
+            return;
+        }
+        PIPE.store(write, Ordering::Relaxed);
+        let _ = sender.send(install_signal_handlers());
+    }
+}
+
+#[cfg(windows)]
+mod handlers {
+    use windows_sys::Win32::{Foundation::BOOL, System::Console::SetConsoleCtrlHandler};
+
+    extern "C" {
+        fn atexit(callback: extern "C" fn()) -> std::os::raw::c_int;
+    }
+
+    unsafe extern "system" fn on_console_event(_ctrl_type: u32) -> BOOL {
+        super::emergency_cleanup();
+        // Let the next handler (by default the one exiting the process) run
+        0
+    }
+
+    pub(super) fn install() {
+        // SAFETY: both callbacks are functions living as long as the process
+        unsafe {
+            SetConsoleCtrlHandler(Some(on_console_event), 1);
+            atexit(super::at_exit);
+        }
+    }
+}
+
+fn install_handlers() {
+    handlers::install();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{set_var, TmpDir};
+
+    #[test]
+    fn test_emergency_cleanup() {
+        let kept = TmpDir::builder()
+            .cleanup_on_exit(true)
+            .create()
+            .expect("cannot create temp dir");
+        let kept_path = kept.keep();
+        let dropped = TmpDir::builder()
+            .cleanup_on_exit(true)
+            .create()
+            .expect("cannot create temp dir");
+        drop(dropped);
+        let tmp_dir = TmpDir::builder()
+            .cleanup_on_exit(true)
+            .create()
+            .expect("cannot create temp dir");
+        tmp_dir.write("sub/file", "content").unwrap();
+        std::env::set_var("TEST_EMERGENCY_VAR", "original");
+        let first = set_var("TEST_EMERGENCY_VAR", "first").restore_on_exit();
+        let second = set_var("TEST_EMERGENCY_VAR", "second").restore_on_exit();
+        let mut opted_out_dir = TmpDir::builder()
+            .cleanup_on_exit(true)
+            .create()
+            .expect("cannot create temp dir");
+        opted_out_dir.set_cleanup_on_exit(false);
+        let mut opted_out_var = set_var("TEST_EMERGENCY_OPTED_OUT", "value").restore_on_exit();
+        opted_out_var.set_restore_on_exit(false);
+
+        emergency_cleanup();
+        assert!(!tmp_dir.exists());
+        assert!(kept_path.exists());
+        assert!(opted_out_dir.exists());
+        assert_eq!(std::env::var("TEST_EMERGENCY_OPTED_OUT").unwrap(), "value");
+        drop(opted_out_var);
+        assert!(std::env::var("TEST_EMERGENCY_OPTED_OUT").is_err());
+        // Restored in reverse order
+        assert_eq!(std::env::var("TEST_EMERGENCY_VAR").unwrap(), "original");
+        // The guards have nothing to undo anymore
+        std::env::set_var("TEST_EMERGENCY_VAR", "changed");
+        drop(second);
+        drop(first);
+        assert_eq!(std::env::var("TEST_EMERGENCY_VAR").unwrap(), "changed");
+        std::env::remove_var("TEST_EMERGENCY_VAR");
+        std::fs::remove_dir(kept_path).unwrap();
+    }
+}
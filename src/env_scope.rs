@@ -0,0 +1,177 @@
+use std::{ffi::OsStr, fmt::Debug, path::Path};
+
+use crate::{remove_var, set_current_dir, set_var, Error, TempGuard, TmpEnvSet};
+
+/// A stack of frames recording the changes made through it. [`EnvScope::exit`] undoes the changes
+/// of the last frame entered, so fixtures can layer their setup and teardown without keeping
+/// their guards, and everything left is undone when dropped.
+/// ```
+/// let mut scope = tmp_env::EnvScope::new();
+/// scope.set_var("TEST_ENV_SCOPE", "suite");
+/// scope.enter();
+/// scope.set_var("TEST_ENV_SCOPE", "test").remove_var("TEST_ENV_SCOPE_REMOVED");
+/// assert_eq!(std::env::var("TEST_ENV_SCOPE").unwrap(), "test");
+/// scope.exit().expect("cannot exit the frame");
+/// assert_eq!(std::env::var("TEST_ENV_SCOPE").unwrap(), "suite");
+/// drop(scope);
+/// assert!(std::env::var("TEST_ENV_SCOPE").is_err());
+/// ```
+#[must_use = "the changes are undone as soon as the scope is dropped"]
+pub struct EnvScope {
+    // Never empty, the first frame is the one of the scope itself
+    frames: Vec<TmpEnvSet>,
+}
+
+impl Default for EnvScope {
+    fn default() -> Self {
+        Self {
+            frames: vec![TmpEnvSet::new()],
+        }
+    }
+}
+
+impl EnvScope {
+    /// Create a scope with a single frame
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin a nested frame, the next changes are undone by the matching [`EnvScope::exit`]
+    pub fn enter(&mut self) -> &mut Self {
+        self.frames.push(TmpEnvSet::new());
+        self
+    }
+
+    /// Undo the changes made since the last [`EnvScope::enter`], in the reverse order they were
+    /// made, and go back to the outer frame. Without nested frame the changes of the scope itself
+    /// are undone. All the changes are undone even if some fail, the first error is returned.
+    pub fn exit(&mut self) -> Result<(), Error> {
+        if self.frames.len() > 1 {
+            let mut frame = self.frames.pop().expect("there is a nested frame");
+            frame.restore_all()
+        } else {
+            self.current().restore_all()
+        }
+    }
+
+    /// Number of nested frames entered and not exited yet
+    pub fn depth(&self) -> usize {
+        self.frames.len() - 1
+    }
+
+    /// Set the environment variable in the current frame, see [`set_var`]
+    pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.push(set_var(key, value))
+    }
+
+    /// Remove the environment variable in the current frame, see [`remove_var`]
+    pub fn remove_var<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.push(remove_var(key))
+    }
+
+    /// Switch to the directory in the current frame, see [`set_current_dir`]
+    pub fn set_current_dir<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<&mut Self, std::io::Error> {
+        let current_dir = set_current_dir(path)?;
+        Ok(self.push(current_dir))
+    }
+
+    /// Add any other guard to the current frame, it is restored with the frame
+    pub fn push<G: TempGuard + 'static>(&mut self, guard: G) -> &mut Self {
+        self.current().push(guard);
+        self
+    }
+
+    fn current(&mut self) -> &mut TmpEnvSet {
+        self.frames.last_mut().expect("the scope has a frame")
+    }
+}
+
+impl TempGuard for EnvScope {
+    fn keep(&mut self) {
+        for frame in self.frames.iter_mut().rev() {
+            frame.keep_all();
+        }
+        self.frames.truncate(1);
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        let mut res = Ok(());
+        while self.depth() > 0 {
+            let frame_res = self.exit();
+            if res.is_ok() {
+                res = frame_res;
+            }
+        }
+        let frame_res = self.exit();
+        res.and(frame_res)
+    }
+
+    fn is_active(&self) -> bool {
+        self.frames.iter().any(|frame| frame.is_active())
+    }
+}
+
+impl Debug for EnvScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EnvScope({} frames)", self.frames.len())
+    }
+}
+
+impl Drop for EnvScope {
+    fn drop(&mut self) {
+        while let Some(frame) = self.frames.pop() {
+            drop(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, lock_current_dir};
+
+    #[test]
+    fn test_env_scope() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        {
+            let mut scope = EnvScope::new();
+            scope.set_var("TEST_ENV_SCOPE_LAYERS", "suite");
+            scope.enter();
+            scope
+                .set_current_dir(&*tmp_dir)
+                .expect("cannot set current dir");
+            scope.set_var("TEST_ENV_SCOPE_LAYERS", "fixture");
+            scope.enter().set_var("TEST_ENV_SCOPE_LAYERS", "test");
+            assert_eq!(scope.depth(), 2);
+            assert_eq!(std::env::var("TEST_ENV_SCOPE_LAYERS").unwrap(), "test");
+
+            scope.exit().unwrap();
+            assert_eq!(std::env::var("TEST_ENV_SCOPE_LAYERS").unwrap(), "fixture");
+            scope.exit().unwrap();
+            assert_eq!(scope.depth(), 0);
+            assert_eq!(std::env::var("TEST_ENV_SCOPE_LAYERS").unwrap(), "suite");
+            assert_eq!(std::env::current_dir().unwrap(), original_dir);
+
+            // Dropped with a nested frame
+            scope.enter().set_var("TEST_ENV_SCOPE_LAYERS", "leaked");
+        }
+        assert!(std::env::var("TEST_ENV_SCOPE_LAYERS").is_err());
+
+        let mut scope = EnvScope::new();
+        scope.set_var("TEST_ENV_SCOPE_LAYERS", "root");
+        scope.exit().unwrap();
+        assert!(std::env::var("TEST_ENV_SCOPE_LAYERS").is_err());
+        scope.enter().set_var("TEST_ENV_SCOPE_LAYERS", "kept");
+        scope.keep();
+        assert!(!scope.is_active());
+        assert_eq!(scope.depth(), 0);
+        drop(scope);
+        assert_eq!(std::env::var("TEST_ENV_SCOPE_LAYERS").unwrap(), "kept");
+        std::env::remove_var("TEST_ENV_SCOPE_LAYERS");
+    }
+}
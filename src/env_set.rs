@@ -0,0 +1,150 @@
+use std::fmt::Debug;
+
+use crate::{Error, TempGuard};
+
+/// A collection of guards restored in the reverse order they were pushed when dropped, so the
+/// last change is undone first like separate guards declared in a scope would be
+/// ```
+/// let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+/// {
+///     let mut set = tmp_env::TmpEnvSet::new();
+///     set.push(tmp_env::set_var("TEST_TMP_ENV_SET", "value"));
+///     set.push(tmp_env::set_current_dir("src").expect("should set the new current_dir"));
+///     assert_eq!(set.len(), 2);
+/// }
+/// // Because the set is dropped everything is restored
+/// assert!(std::env::var("TEST_TMP_ENV_SET").is_err());
+/// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+/// ```
+#[derive(Default)]
+#[must_use = "the guards are restored as soon as the set is dropped"]
+pub struct TmpEnvSet {
+    guards: Vec<Box<dyn TempGuard>>,
+}
+
+impl TmpEnvSet {
+    /// Create an empty set of guards
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a guard to the set, it is restored before the guards already in the set
+    pub fn push<G: TempGuard + 'static>(&mut self, guard: G) -> &mut Self {
+        self.guards.push(Box::new(guard));
+        self
+    }
+
+    /// Number of guards in the set
+    pub fn len(&self) -> usize {
+        self.guards.len()
+    }
+
+    /// Returns `true` if no guard has been pushed in the set
+    pub fn is_empty(&self) -> bool {
+        self.guards.is_empty()
+    }
+
+    /// Keep all the changes made by the guards of the set, nothing is undone when dropped
+    pub fn keep_all(&mut self) {
+        for guard in self.guards.iter_mut().rev() {
+            guard.keep();
+        }
+        self.guards.clear();
+    }
+
+    /// Restore all the guards of the set right now, in the reverse order they were pushed. All
+    /// the guards are restored even if some fail, the first error is returned.
+    /// ```
+    /// let mut set = tmp_env::TmpEnvSet::new();
+    /// set.push(tmp_env::set_var("TEST_TMP_ENV_SET_RESTORE", "first"));
+    /// set.push(tmp_env::set_var("TEST_TMP_ENV_SET_RESTORE", "second"));
+    /// set.restore_all().expect("cannot restore");
+    /// assert!(std::env::var("TEST_TMP_ENV_SET_RESTORE").is_err());
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn restore_all(&mut self) -> Result<(), Error> {
+        let mut res = Ok(());
+        while let Some(mut guard) = self.guards.pop() {
+            let guard_res = guard.restore_now();
+            if res.is_ok() {
+                res = guard_res;
+            }
+        }
+        res
+    }
+}
+
+impl TempGuard for TmpEnvSet {
+    fn keep(&mut self) {
+        self.keep_all();
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        self.restore_all()
+    }
+
+    fn is_active(&self) -> bool {
+        self.guards.iter().any(|guard| guard.is_active())
+    }
+}
+
+impl Debug for TmpEnvSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpEnvSet({} guards)", self.guards.len())
+    }
+}
+
+impl Drop for TmpEnvSet {
+    fn drop(&mut self) {
+        while let Some(guard) = self.guards.pop() {
+            drop(guard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, lock_current_dir, set_current_dir, set_var};
+
+    #[test]
+    fn test_tmp_env_set() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let dir_path = tmp_dir.to_path_buf();
+        {
+            let mut set = TmpEnvSet::new();
+            set.push(set_var("TEST_TMP_ENV_SET_ORDER", "first"))
+                .push(set_var("TEST_TMP_ENV_SET_ORDER", "second"));
+            // The current directory is restored before the directory is deleted
+            set.push(tmp_dir);
+            set.push(set_current_dir(&dir_path).expect("cannot set current dir"));
+            assert_eq!(set.len(), 4);
+            assert!(set.is_active());
+        }
+        assert!(std::env::var("TEST_TMP_ENV_SET_ORDER").is_err());
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        assert!(!dir_path.exists());
+
+        let mut set = TmpEnvSet::new();
+        set.push(set_var("TEST_TMP_ENV_SET_KEPT", "kept"));
+        set.keep_all();
+        assert!(!set.is_active());
+        drop(set);
+        assert_eq!(std::env::var("TEST_TMP_ENV_SET_KEPT").unwrap(), "kept");
+        std::env::remove_var("TEST_TMP_ENV_SET_KEPT");
+
+        // All the guards are restored even if one fails
+        let removed = create_temp_dir().expect("cannot create temp dir");
+        let other = create_temp_dir().expect("cannot create temp dir");
+        let _back = set_current_dir(&*removed).expect("cannot set current dir");
+        let mut set = TmpEnvSet::new();
+        set.push(set_var("TEST_TMP_ENV_SET_ERROR", "value"));
+        set.push(set_current_dir(&*other).expect("cannot set current dir"));
+        std::fs::remove_dir(&*removed).unwrap();
+        assert!(set.restore_all().is_err());
+        assert!(set.is_empty());
+        assert!(std::env::var("TEST_TMP_ENV_SET_ERROR").is_err());
+    }
+}
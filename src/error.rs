@@ -0,0 +1,185 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    path::PathBuf,
+};
+
+use crate::{report_cleanup_error, CleanupErrorEvent};
+
+/// The errors of this crate. The functions only doing IO still return a [`std::io::Error`], both
+/// convert into each other so `?` works in either direction.
+/// ```
+/// fn run() -> Result<(), tmp_env::Error> {
+///     let tmp_dir = tmp_env::create_temp_dir()?;
+///     let _tmp_env = tmp_env::try_set_var("TEST_TMP_ENV_ERROR", &*tmp_dir)?;
+///     Ok(())
+/// }
+/// run().expect("cannot run");
+/// assert!(matches!(
+///     tmp_env::try_set_var("TEST=TMP_ENV_ERROR", "value"),
+///     Err(tmp_env::Error::InvalidKey(_))
+/// ));
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The name of an environment variable is empty, or contains `=` or a NUL character
+    InvalidKey(OsString),
+    /// The value of an environment variable contains a NUL character
+    InvalidValue {
+        /// The name of the environment variable
+        key: OsString,
+        /// The rejected value
+        value: OsString,
+    },
+    /// An IO operation failed
+    Io(std::io::Error),
+    /// A temporary change couldn't be undone
+    Restore {
+        /// The path which couldn't be restored or deleted
+        path: PathBuf,
+        /// Why it couldn't be restored or deleted
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// The kind of the underlying IO error, [`InvalidInput`](std::io::ErrorKind::InvalidInput)
+    /// for the invalid environment variables
+    pub fn kind(&self) -> std::io::ErrorKind {
+        match self {
+            Error::InvalidKey(_) | Error::InvalidValue { .. } => std::io::ErrorKind::InvalidInput,
+            Error::Io(error) | Error::Restore { source: error, .. } => error.kind(),
+        }
+    }
+
+    pub(crate) fn restore<P: Into<PathBuf>>(path: P, source: std::io::Error) -> Self {
+        Error::Restore {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// The IO error which prevented the restoration, without the path
+    pub(crate) fn into_source(self) -> std::io::Error {
+        match self {
+            Error::Restore { source, .. } => source,
+            error => error.into(),
+        }
+    }
+
+    /// Report the error of a guard restored when dropped, see
+    /// [`on_cleanup_error`](crate::on_cleanup_error)
+    pub(crate) fn report(self) {
+        let event = match self {
+            Error::Restore { path, source } => CleanupErrorEvent {
+                path,
+                error: source,
+            },
+            error => CleanupErrorEvent {
+                path: PathBuf::new(),
+                error: error.into(),
+            },
+        };
+        report_cleanup_error(event);
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidKey(key) => write!(f, "invalid environment variable name {:?}", key),
+            Error::InvalidValue { key, value } => write!(
+                f,
+                "invalid value {:?} for the environment variable {:?}",
+                value, key
+            ),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Restore { path, source } => write!(f, "cannot restore {:?}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidKey(_) | Error::InvalidValue { .. } => None,
+            Error::Io(error) | Error::Restore { source: error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            error => std::io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+/// Check what [`std::env::set_var`] would panic on
+pub(crate) fn check_var(key: &OsStr, value: Option<&OsStr>) -> Result<(), Error> {
+    let key_bytes = key.as_encoded_bytes();
+    if key_bytes.is_empty() || key_bytes.contains(&b'=') || key_bytes.contains(&0) {
+        return Err(Error::InvalidKey(key.to_owned()));
+    }
+    match value {
+        Some(value) if value.as_encoded_bytes().contains(&0) => Err(Error::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{try_remove_var, try_set_var};
+
+    #[test]
+    fn test_error() {
+        for key in &["", "A=B", "A\0B"] {
+            let err = try_set_var(key, "value").unwrap_err();
+            assert!(matches!(&err, Error::InvalidKey(invalid) if invalid == key));
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(try_remove_var(key).is_err());
+        }
+        assert!(matches!(
+            try_set_var("TEST_TMP_ENV_ERROR_VALUE", "a\0b"),
+            Err(Error::InvalidValue { .. })
+        ));
+        assert!(std::env::var("TEST_TMP_ENV_ERROR_VALUE").is_err());
+        {
+            let _tmp_env = try_set_var("TEST_TMP_ENV_ERROR_VALUE", "value").unwrap();
+            assert_eq!(std::env::var("TEST_TMP_ENV_ERROR_VALUE").unwrap(), "value");
+        }
+        assert!(std::env::var("TEST_TMP_ENV_ERROR_VALUE").is_err());
+
+        let err = Error::restore(
+            "/missing",
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+        );
+        assert!(err.to_string().starts_with("cannot restore \"/missing\""));
+        assert!(std::error::Error::source(&err).is_some());
+        let io_err = std::io::Error::from(err);
+        assert_eq!(io_err.kind(), std::io::ErrorKind::NotFound);
+        assert!(matches!(
+            io_err
+                .into_inner()
+                .unwrap()
+                .downcast::<Error>()
+                .map(|err| *err),
+            Ok(Error::Restore { .. })
+        ));
+        let io_err = std::io::Error::from(Error::from(std::io::Error::other("io")));
+        assert_eq!(io_err.to_string(), "io");
+    }
+}
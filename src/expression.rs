@@ -0,0 +1,83 @@
+use duct::Expression;
+
+use crate::{command::Vars, EnvChanges, Error, Sandbox};
+
+/// Apply [`EnvChanges`] or a [`Sandbox`] to a `duct::Expression`, for its child processes only
+pub trait ExpressionExt {
+    /// A new expression with the changes applied to the environment of the current process,
+    /// given with `full_env` and `dir`. Like with duct, the variables and directory already set
+    /// on this expression take precedence.
+    /// ```no_run
+    /// use tmp_env::ExpressionExt;
+    ///
+    /// let changes = tmp_env::EnvChanges::new()
+    ///     .set_var("APP_MODE", "test")
+    ///     .prepend_path("PATH", "target/debug");
+    /// let output = duct::cmd!("app", "--version")
+    ///     .apply_tmp_env(&changes)
+    ///     .expect("cannot apply the changes")
+    ///     .read()
+    ///     .expect("cannot run the command");
+    /// ```
+    fn apply_tmp_env(&self, changes: &EnvChanges) -> Result<Expression, Error>;
+
+    /// A new expression running inside the sandbox, like the commands returned by
+    /// [`Sandbox::command`]
+    fn in_sandbox(&self, sandbox: &Sandbox) -> Result<Expression, Error>;
+}
+
+impl ExpressionExt for Expression {
+    fn apply_tmp_env(&self, changes: &EnvChanges) -> Result<Expression, Error> {
+        apply(self, changes, std::env::vars_os().collect())
+    }
+
+    fn in_sandbox(&self, sandbox: &Sandbox) -> Result<Expression, Error> {
+        apply(self, &sandbox.changes(), sandbox.inherited_vars())
+    }
+}
+
+fn apply(expression: &Expression, changes: &EnvChanges, vars: Vars) -> Result<Expression, Error> {
+    let (vars, current_dir) = changes.resolve(vars)?;
+    let expression = expression.full_env(vars);
+    Ok(match current_dir {
+        Some(current_dir) => expression.dir(current_dir),
+        None => expression,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duct_expression() {
+        std::env::set_var("TEST_DUCT_INHERITED", "inherited");
+        let changes = EnvChanges::new()
+            .set_var("TEST_DUCT_SET", "first")
+            .set_var("TEST_DUCT_SET", "value")
+            .remove_var("TEST_DUCT_INHERITED");
+        let output = duct::cmd!("sh", "-c", "echo $TEST_DUCT_SET-$TEST_DUCT_INHERITED")
+            .apply_tmp_env(&changes)
+            .unwrap()
+            .read()
+            .unwrap();
+        assert_eq!(output, "value-");
+        assert!(std::env::var("TEST_DUCT_SET").is_err());
+
+        let sandbox = Sandbox::new()
+            .expect("cannot create sandbox")
+            .clear_env(true);
+        let output = duct::cmd!("sh", "-c", "echo $HOME-$TEST_DUCT_INHERITED; pwd")
+            .in_sandbox(&sandbox)
+            .unwrap()
+            .read()
+            .unwrap();
+        std::env::remove_var("TEST_DUCT_INHERITED");
+        let expected = format!(
+            "{}-\n{}",
+            sandbox.home().display(),
+            sandbox.canonicalize().unwrap().display()
+        );
+        assert_eq!(output, expected);
+    }
+}
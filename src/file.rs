@@ -0,0 +1,474 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    counters::{count, is_measuring_removed_bytes, Counter},
+    dry_run, ignore_not_found, random_path,
+    registry::Registration,
+    ChangeKind, Error, Naming, Policy, TempGuard,
+};
+
+/// A helper datastructure for ensuring that we delete the tmp file created before
+/// end of the current scope.
+#[must_use = "the temporary file is deleted as soon as the guard is dropped"]
+pub struct TmpFile {
+    pub(crate) path: PathBuf,
+    // Only taken to close the file before deleting it
+    file: Option<File>,
+    registration: Registration,
+    policy: Policy,
+}
+
+impl Deref for TmpFile {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Create an empty temporary file in the temporary directory of your operating system
+/// ```
+/// {
+///     let tmp_file = tmp_env::create_temp_file().expect("cannot create temp file"); // When tmp_file is dropped this temporary file will be removed
+///     assert!(std::fs::metadata(&*tmp_file).unwrap().is_file());
+/// }
+/// // The temporary file is now removed
+/// ```
+pub fn create_temp_file() -> Result<TmpFile, std::io::Error> {
+    TmpFile::builder().create()
+}
+
+/// Create a temporary file in the temporary directory of your operating system containing the
+/// given content. The content is written in a staging file first and then renamed so the file
+/// never appears partially written.
+/// ```
+/// let tmp_file = tmp_env::create_temp_file_with_content("key = \"value\"").expect("cannot create temp file");
+/// assert_eq!(std::fs::read_to_string(&*tmp_file).unwrap(), "key = \"value\"");
+/// ```
+pub fn create_temp_file_with_content<C: AsRef<[u8]>>(
+    content: C,
+) -> Result<TmpFile, std::io::Error> {
+    TmpFile::builder().create_with_content(content)
+}
+
+impl TmpFile {
+    /// Create a builder to configure where and how the temporary file is created
+    pub fn builder() -> TmpFileBuilder {
+        TmpFileBuilder::default()
+    }
+
+    pub(crate) fn new(path: PathBuf, file: File) -> Self {
+        Self {
+            registration: Registration::new(ChangeKind::TmpFile, path.as_os_str()),
+            path,
+            file: Some(file),
+            policy: Policy::default(),
+        }
+    }
+
+    /// Choose how the failure to delete the file when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The file opened when the temporary file has been created, readable and writable by
+    /// default (see [`TmpFileBuilder::open_options`])
+    /// ```
+    /// use std::io::{Read, Seek, SeekFrom, Write};
+    ///
+    /// let mut tmp_file = tmp_env::create_temp_file().expect("cannot create temp file");
+    /// tmp_file.write_all(b"content").unwrap();
+    /// tmp_file.seek(SeekFrom::Start(0)).unwrap();
+    /// let mut content = String::new();
+    /// tmp_file.as_file().read_to_string(&mut content).unwrap();
+    /// assert_eq!(content, "content");
+    /// ```
+    pub fn as_file(&self) -> &File {
+        self.file
+            .as_ref()
+            .expect("the tmp file is open until deleted")
+    }
+
+    /// Keep the temporary file on disk instead of deleting it when dropped and return its path
+    /// ```
+    /// let tmp_file = tmp_env::create_temp_file().expect("cannot create temp file");
+    /// let path = tmp_file.keep();
+    /// assert!(path.exists());
+    /// # std::fs::remove_file(path).unwrap();
+    /// ```
+    pub fn keep(mut self) -> PathBuf {
+        // An empty path disarms the deletion when dropped
+        std::mem::take(&mut self.path)
+    }
+
+    /// Same as [`TmpFile::as_file`] but mutable
+    pub fn as_file_mut(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("the tmp file is open until deleted")
+    }
+}
+
+impl Read for TmpFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.as_file_mut().read(buf)
+    }
+}
+
+impl Write for TmpFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.as_file_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.as_file_mut().flush()
+    }
+}
+
+impl Seek for TmpFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.as_file_mut().seek(pos)
+    }
+}
+
+/// A builder to configure where and how a [`TmpFile`] is created
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let tmp_file = tmp_env::TmpFile::builder()
+///     .parent(&*tmp_dir)
+///     .stem("config")
+///     .extension("json")
+///     .create_with_content("{}")
+///     .expect("cannot create temp file");
+/// assert_eq!(*tmp_file, tmp_dir.join("config.json"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TmpFileBuilder {
+    parent: Option<PathBuf>,
+    prefix: OsString,
+    suffix: OsString,
+    naming: Naming,
+    stem: Option<OsString>,
+    pub(crate) extension: Option<OsString>,
+    open_options: OpenOptions,
+}
+
+impl Default for TmpFileBuilder {
+    fn default() -> Self {
+        let mut open_options = OpenOptions::new();
+        open_options.read(true).write(true);
+        Self {
+            parent: None,
+            prefix: OsString::new(),
+            suffix: OsString::new(),
+            naming: Naming::default(),
+            stem: None,
+            extension: None,
+            open_options,
+        }
+    }
+}
+
+impl TmpFileBuilder {
+    /// Create the temporary file inside this directory (which can be a [`TmpDir`](crate::TmpDir))
+    /// instead of the temporary directory of your operating system
+    pub fn parent<P: AsRef<Path>>(mut self, parent: P) -> Self {
+        self.parent = Some(parent.as_ref().to_path_buf());
+        self
+    }
+
+    /// Start the name of the temporary file with this prefix
+    pub fn prefix<P: AsRef<OsStr>>(mut self, prefix: P) -> Self {
+        self.prefix = prefix.as_ref().to_os_string();
+        self
+    }
+
+    /// End the name of the temporary file (before the extension) with this suffix
+    pub fn suffix<S: AsRef<OsStr>>(mut self, suffix: S) -> Self {
+        self.suffix = suffix.as_ref().to_os_string();
+        self
+    }
+
+    /// Number of random characters in the name of the temporary file (10 by default)
+    pub fn rand_len(mut self, rand_len: usize) -> Self {
+        self.naming = self.naming.rand_len(rand_len);
+        self
+    }
+
+    /// Generate the random part of the name of the temporary file with this strategy
+    pub fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Use this fixed stem instead of a random name, creating the file fails if it already exists
+    pub fn stem<S: AsRef<OsStr>>(mut self, stem: S) -> Self {
+        self.stem = Some(stem.as_ref().to_os_string());
+        self
+    }
+
+    /// Add this extension to the name of the temporary file
+    pub fn extension<E: AsRef<OsStr>>(mut self, extension: E) -> Self {
+        self.extension = Some(extension.as_ref().to_os_string());
+        self
+    }
+
+    /// Open the temporary file with these options instead of reading and writing, the file is
+    /// always created so `create_new` is forced
+    pub fn open_options(mut self, open_options: OpenOptions) -> Self {
+        self.open_options = open_options;
+        self
+    }
+
+    /// Create the empty temporary file
+    pub fn create(&self) -> Result<TmpFile, std::io::Error> {
+        let tmp_path = self.path();
+        let file = self.open_options.clone().create_new(true).open(&tmp_path)?;
+        count(Counter::FilesCreated, 1);
+        dry_run::record_created(&tmp_path, false);
+
+        Ok(TmpFile::new(tmp_path, file))
+    }
+
+    /// Create the temporary file containing the given content. The content is written in a
+    /// staging file first and then linked to its name so the file never appears partially
+    /// written, which fails if the name is already taken. On filesystems without hard links
+    /// (FAT, some network filesystems) the name is reserved with an empty file which the staging
+    /// file then replaces, so the file may appear empty before its content. The opened file is
+    /// rewound to the start of the content.
+    pub fn create_with_content<C: AsRef<[u8]>>(
+        &self,
+        content: C,
+    ) -> Result<TmpFile, std::io::Error> {
+        let tmp_path = self.path();
+        let staging_path =
+            tmp_path.with_file_name(random_path(".".as_ref(), ".staging".as_ref(), 10));
+        let res = (|| {
+            let mut file = self
+                .open_options
+                .clone()
+                .create_new(true)
+                .open(&staging_path)?;
+            file.write_all(content.as_ref())?;
+            file.sync_all()?;
+            // Unlike a rename, the link never replaces a file created meanwhile
+            match std::fs::hard_link(&staging_path, &tmp_path) {
+                Ok(()) => {
+                    if let Err(err) = std::fs::remove_file(&staging_path) {
+                        let _ = std::fs::remove_file(&tmp_path);
+                        return Err(err);
+                    }
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::Unsupported | std::io::ErrorKind::PermissionDenied
+                    ) =>
+                {
+                    rename_no_clobber(&staging_path, &tmp_path)?
+                }
+                Err(err) => return Err(err),
+            }
+            file.seek(SeekFrom::Start(0))?;
+            Ok(file)
+        })();
+        match res {
+            Ok(file) => {
+                count(Counter::FilesCreated, 1);
+                dry_run::record_created(&tmp_path, false);
+                Ok(TmpFile::new(tmp_path, file))
+            }
+            Err(err) => {
+                let _ = std::fs::remove_file(&staging_path);
+                Err(err)
+            }
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        let parent = match &self.parent {
+            Some(parent) => parent.clone(),
+            None => std::env::temp_dir(),
+        };
+        let mut name = match &self.stem {
+            Some(stem) => stem.clone(),
+            None => self
+                .naming
+                .name(&self.prefix, &self.suffix)
+                .into_os_string(),
+        };
+        if let Some(extension) = &self.extension {
+            name.push(".");
+            name.push(extension);
+        }
+
+        parent.join(name)
+    }
+}
+
+/// Rename `from` to `to` unless `to` already exists, by reserving the name with an empty file
+/// first
+fn rename_no_clobber(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    OpenOptions::new().write(true).create_new(true).open(to)?;
+    if let Err(err) = std::fs::rename(from, to) {
+        let _ = std::fs::remove_file(to);
+        return Err(err);
+    }
+    Ok(())
+}
+
+impl TempGuard for TmpFile {
+    fn keep(&mut self) {
+        // An empty path disarms the deletion when dropped
+        std::mem::take(&mut self.path);
+        self.registration.release();
+    }
+
+    /// Close and delete the temporary file, [`TmpFile::as_file`] panics afterwards
+    fn restore_now(&mut self) -> Result<(), Error> {
+        // Windows can't delete an opened file
+        drop(self.file.take());
+        if !self.is_active() {
+            return Ok(());
+        }
+        let size = if is_measuring_removed_bytes() {
+            std::fs::metadata(&self.path).map_or(0, |metadata| metadata.len())
+        } else {
+            0
+        };
+        let res = ignore_not_found(std::fs::remove_file(&self.path));
+        let path = std::mem::take(&mut self.path);
+        if res.is_ok() {
+            count(Counter::FilesDeleted, 1);
+            count(Counter::BytesRemoved, size);
+            self.registration.restored();
+        }
+        self.registration.release();
+        res.map_err(|err| Error::restore(path, err))
+    }
+
+    fn is_active(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.restore_now() {
+            self.policy.handle(error, Error::report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmp_file() {
+        #[allow(unused_assignments)]
+        let mut tmp_file_created: Option<PathBuf> = None;
+        {
+            let tmp_file = create_temp_file().expect("cannot create temp file");
+            tmp_file_created = Some(tmp_file.path.clone());
+            assert!(tmp_file.starts_with(std::env::temp_dir()));
+            assert_eq!(std::fs::metadata(&*tmp_file).unwrap().len(), 0);
+        }
+        assert!(std::fs::metadata(tmp_file_created.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_tmp_file_with_content() {
+        #[allow(unused_assignments)]
+        let mut tmp_file_created: Option<PathBuf> = None;
+        {
+            let tmp_file =
+                create_temp_file_with_content(b"\x00binary\xff").expect("cannot create temp file");
+            tmp_file_created = Some(tmp_file.path.clone());
+            assert_eq!(std::fs::read(&*tmp_file).unwrap(), b"\x00binary\xff");
+        }
+        assert!(std::fs::metadata(tmp_file_created.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_tmp_file_builder() {
+        let tmp_dir = crate::create_temp_dir().expect("cannot create temp dir");
+        let mut open_options = OpenOptions::new();
+        open_options.append(true);
+        let tmp_file = TmpFile::builder()
+            .parent(&*tmp_dir)
+            .prefix("pre")
+            .suffix("suf")
+            .extension("toml")
+            .open_options(open_options)
+            .create()
+            .expect("cannot create temp file");
+        assert_eq!(tmp_file.parent(), Some(tmp_dir.as_path()));
+        let name = tmp_file.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("pre") && name.ends_with("suf.toml"));
+
+        let builder = TmpFile::builder().parent(&*tmp_dir).stem("fixed");
+        let tmp_file = builder
+            .create_with_content("content")
+            .expect("cannot create temp file");
+        assert_eq!(*tmp_file, tmp_dir.join("fixed"));
+        assert_eq!(
+            builder.create_with_content("other").unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            builder.create().unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(std::fs::read_to_string(&*tmp_file).unwrap(), "content");
+        drop(tmp_file);
+        assert_eq!(std::fs::read_dir(&*tmp_dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_rename_no_clobber() {
+        let tmp_dir = crate::create_temp_dir().expect("cannot create temp dir");
+        let (staging, target) = (tmp_dir.join("staging"), tmp_dir.join("target"));
+        std::fs::write(&staging, "new").unwrap();
+        rename_no_clobber(&staging, &target).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+        assert!(!staging.exists());
+
+        std::fs::write(&staging, "other").unwrap();
+        assert_eq!(
+            rename_no_clobber(&staging, &target).unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_tmp_file_handle() {
+        let mut tmp_file = create_temp_file_with_content("hello").expect("cannot create temp file");
+        let mut content = String::new();
+        tmp_file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "hello");
+        tmp_file.write_all(b" world").unwrap();
+        tmp_file.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&*tmp_file).unwrap(), "hello world");
+        tmp_file.seek(SeekFrom::Start(6)).unwrap();
+        let mut content = String::new();
+        tmp_file.as_file_mut().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "world");
+        assert_eq!(tmp_file.as_file().metadata().unwrap().len(), 11);
+    }
+}
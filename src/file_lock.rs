@@ -0,0 +1,197 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::TmpDir;
+
+/// Name of the lock file used by [`TmpDir::lock`]
+const LOCK_FILE: &str = ".tmp_env-lock";
+
+/// A helper datastructure for ensuring that we release the advisory exclusive lock taken on a
+/// file before end of the current scope. The lock file itself is not removed.
+#[must_use = "the lock is released as soon as it is dropped"]
+pub struct FileLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl Deref for FileLock {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for FileLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Take an advisory exclusive lock on the file at `path` (created if it doesn't exist), waiting
+/// until no other process or thread holds it. It returns a datastructure to keep the lock, when
+/// dropped the lock is released. It uses `flock` on Unix and `LockFileEx` on Windows.
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// {
+///     let _lock = tmp_env::lock_file(tmp_dir.join("fixtures.lock")).expect("cannot lock file");
+///     // Other processes locking "fixtures.lock" wait here
+/// }
+/// // The lock is now released
+/// ```
+pub fn lock_file<P: AsRef<Path>>(path: P) -> Result<FileLock, std::io::Error> {
+    let path = path.as_ref().to_path_buf();
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    lock(&file)?;
+
+    Ok(FileLock { path, file })
+}
+
+impl TmpDir {
+    /// Take an advisory exclusive lock on a lock file inside the temporary directory, see
+    /// [`lock_file`]
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// let _lock = tmp_dir.lock().expect("cannot lock the tmp dir");
+    /// ```
+    pub fn lock(&self) -> Result<FileLock, std::io::Error> {
+        lock_file(self.path.join(LOCK_FILE))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway
+        let _ = unlock(&self.file);
+    }
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        // SAFETY: the file descriptor stays open for the duration of the call
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the file descriptor stays open for the duration of the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn lock(file: &File) -> Result<(), std::io::Error> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::{
+        Storage::FileSystem::{LockFileEx, LOCKFILE_EXCLUSIVE_LOCK},
+        System::IO::OVERLAPPED,
+    };
+
+    // SAFETY: the handle stays open for the duration of the call and OVERLAPPED is plain data
+    let res = unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        LockFileEx(
+            file.as_raw_handle(),
+            LOCKFILE_EXCLUSIVE_LOCK,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if res != 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn unlock(file: &File) -> Result<(), std::io::Error> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::{Storage::FileSystem::UnlockFileEx, System::IO::OVERLAPPED};
+
+    // SAFETY: the handle stays open for the duration of the call and OVERLAPPED is plain data
+    let res = unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        UnlockFileEx(file.as_raw_handle(), 0, u32::MAX, u32::MAX, &mut overlapped)
+    };
+    if res != 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock(_file: &File) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file locks are not supported on this platform",
+    ))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn unlock(_file: &File) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_temp_dir;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[test]
+    fn test_lock_file() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let lock = tmp_dir.lock().expect("cannot lock the tmp dir");
+        assert_eq!(*lock, tmp_dir.join(LOCK_FILE));
+
+        let acquired = Arc::new(AtomicBool::new(false));
+        let handle = std::thread::spawn({
+            let path = lock.to_path_buf();
+            let acquired = Arc::clone(&acquired);
+            move || {
+                let _lock = lock_file(path).expect("cannot lock file");
+                acquired.store(true, Ordering::SeqCst);
+            }
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!acquired.load(Ordering::SeqCst));
+        drop(lock);
+        handle.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+        assert!(tmp_dir.join(LOCK_FILE).is_file());
+    }
+}
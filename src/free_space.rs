@@ -0,0 +1,84 @@
+use std::path::Path;
+
+/// Space in bytes available to the current user on the filesystem containing `path`
+#[cfg(unix)]
+pub(crate) fn free_space(path: &Path) -> Result<u64, std::io::Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: statvfs is a plain C struct for which all zeroes is a valid value
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is NUL terminated and the struct is writable
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(widen(stat.f_bavail) * widen(stat.f_frsize))
+}
+
+/// Lossless conversion of the statvfs fields, whose types differ between the platforms (`u32`
+/// blocks on macOS, `u32` block sizes on 32 bits Linux, `u64` otherwise)
+#[cfg(unix)]
+fn widen<T: Into<u64>>(value: T) -> u64 {
+    value.into()
+}
+
+/// Space in bytes available to the current user on the volume containing `path`
+#[cfg(windows)]
+pub(crate) fn free_space(path: &Path) -> Result<u64, std::io::Error> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0;
+    // SAFETY: the path is NUL terminated and the optional outputs may be null
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(available)
+}
+
+/// Fail with an error of kind [`StorageFull`](std::io::ErrorKind::StorageFull) if less than
+/// `min_free_bytes` are available on the filesystem containing `path`
+pub(crate) fn check_free_space(path: &Path, min_free_bytes: u64) -> Result<(), std::io::Error> {
+    let available = free_space(path)?;
+    if available < min_free_bytes {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::StorageFull,
+            format!(
+                "only {} bytes are free on the filesystem of {:?}, {} bytes are needed",
+                available, path, min_free_bytes
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_space() {
+        let tmp_dir = crate::create_temp_dir().expect("cannot create temp dir");
+        let available = free_space(&tmp_dir).expect("cannot get free space");
+        assert!(available > 0);
+        assert!(check_free_space(&tmp_dir, 1).is_ok());
+        assert_eq!(
+            check_free_space(&tmp_dir, u64::MAX).unwrap_err().kind(),
+            std::io::ErrorKind::StorageFull
+        );
+        assert!(free_space(&tmp_dir.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+
+use crate::{remove_tree, report_cleanup_error, CleanupErrorEvent};
+
+/// Name of the marker file containing the PID of the owner of a registered temporary directory
+pub(crate) const OWNER_MARKER: &str = ".tmp_env-owner";
+
+pub(crate) fn write_owner_marker(tmp_dir: &Path) -> Result<(), std::io::Error> {
+    std::fs::write(tmp_dir.join(OWNER_MARKER), std::process::id().to_string())
+}
+
+/// Remove the temporary directories created with [`TmpDirBuilder::register`](crate::TmpDirBuilder::register)
+/// in the temporary directory of your operating system whose owning process is gone (for example
+/// because it has been killed before dropping them). It returns the removed directories.
+/// ```
+/// let tmp_dir = tmp_env::TmpDir::builder().register(true).create().expect("cannot create temp dir");
+/// let removed = tmp_env::gc_orphans().expect("cannot collect orphans");
+/// // The current process is still alive
+/// assert!(!removed.contains(&*tmp_dir));
+/// ```
+pub fn gc_orphans() -> Result<Vec<PathBuf>, std::io::Error> {
+    gc_orphans_in(std::env::temp_dir())
+}
+
+/// Same as [`gc_orphans`] but for the temporary directories registered inside `parent`
+pub fn gc_orphans_in<P: AsRef<Path>>(parent: P) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut removed = Vec::new();
+    for entry in std::fs::read_dir(parent)? {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        let pid = match std::fs::read_to_string(path.join(OWNER_MARKER)) {
+            Ok(pid) => match pid.trim().parse::<u32>() {
+                Ok(pid) => pid,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        if pid == std::process::id() || process_is_alive(pid) {
+            continue;
+        }
+        match remove_tree(&path) {
+            Ok(()) => removed.push(path),
+            Err(error) => report_cleanup_error(CleanupErrorEvent { path, error }),
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(unix)]
+pub(crate) fn process_is_alive(pid: u32) -> bool {
+    use std::convert::TryFrom;
+
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) => pid,
+        Err(_) => return false,
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+pub(crate) fn process_is_alive(pid: u32) -> bool {
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE},
+        System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+    };
+
+    // SAFETY: the handle is checked before being used and closed once done
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut exit_code = 0;
+        let alive =
+            GetExitCodeProcess(handle, &mut exit_code) != 0 && exit_code == STILL_ACTIVE as u32;
+        CloseHandle(handle);
+        alive
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn process_is_alive(_pid: u32) -> bool {
+    // Without any way to know, never consider a directory as orphaned
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, TmpDir};
+
+    #[test]
+    fn test_gc_orphans() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        let alive = TmpDir::builder()
+            .parent(&*parent)
+            .register(true)
+            .create()
+            .expect("cannot create temp dir");
+        assert_eq!(
+            std::fs::read_to_string(alive.join(OWNER_MARKER)).unwrap(),
+            std::process::id().to_string()
+        );
+        let unregistered = TmpDir::builder()
+            .parent(&*parent)
+            .create()
+            .expect("cannot create temp dir");
+        let orphan = TmpDir::builder()
+            .parent(&*parent)
+            .register(true)
+            .create()
+            .expect("cannot create temp dir");
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .expect("cannot spawn child");
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        assert!(!process_is_alive(dead_pid));
+        std::fs::write(orphan.join(OWNER_MARKER), dead_pid.to_string()).unwrap();
+
+        let removed = gc_orphans_in(&*parent).expect("cannot collect orphans");
+        assert_eq!(removed, vec![orphan.to_path_buf()]);
+        assert!(alive.exists());
+        assert!(unregistered.exists());
+        // Already removed by the garbage collection
+        orphan.keep();
+    }
+}
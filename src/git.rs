@@ -0,0 +1,152 @@
+use std::{
+    ffi::OsStr,
+    fmt::Debug,
+    ops::Deref,
+    path::Path,
+    process::{Command, Output},
+};
+
+use crate::{create_temp_dir, TmpDir};
+
+/// Author, committer and date of the commits made by [`TmpGitRepo::commit`], so the commit
+/// hashes are the same in every run
+const AUTHOR_NAME: &str = "tmp_env";
+const AUTHOR_EMAIL: &str = "tmp_env@example.com";
+const DATE: &str = "2000-01-01T00:00:00+0000";
+
+/// A helper datastructure for ensuring that we delete the temporary git repository created before
+/// end of the current scope.
+#[must_use = "the temporary repository is deleted as soon as the guard is dropped"]
+pub struct TmpGitRepo {
+    dir: TmpDir,
+}
+
+impl Deref for TmpGitRepo {
+    type Target = TmpDir;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dir
+    }
+}
+
+impl Debug for TmpGitRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.dir.path)
+    }
+}
+
+/// Create a temporary directory and run `git init` in it, on a `main` branch. The `git` binary
+/// must be in the `PATH`. It returns a datastructure to keep the repository, when dropped the
+/// repository is removed.
+/// ```
+/// let repo = tmp_env::create_temp_git_repo().expect("cannot create git repo");
+/// repo.commit(&[("README.md", "# Hello")], "Initial commit").expect("cannot commit");
+/// let log = repo.git(["log", "--format=%an %s"]).expect("cannot run git");
+/// assert_eq!(String::from_utf8_lossy(&log.stdout).trim(), "tmp_env Initial commit");
+/// ```
+pub fn create_temp_git_repo() -> Result<TmpGitRepo, std::io::Error> {
+    let repo = TmpGitRepo {
+        dir: create_temp_dir()?,
+    };
+    repo.git(["-c", "init.defaultBranch=main", "init", "--quiet"])?;
+
+    Ok(repo)
+}
+
+impl TmpGitRepo {
+    /// Run `git` with these arguments inside the repository, ignoring the user and system git
+    /// configurations. It fails if git exits with an error.
+    pub fn git<I, S>(&self, args: I) -> Result<Output, std::io::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<_> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_os_string())
+            .collect();
+        let output = Command::new("git")
+            .args(&args)
+            .current_dir(&self.dir.path)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", null_path())
+            .env("GIT_AUTHOR_NAME", AUTHOR_NAME)
+            .env("GIT_AUTHOR_EMAIL", AUTHOR_EMAIL)
+            .env("GIT_AUTHOR_DATE", DATE)
+            .env("GIT_COMMITTER_NAME", AUTHOR_NAME)
+            .env("GIT_COMMITTER_EMAIL", AUTHOR_EMAIL)
+            .env("GIT_COMMITTER_DATE", DATE)
+            .output()?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "git {:?} failed with {}: {}",
+                args,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(output)
+    }
+
+    /// Write the files at the given paths relative to the repository, stage them and commit them
+    /// with a fixed author and date so the commit hashes are reproducible
+    pub fn commit<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        files: &[(P, C)],
+        message: &str,
+    ) -> Result<(), std::io::Error> {
+        for (path, content) in files {
+            let path = self.dir.write(path, content)?;
+            self.git([OsStr::new("add"), OsStr::new("--"), path.as_os_str()])?;
+        }
+        self.git([
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--no-gpg-sign",
+            "-m",
+            message,
+        ])?;
+
+        Ok(())
+    }
+}
+
+fn null_path() -> &'static str {
+    if cfg!(windows) {
+        "NUL"
+    } else {
+        "/dev/null"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_git_repo() {
+        if Command::new("git").arg("--version").output().is_err() {
+            // git is not installed
+            return;
+        }
+        let head = || {
+            let repo = create_temp_git_repo().expect("cannot create git repo");
+            assert!(repo.join(".git").is_dir());
+            repo.commit(&[("a.txt", "a"), ("sub/b.txt", "b")], "first")
+                .expect("cannot commit");
+            repo.commit(&[("a.txt", "changed")], "second")
+                .expect("cannot commit");
+            let branch = repo.git(["branch", "--show-current"]).unwrap();
+            assert_eq!(String::from_utf8_lossy(&branch.stdout).trim(), "main");
+            let head = repo.git(["rev-parse", "HEAD"]).unwrap();
+            String::from_utf8(head.stdout).unwrap()
+        };
+        // Fixed author and date
+        assert_eq!(head(), head());
+
+        let repo = create_temp_git_repo().expect("cannot create git repo");
+        assert!(repo.git(["checkout", "does-not-exist"]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{CurrentDir, CurrentEnv, Error, TmpDir};
+
+/// The operations shared by all the guards of this crate, so generic code (for example a test
+/// harness) can manage guards of different kinds together
+/// ```
+/// use tmp_env::TempGuard;
+///
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let mut guards: Vec<Box<dyn TempGuard>> = vec![
+///     Box::new(tmp_env::set_var("TEST_TEMP_GUARD", "value")),
+///     Box::new(tmp_env::create_temp_file().expect("cannot create temp file")),
+/// ];
+/// assert!(guards.iter().all(|guard| guard.is_active()));
+/// for guard in &mut guards {
+///     guard.restore_now().expect("cannot restore");
+/// }
+/// assert!(std::env::var("TEST_TEMP_GUARD").is_err());
+/// assert!(guards.iter().all(|guard| !guard.is_active()));
+/// ```
+pub trait TempGuard {
+    /// Keep the change made by the guard, nothing is undone when it is dropped
+    fn keep(&mut self);
+
+    /// Undo the change made by the guard right now instead of waiting for it to be dropped and
+    /// return the error if it fails, nothing is undone again when it is dropped. The failures are
+    /// returned as [`Error::Restore`].
+    fn restore_now(&mut self) -> Result<(), Error>;
+
+    /// Returns `true` while the guard still has to undo its change when dropped
+    fn is_active(&self) -> bool;
+}
+
+impl TempGuard for CurrentEnv {
+    fn keep(&mut self) {
+        self.disarm();
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        self.restore();
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        !self.restored
+    }
+}
+
+impl TempGuard for CurrentDir {
+    fn keep(&mut self) {
+        self.restored = true;
+        self.created = None;
+        self.registration.release();
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        let res = self.restore();
+        // The caller is in charge of the error, don't try again when dropped
+        self.restored = true;
+        res.map_err(|err| Error::restore(&self.original, err))?;
+        let created = self.created.clone();
+        self.remove_created()
+            .map_err(|err| Error::restore(created.unwrap_or_default(), err))
+    }
+
+    fn is_active(&self) -> bool {
+        !self.restored || (self.remove_created && self.created.is_some())
+    }
+}
+
+impl TempGuard for TmpDir {
+    fn keep(&mut self) {
+        // An empty path disarms the deletion when dropped
+        std::mem::take(&mut self.path);
+        self.set_cleanup_on_exit(false);
+        self.registration.release();
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let res = self.delete();
+        let path = std::mem::take(&mut self.path);
+        if res.is_ok() {
+            self.registration.restored();
+        }
+        self.registration.release();
+        res.map_err(|err| Error::restore(path, err))
+    }
+
+    fn is_active(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, lock_current_dir, set_current_dir_all, set_var};
+
+    #[test]
+    fn test_temp_guard() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let dir_path = tmp_dir.to_path_buf();
+        let kept = create_temp_dir().expect("cannot create temp dir");
+        let kept_path = kept.to_path_buf();
+        let mut guards: Vec<Box<dyn TempGuard>> = vec![
+            Box::new(set_var("TEST_TEMP_GUARD_TRAIT", "value")),
+            Box::new(
+                set_current_dir_all(tmp_dir.join("a/b"))
+                    .expect("cannot set current dir")
+                    .remove_created_dirs(true),
+            ),
+            Box::new(tmp_dir),
+            Box::new(kept),
+        ];
+        assert!(guards.iter().all(|guard| guard.is_active()));
+
+        guards[3].keep();
+        assert!(!guards[3].is_active());
+        for guard in &mut guards {
+            guard.restore_now().expect("cannot restore");
+            assert!(!guard.is_active());
+        }
+        assert!(std::env::var("TEST_TEMP_GUARD_TRAIT").is_err());
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        assert!(!dir_path.exists());
+        // Restoring twice or dropping after restoring does nothing
+        guards[0].restore_now().unwrap();
+        std::env::set_var("TEST_TEMP_GUARD_TRAIT", "set again");
+        drop(guards);
+        assert_eq!(std::env::var("TEST_TEMP_GUARD_TRAIT").unwrap(), "set again");
+        std::env::remove_var("TEST_TEMP_GUARD_TRAIT");
+        assert!(kept_path.exists());
+        std::fs::remove_dir(kept_path).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{TmpDir, TmpFile};
+
+/// Kind of a temporary resource handed over with a [`CleanupToken`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// A temporary directory, see [`TmpDir`]
+    Dir,
+    /// A temporary file, see [`TmpFile`]
+    File,
+}
+
+/// A serializable list of temporary resources handed over from one process to another one in
+/// charge of deleting them. For example a child process creates temporary directories and saves
+/// the token in a file (whose path the parent gave it through an environment variable), then the
+/// parent loads and redeems the token to adopt the directories.
+/// ```
+/// let token_file = tmp_env::create_temp_file().expect("cannot create temp file");
+/// // In the child process
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let path = tmp_dir.to_path_buf();
+/// let mut token = tmp_env::CleanupToken::new();
+/// token.handoff_dir(tmp_dir);
+/// token.save(&*token_file).expect("cannot save token");
+/// // In the parent process
+/// let adopted = tmp_env::CleanupToken::load(&*token_file)
+///     .expect("cannot load token")
+///     .redeem()
+///     .expect("cannot redeem token");
+/// assert!(path.exists());
+/// drop(adopted);
+/// // The temporary directory is now removed
+/// assert!(!path.exists());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanupToken {
+    resources: Vec<(ResourceKind, PathBuf)>,
+}
+
+/// The temporary resources adopted by redeeming a [`CleanupToken`], deleted when dropped
+#[derive(Debug)]
+pub struct AdoptedResources {
+    /// The adopted temporary directories
+    pub dirs: Vec<TmpDir>,
+    /// The adopted temporary files
+    pub files: Vec<TmpFile>,
+}
+
+impl CleanupToken {
+    /// Create an empty token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hand over the temporary directory, it won't be deleted when this process drops it
+    pub fn handoff_dir(&mut self, tmp_dir: TmpDir) {
+        self.resources.push((ResourceKind::Dir, tmp_dir.keep()));
+    }
+
+    /// Hand over the temporary file, it won't be deleted when this process drops it
+    pub fn handoff_file(&mut self, tmp_file: TmpFile) {
+        self.resources.push((ResourceKind::File, tmp_file.keep()));
+    }
+
+    /// The resources handed over with this token
+    pub fn resources(&self) -> &[(ResourceKind, PathBuf)] {
+        &self.resources
+    }
+
+    /// Write the token in the given file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Read a token written with [`CleanupToken::save`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Adopt the resources of the token so they are deleted once the returned datastructure is
+    /// dropped
+    pub fn redeem(self) -> Result<AdoptedResources, std::io::Error> {
+        let mut adopted = AdoptedResources {
+            dirs: Vec::new(),
+            files: Vec::new(),
+        };
+        for (kind, path) in self.resources {
+            match kind {
+                ResourceKind::Dir => adopted.dirs.push(TmpDir::new(path)),
+                ResourceKind::File => {
+                    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+                    adopted.files.push(TmpFile::new(path, file));
+                }
+            }
+        }
+
+        Ok(adopted)
+    }
+}
+
+/// One resource per line, its kind and its path separated by a tab. The printable ASCII
+/// characters of the path are kept as is and the others are escaped with `%`, so paths which are
+/// not valid UTF-8 or contain tabs and newlines are read back unchanged.
+impl Display for CleanupToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (kind, path) in &self.resources {
+            let kind = match kind {
+                ResourceKind::Dir => "dir",
+                ResourceKind::File => "file",
+            };
+            writeln!(f, "{}\t{}", kind, encode_path(path))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CleanupToken {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |line: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid cleanup token line {:?}", line),
+            )
+        };
+        let mut token = CleanupToken::new();
+        for line in s.lines().filter(|line| !line.is_empty()) {
+            let (kind, path) = line.split_once('\t').ok_or_else(|| invalid(line))?;
+            let kind = match kind {
+                "dir" => ResourceKind::Dir,
+                "file" => ResourceKind::File,
+                _ => return Err(invalid(line)),
+            };
+            let path = decode_path(path).ok_or_else(|| invalid(line))?;
+            token.resources.push((kind, path));
+        }
+
+        Ok(token)
+    }
+}
+
+fn is_kept(unit: u32) -> bool {
+    (0x20..0x7f).contains(&unit) && unit != u32::from(b'%')
+}
+
+/// Escape the bytes of the path which are not printable ASCII as `%XX`
+#[cfg(unix)]
+fn encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|&byte| match is_kept(u32::from(byte)) {
+            true => char::from(byte).to_string(),
+            false => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn decode_path(encoded: &str) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte if is_kept(u32::from(byte)) => bytes.push(byte),
+            _ => return None,
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+/// Escape the UTF-16 units of the path which are not printable ASCII as `%uXXXX`
+#[cfg(windows)]
+fn encode_path(path: &Path) -> String {
+    use std::os::windows::ffi::OsStrExt;
+
+    path.as_os_str()
+        .encode_wide()
+        .map(|unit| match is_kept(u32::from(unit)) {
+            true => char::from(unit as u8).to_string(),
+            false => format!("%u{:04X}", unit),
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+fn decode_path(encoded: &str) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut units = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                if chars.next()? != 'u' {
+                    return None;
+                }
+                let hex: String = chars.by_ref().take(4).collect();
+                if hex.len() != 4 {
+                    return None;
+                }
+                units.push(u16::from_str_radix(&hex, 16).ok()?);
+            }
+            c if is_kept(u32::from(c)) => units.push(c as u16),
+            _ => return None,
+        }
+    }
+    Some(PathBuf::from(OsString::from_wide(&units)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, create_temp_file};
+
+    #[test]
+    fn test_cleanup_token() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let tmp_file = create_temp_file().expect("cannot create temp file");
+        let (dir_path, file_path) = (tmp_dir.to_path_buf(), tmp_file.to_path_buf());
+        let mut token = CleanupToken::new();
+        token.handoff_dir(tmp_dir);
+        token.handoff_file(tmp_file);
+        assert!(dir_path.exists() && file_path.exists());
+        assert_eq!(
+            token.resources(),
+            &[
+                (ResourceKind::Dir, dir_path.clone()),
+                (ResourceKind::File, file_path.clone())
+            ]
+        );
+
+        let parsed: CleanupToken = token.to_string().parse().expect("cannot parse token");
+        assert_eq!(parsed, token);
+        assert!("unknown\t/tmp".parse::<CleanupToken>().is_err());
+        assert!("dir".parse::<CleanupToken>().is_err());
+        assert!("dir\t/tmp/%".parse::<CleanupToken>().is_err());
+
+        let adopted = parsed.redeem().expect("cannot redeem token");
+        assert_eq!(*adopted.dirs[0], dir_path);
+        assert_eq!(*adopted.files[0], file_path);
+        drop(adopted);
+        assert!(!dir_path.exists() && !file_path.exists());
+    }
+
+    #[test]
+    fn test_cleanup_token_escaped_paths() {
+        #[cfg(unix)]
+        let odd = {
+            use std::os::unix::ffi::OsStringExt;
+            OsString::from_vec(b"/tmp/50%\tnew\nline\xff\xfe".to_vec())
+        };
+        #[cfg(windows)]
+        let odd = {
+            use std::os::windows::ffi::OsStringExt;
+            let mut units: Vec<u16> = "C:\\50%\tnew\nline\u{e9}".encode_utf16().collect();
+            units.push(0xd800);
+            OsString::from_wide(&units)
+        };
+        let token = CleanupToken {
+            resources: vec![
+                (ResourceKind::Dir, PathBuf::from(odd)),
+                (ResourceKind::File, PathBuf::from("/tmp/plain dir/file.txt")),
+            ],
+        };
+        let text = token.to_string();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.contains("\t/tmp/plain dir/file.txt\n"));
+        let parsed: CleanupToken = text.parse().expect("cannot parse token");
+        assert_eq!(parsed, token);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Debug,
+};
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we restore the previous hostname of the machine
+/// before end of the current scope.
+#[must_use = "the previous hostname is restored as soon as the guard is dropped"]
+pub struct TmpHostname {
+    // `None` once restored or kept
+    previous: Option<OsString>,
+    policy: Policy,
+}
+
+/// The hostname of the machine
+/// ```
+/// let hostname = tmp_env::hostname().expect("cannot get the hostname");
+/// assert!(!hostname.is_empty());
+/// ```
+pub fn hostname() -> Result<OsString, std::io::Error> {
+    sys::get()
+}
+
+/// Change the hostname of the machine and return a datastructure restoring the previous one when
+/// dropped, to test the code depending on it inside a container or a CI job where it is allowed.
+/// It uses `sethostname` on Unix, which needs `CAP_SYS_ADMIN` on Linux and changes the hostname
+/// of the whole UTS namespace, and `SetComputerNameExW` on Windows, which needs to be run as an
+/// administrator and only takes effect after a restart.
+/// ```no_run
+/// {
+///     let _hostname = tmp_env::set_hostname("node-1").expect("cannot set the hostname");
+///     assert_eq!(tmp_env::hostname().unwrap(), "node-1");
+/// }
+/// // Because guard is dropped the previous hostname is restored
+/// ```
+pub fn set_hostname<S: AsRef<OsStr>>(name: S) -> Result<TmpHostname, std::io::Error> {
+    let previous = sys::get()?;
+    sys::set(name.as_ref())?;
+    Ok(TmpHostname {
+        previous: Some(previous),
+        policy: Policy::default(),
+    })
+}
+
+impl TmpHostname {
+    /// The hostname before it was changed, or `None` once it is restored or kept
+    pub fn previous(&self) -> Option<&OsStr> {
+        self.previous.as_deref()
+    }
+
+    /// Choose how the failure to restore the previous hostname when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpHostname {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => Ok(sys::set(&previous)?),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpHostname {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.previous {
+            Some(previous) => write!(f, "TmpHostname({:?})", previous),
+            None => write!(f, "TmpHostname"),
+        }
+    }
+}
+
+impl Drop for TmpHostname {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!("cannot restore the hostname: {}", err))
+            });
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::{
+        ffi::{OsStr, OsString},
+        os::unix::ffi::{OsStrExt, OsStringExt},
+    };
+
+    pub(super) fn get() -> Result<OsString, std::io::Error> {
+        // Longer than the maximum of the platforms, and than the limit of POSIX
+        let mut buffer = [0u8; 256];
+        // SAFETY: the buffer is writable for the length given
+        if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+        Ok(OsString::from_vec(buffer[..len].to_vec()))
+    }
+
+    pub(super) fn set(name: &OsStr) -> Result<(), std::io::Error> {
+        let name = name.as_bytes();
+        // SAFETY: the name is readable for the length given
+        if unsafe { libc::sethostname(name.as_ptr().cast(), name.len() as _) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        ffi::{OsStr, OsString},
+        os::windows::ffi::{OsStrExt, OsStringExt},
+    };
+    use windows_sys::Win32::System::SystemInformation::{
+        ComputerNamePhysicalDnsHostname, GetComputerNameExW, SetComputerNameExW,
+    };
+
+    pub(super) fn get() -> Result<OsString, std::io::Error> {
+        let mut len = 0;
+        // Fails with the needed length, including the NUL
+        // SAFETY: a null buffer with a zero length only queries the needed length
+        unsafe {
+            GetComputerNameExW(
+                ComputerNamePhysicalDnsHostname,
+                std::ptr::null_mut(),
+                &mut len,
+            )
+        };
+        let mut buffer = vec![0u16; len as usize];
+        // SAFETY: the buffer is writable for the length given
+        if unsafe {
+            GetComputerNameExW(
+                ComputerNamePhysicalDnsHostname,
+                buffer.as_mut_ptr(),
+                &mut len,
+            )
+        } == 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        buffer.truncate(len as usize);
+        Ok(OsString::from_wide(&buffer))
+    }
+
+    pub(super) fn set(name: &OsStr) -> Result<(), std::io::Error> {
+        let name = name.encode_wide().chain(Some(0)).collect::<Vec<_>>();
+        // SAFETY: the name is NUL terminated
+        if unsafe { SetComputerNameExW(ComputerNamePhysicalDnsHostname, name.as_ptr()) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{run_in_child, EnvChanges};
+
+    #[test]
+    fn test_hostname() {
+        // In a UTS namespace of its own so the machine keeps its hostname
+        let status = run_in_child(&EnvChanges::new(), || {
+            let original = hostname().unwrap();
+            // SAFETY: unsharing the UTS namespace has no memory requirements
+            if unsafe { libc::unshare(libc::CLONE_NEWUTS) } != 0 {
+                let err = set_hostname("tmp-env-test").unwrap_err();
+                assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+                return;
+            }
+            {
+                let guard = set_hostname("tmp-env-test").unwrap();
+                assert_eq!(guard.previous(), Some(original.as_os_str()));
+                assert_eq!(hostname().unwrap(), "tmp-env-test");
+            }
+            assert_eq!(hostname().unwrap(), original);
+        })
+        .unwrap();
+        assert!(status.success());
+    }
+}
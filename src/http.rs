@@ -0,0 +1,240 @@
+use std::{
+    fmt::Debug,
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{content::resolve, TmpDir};
+
+/// A helper datastructure for ensuring that we stop the HTTP server serving the temporary
+/// directory and delete the directory before end of the current scope.
+#[must_use = "the server stops as soon as it is dropped"]
+pub struct TmpHttpServer {
+    dir: TmpDir,
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TmpHttpServer {
+    /// Address the server listens on, on the loopback interface
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base URL of the server, without trailing slash, e.g. `http://127.0.0.1:41234`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// URL of the file at the given path relative to the temporary directory
+    pub fn url_of(&self, path: &str) -> String {
+        format!("{}/{}", self.url(), path.trim_start_matches('/'))
+    }
+}
+
+impl Deref for TmpHttpServer {
+    type Target = TmpDir;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dir
+    }
+}
+
+impl Debug for TmpHttpServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.dir.path)
+    }
+}
+
+impl Drop for TmpHttpServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake up the blocking accept so the server notices it must stop, without it the server
+        // only stops with the next connection and isn't waited for
+        let woken = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1)).is_ok();
+        if let Some(thread) = self.thread.take().filter(|_| woken) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl TmpDir {
+    /// Serve the files of the temporary directory over HTTP on an ephemeral port of the loopback
+    /// interface. Only `GET` and `HEAD` requests are supported, directories are not listed and the
+    /// symbolic links leading out of the directory are not followed. When the returned
+    /// datastructure is dropped the server is stopped and the directory is removed.
+    /// ```
+    /// use std::io::{Read, Write};
+    ///
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("pkg/index.json", "{}").expect("cannot write file");
+    /// let server = tmp_dir.serve_http().expect("cannot start server");
+    /// assert_eq!(server.url_of("pkg/index.json"), format!("{}/pkg/index.json", server.url()));
+    ///
+    /// let mut stream = std::net::TcpStream::connect(server.addr()).unwrap();
+    /// stream.write_all(b"GET /pkg/index.json HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    /// let mut response = String::new();
+    /// stream.read_to_string(&mut response).unwrap();
+    /// assert!(response.starts_with("HTTP/1.1 200 OK"));
+    /// assert!(response.ends_with("\r\n\r\n{}"));
+    /// ```
+    pub fn serve_http(self) -> Result<TmpHttpServer, std::io::Error> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let root = std::fs::canonicalize(&self.path)?;
+        let thread = {
+            let stopped = Arc::clone(&stopped);
+            std::thread::Builder::new()
+                .name("tmp_env-http".to_string())
+                .spawn(move || serve(listener, root, stopped))?
+        };
+
+        Ok(TmpHttpServer {
+            dir: self,
+            addr,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn serve(listener: TcpListener, root: PathBuf, stopped: Arc<AtomicBool>) {
+    let root = Arc::new(root);
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Ok(stream) = stream {
+            let root = Arc::clone(&root);
+            let _ = std::thread::Builder::new()
+                .name("tmp_env-http-connection".to_string())
+                .spawn(move || handle(stream, &root));
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, root: &Path) -> Result<(), std::io::Error> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return respond(&mut stream, "400 Bad Request", None, true),
+    };
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", None, true);
+    }
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let content = percent_decode(path)
+        .and_then(|path| resolve(root, Path::new(path.trim_start_matches('/'))).ok())
+        // The symbolic links inside the directory may point outside of it
+        .and_then(|path| std::fs::canonicalize(path).ok())
+        .filter(|path| path.starts_with(root) && path.is_file())
+        .and_then(|path| std::fs::read(path).ok());
+    match content {
+        Some(content) => respond(&mut stream, "200 OK", Some(&content), method == "GET"),
+        None => respond(&mut stream, "404 Not Found", None, true),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content: Option<&[u8]>,
+    with_body: bool,
+) -> Result<(), std::io::Error> {
+    let content = content.unwrap_or(status.as_bytes());
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n\r\n",
+        status,
+        content.len()
+    )?;
+    if with_body {
+        stream.write_all(content)?;
+    }
+    stream.flush()
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_temp_dir;
+    use std::io::Read;
+
+    fn request(server: &TmpHttpServer, request: &str) -> String {
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_tmp_dir_serve_http() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        tmp_dir.write("a b/file.txt", "content").unwrap();
+        let path = tmp_dir.to_path_buf();
+        let server = tmp_dir.serve_http().expect("cannot start server");
+        assert!(server.url().starts_with("http://127.0.0.1:"));
+
+        let response = request(&server, "GET /a%20b/file.txt?v=1 HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 7\r\n"));
+        assert!(response.ends_with("\r\n\r\ncontent"));
+        let response = request(&server, "HEAD /a%20b/file.txt HTTP/1.1\r\n\r\n");
+        assert!(response.contains("Content-Length: 7\r\n"));
+        assert!(response.ends_with("\r\n\r\n"));
+        for missing in &["/missing", "/a%20b", "/../etc/passwd", "/%zz"] {
+            let response = request(&server, &format!("GET {} HTTP/1.1\r\n\r\n", missing));
+            assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+        let response = request(&server, "DELETE /a%20b/file.txt HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        #[cfg(unix)]
+        {
+            let outside = create_temp_dir().unwrap();
+            outside.write("secret.txt", "secret").unwrap();
+            std::os::unix::fs::symlink(&*outside, server.join("outside")).unwrap();
+            let response = request(&server, "GET /outside/secret.txt HTTP/1.1\r\n\r\n");
+            assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+        // Files written after the server started are served too
+        server.write("late.txt", "late").unwrap();
+        assert!(request(&server, "GET /late.txt HTTP/1.1\r\n\r\n").ends_with("late"));
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
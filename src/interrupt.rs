@@ -0,0 +1,81 @@
+use crate::emergency_cleanup;
+
+/// Handle `Ctrl-C` (`SIGINT` on Unix, `CTRL_C_EVENT` on Windows) with the `ctrlc` crate: undo the
+/// changes of the guards opted in with
+/// [`TmpDirBuilder::cleanup_on_exit`](crate::TmpDirBuilder::cleanup_on_exit),
+/// [`TmpDir::set_cleanup_on_exit`](crate::TmpDir::set_cleanup_on_exit) or
+/// [`CurrentEnv::restore_on_exit`](crate::CurrentEnv::restore_on_exit) and then die from the
+/// interruption as if it wasn't handled, for the programs already handling `Ctrl-C` with `ctrlc`.
+/// It fails if another `ctrlc` handler is already set.
+///
+/// Both handlers can be used together, in any order: the handler installed with the first opted
+/// in guard forwards the signal to the `ctrlc` one once the changes are undone, and the `ctrlc`
+/// one undoes the changes on its own when it replaced the other.
+/// ```
+/// tmp_env::cleanup_on_ctrlc().expect("cannot set the ctrl-c handler");
+/// let _tmp_dir = tmp_env::TmpDir::builder()
+///     .cleanup_on_exit(true)
+///     .create()
+///     .expect("cannot create temp dir");
+/// ```
+pub fn cleanup_on_ctrlc() -> Result<(), std::io::Error> {
+    ::ctrlc::set_handler(|| {
+        emergency_cleanup();
+        die_from_interruption();
+    })
+    .map_err(std::io::Error::other)
+}
+
+#[cfg(unix)]
+fn die_from_interruption() {
+    // SAFETY: resetting the disposition and raising a signal have no memory requirements
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::raise(libc::SIGINT);
+    }
+}
+
+#[cfg(windows)]
+fn die_from_interruption() {
+    // STATUS_CONTROL_C_EXIT, the exit code of a process killed by ctrl-c
+    std::process::exit(0xC000_013A_u32 as i32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cleanup_on_ctrlc() {
+        cleanup_on_ctrlc().expect("cannot set the ctrl-c handler");
+        assert!(cleanup_on_ctrlc().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cleanup_on_ctrlc_with_opted_in_guard() {
+        use crate::{create_temp_dir, run_in_child, EnvChanges, TmpDir};
+        use std::os::unix::process::ExitStatusExt;
+
+        let parent = create_temp_dir().unwrap();
+        let changes = EnvChanges::new().set_var("TEST_CTRLC_PARENT", &*parent);
+        // The handler of the opted in guard replaces the `ctrlc` one, then forwards it the signal
+        let status = run_in_child(&changes, || {
+            cleanup_on_ctrlc().unwrap();
+            let tmp_dir = TmpDir::builder()
+                .parent(std::env::var_os("TEST_CTRLC_PARENT").unwrap())
+                .cleanup_on_exit(true)
+                .create()
+                .unwrap();
+            std::mem::forget(tmp_dir);
+            // SAFETY: raising a signal has no memory requirements
+            unsafe { libc::raise(libc::SIGINT) };
+            loop {
+                std::thread::park();
+            }
+        })
+        .unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGINT));
+        assert!(parent.read_dir().unwrap().next().is_none());
+    }
+}
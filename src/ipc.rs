@@ -0,0 +1,215 @@
+use std::{
+    ffi::CString,
+    fmt::Debug,
+    ops::Deref,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{ignore_not_found, random_path, Error, Policy};
+
+/// A helper datastructure for ensuring that we remove the named pipe created before
+/// end of the current scope.
+#[must_use = "the fifo is removed as soon as the guard is dropped"]
+pub struct TmpFifo {
+    path: PathBuf,
+    policy: Policy,
+}
+
+impl Deref for TmpFifo {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpFifo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Create a uniquely named pipe (FIFO) in the temporary directory of your operating system,
+/// only accessible by the current user
+/// ```
+/// use std::io::{Read, Write};
+///
+/// let fifo = tmp_env::create_temp_fifo().expect("cannot create fifo"); // When fifo is dropped the named pipe will be removed
+/// let path = fifo.to_path_buf();
+/// let writer = std::thread::spawn(move || {
+///     std::fs::OpenOptions::new().write(true).open(path).unwrap().write_all(b"hello").unwrap();
+/// });
+/// let mut content = String::new();
+/// std::fs::File::open(&*fifo).unwrap().read_to_string(&mut content).unwrap();
+/// writer.join().unwrap();
+/// assert_eq!(content, "hello");
+/// ```
+pub fn create_temp_fifo() -> Result<TmpFifo, std::io::Error> {
+    let tmp_path = std::env::temp_dir().join(random_path("".as_ref(), "".as_ref(), 10));
+    mkfifo(&tmp_path)?;
+
+    Ok(TmpFifo {
+        path: tmp_path,
+        policy: Policy::default(),
+    })
+}
+
+impl TmpFifo {
+    /// Choose how the failure to remove the fifo when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+fn mkfifo(path: &Path) -> Result<(), std::io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `c_path` is a valid NUL terminated string living until the end of the call
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Drop for TmpFifo {
+    fn drop(&mut self) {
+        if let Err(error) = ignore_not_found(std::fs::remove_file(&self.path)) {
+            let error = Error::restore(&self.path, error);
+            self.policy.handle(error, Error::report);
+        }
+    }
+}
+
+/// A helper datastructure for ensuring that we remove the Unix domain socket bound to the
+/// reserved path before end of the current scope.
+#[must_use = "the socket is removed as soon as the guard is dropped"]
+pub struct TmpSocketPath {
+    path: PathBuf,
+    policy: Policy,
+}
+
+impl Deref for TmpSocketPath {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpSocketPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+// Size of `sun_path` in `sockaddr_un`, including the NUL terminator
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+const SUN_PATH_LEN: usize = 104;
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+const SUN_PATH_LEN: usize = 108;
+
+/// Reserve a unique path, short enough to bind a Unix domain socket on it. The temporary
+/// directory of your operating system is used when its path is short enough (which is often not
+/// the case on macOS), `/tmp` otherwise. When dropped the socket file is removed if it has been
+/// created.
+/// ```
+/// let socket_path = tmp_env::temp_socket_path().expect("cannot reserve socket path");
+/// let _listener = std::os::unix::net::UnixListener::bind(&*socket_path).expect("cannot bind socket");
+/// assert!(socket_path.exists());
+/// ```
+pub fn temp_socket_path() -> Result<TmpSocketPath, std::io::Error> {
+    let name = random_path("".as_ref(), ".sock".as_ref(), 10);
+    let tmp_path = std::env::temp_dir().join(&name);
+    if tmp_path.as_os_str().len() < SUN_PATH_LEN {
+        return Ok(TmpSocketPath::new(tmp_path));
+    }
+    let tmp_path = Path::new("/tmp").join(&name);
+    if tmp_path.as_os_str().len() < SUN_PATH_LEN {
+        return Ok(TmpSocketPath::new(tmp_path));
+    }
+
+    Err(std::io::Error::other(format!(
+        "cannot find a socket path shorter than {} bytes",
+        SUN_PATH_LEN
+    )))
+}
+
+impl TmpSocketPath {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            policy: Policy::default(),
+        }
+    }
+
+    /// Choose how the failure to remove the socket when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl Drop for TmpSocketPath {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                let error = Error::restore(&self.path, error);
+                self.policy.handle(error, Error::report);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+
+    #[test]
+    fn test_tmp_fifo() {
+        #[allow(unused_assignments)]
+        let mut fifo_created: Option<PathBuf> = None;
+        {
+            let fifo = create_temp_fifo().expect("cannot create fifo");
+            fifo_created = Some(fifo.path.clone());
+            assert!(std::fs::metadata(&*fifo).unwrap().file_type().is_fifo());
+            assert!(mkfifo(&fifo).is_err());
+        }
+        assert!(std::fs::symlink_metadata(fifo_created.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_temp_socket_path() {
+        #[allow(unused_assignments)]
+        let mut socket_created: Option<PathBuf> = None;
+        {
+            let socket_path = temp_socket_path().expect("cannot reserve socket path");
+            socket_created = Some(socket_path.path.clone());
+            assert!(socket_path.as_os_str().len() < SUN_PATH_LEN);
+            assert!(!socket_path.exists());
+            let listener =
+                std::os::unix::net::UnixListener::bind(&*socket_path).expect("cannot bind socket");
+            std::os::unix::net::UnixStream::connect(&*socket_path).expect("cannot connect");
+            listener.accept().expect("cannot accept connection");
+        }
+        assert!(std::fs::symlink_metadata(socket_created.unwrap()).is_err());
+        // Never bound sockets are fine too
+        drop(temp_socket_path().expect("cannot reserve socket path"));
+    }
+}
@@ -3,23 +3,301 @@
 //! For example sometimes you need to change the current directory or set environment variables to launch a process but you don't need this temporary environment for the rest of your program.
 //! Then you will use `tmp_env` to create environment variable using `tmp_env::set_var` instead of `std::env::set_var` to get from `tmp_env::set_var` a datastructure which will automatically restore the
 //! corresponding environmet variable when dropped.
+//!
+//! # Concurrency
+//!
+//! Environment variables and the current directory are shared by all the threads of the process,
+//! so the guards changing them ([`CurrentEnv`], [`CurrentDir`]) only restore the right values if
+//! they are restored in the reverse order they were created, on the thread which created them.
+//! They are still `Send` so they can be stored anywhere, but:
+//! - wrap them in a [`LocalGuard`] to make the compiler refuse to move them to another thread
+//! - call `adopt_thread()` on the thread receiving one when you hand it over on purpose
+//! - in debug builds, restoring one on another thread than its owner is reported on stderr
+//! - take [`lock_current_dir`] (or use [`set_current_dir_locked`]) to serialize the threads
+//!   changing the current directory, for example the tests of a crate
 use std::{
+    backtrace::Backtrace,
+    borrow::Cow,
     ffi::{OsStr, OsString},
     fmt::Debug,
+    io::Write,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, PoisonError, RwLock},
+    thread::ThreadId,
 };
 
-use rand::distributions::Alphanumeric;
-use rand::{thread_rng, Rng};
+use counters::Counter;
+use registry::Registration;
+use thread_owner::ThreadOwner;
+use trace::Trace;
+use unused_guard::CreatedAt;
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+mod affinity;
+#[cfg(feature = "tokio")]
+mod async_dir;
+#[cfg(unix)]
+mod capture;
+mod cargo_project;
+mod child;
+#[cfg(all(feature = "chroot", unix))]
+mod chroot;
+mod command;
+mod compare;
+#[cfg(feature = "config")]
+mod config;
+mod content;
+mod core_dump;
+mod counters;
+mod custom;
+mod deferred;
+mod dry_run;
+mod emergency;
+mod env_scope;
+mod env_set;
+mod error;
+#[cfg(feature = "duct")]
+mod expression;
+mod file;
+mod file_lock;
+mod free_space;
+mod gc;
+#[cfg(feature = "git")]
+mod git;
+mod guard;
+mod handoff;
+#[cfg(feature = "hostname")]
+mod hostname;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "ctrlc")]
+mod interrupt;
+#[cfg(unix)]
+mod ipc;
+mod lifecycle;
+mod link;
+#[cfg(all(feature = "memfd", any(target_os = "linux", target_os = "android")))]
+mod memfd;
+#[cfg(all(feature = "mount", target_os = "linux"))]
+mod mount;
+mod naming;
+mod parallel_delete;
+mod poison;
+mod policy;
+mod pool;
+mod priority;
+#[cfg(all(feature = "privileges", unix))]
+mod privileges;
+mod quota;
+mod read_only;
+mod registry;
+mod retry;
+mod sandbox;
+mod scoped;
+mod shared;
+#[cfg(unix)]
+mod signal;
+mod snapshot;
+mod sqlite;
+#[cfg(feature = "tokio")]
+pub mod task_env;
+mod thread_owner;
+mod trace;
+mod tree;
+mod unused_guard;
+pub mod vcwd;
+mod walk;
+mod with;
+
+#[cfg(any(target_os = "linux", target_os = "android", windows))]
+pub use affinity::{set_cpu_affinity, TmpCpuAffinity};
+#[cfg(feature = "tokio")]
+pub use async_dir::create_temp_dir_async;
+#[cfg(unix)]
+pub use capture::{
+    capture_stderr, capture_stdout, redirect_fd, redirect_stdin, redirect_stdin_from, TmpCapture,
+    TmpRedirect, TmpStdin,
+};
+pub use cargo_project::{create_temp_cargo_project, CargoProjectBuilder, TmpCargoProject};
+pub use child::{run_command_isolated, run_in_child};
+#[cfg(all(feature = "chroot", unix))]
+pub use chroot::{chroot_temp, TmpChroot};
+pub use command::{CommandExt, EnvChanges};
+pub use compare::{assert_dir_eq, compare_dirs, CompareOptions, DirMismatch};
+#[cfg(feature = "config")]
+pub use config::{create_temp_config, Format};
+pub use core_dump::{suppress_core_dumps, TmpCoreDumps};
+pub use counters::{measure_removed_bytes, metrics, Metrics};
+pub use custom::{guard, Guard, Restore};
+pub use deferred::flush_cleanup;
+pub use dry_run::{dry_run, is_dry_run, Plan, PlannedChange};
+pub use emergency::emergency_cleanup;
+pub use env_scope::EnvScope;
+pub use env_set::TmpEnvSet;
+pub use error::Error;
+#[cfg(feature = "duct")]
+pub use expression::ExpressionExt;
+pub use file::{create_temp_file, create_temp_file_with_content, TmpFile, TmpFileBuilder};
+pub use file_lock::{lock_file, FileLock};
+pub use gc::{gc_orphans, gc_orphans_in};
+#[cfg(feature = "git")]
+pub use git::{create_temp_git_repo, TmpGitRepo};
+pub use guard::TempGuard;
+pub use handoff::{AdoptedResources, CleanupToken, ResourceKind};
+#[cfg(feature = "hostname")]
+pub use hostname::{hostname, set_hostname, TmpHostname};
+#[cfg(feature = "http")]
+pub use http::TmpHttpServer;
+#[cfg(feature = "ctrlc")]
+pub use interrupt::cleanup_on_ctrlc;
+#[cfg(unix)]
+pub use ipc::{create_temp_fifo, temp_socket_path, TmpFifo, TmpSocketPath};
+pub use lifecycle::{on_apply, on_restore, redact_values, LifecycleEvent};
+pub use link::{create_temp_hardlink, create_temp_symlink, TmpHardLink, TmpSymlink};
+#[cfg(all(feature = "memfd", any(target_os = "linux", target_os = "android")))]
+pub use memfd::create_temp_memfd;
+#[cfg(all(feature = "mount", target_os = "linux"))]
+pub use mount::{mount_tmpfs, overlay_over, TmpMount};
+pub use naming::Naming;
+pub use poison::{is_poisoned, take_poison, Poison};
+pub use policy::Policy;
+pub use pool::{PooledTmpDir, TmpDirPool};
+pub use priority::{current_priority, set_nice, set_priority, TmpPriority};
+#[cfg(all(feature = "privileges", unix))]
+pub use privileges::{set_egid, set_euid, TmpEffectiveId};
+pub use quota::QuotaWatcher;
+pub use registry::{active_changes, ActiveChange, ChangeKind};
+pub use retry::RetryPolicy;
+pub use sandbox::Sandbox;
+pub use shared::TmpDirShared;
+#[cfg(unix)]
+pub use signal::{set_signal_handler, SignalHandler, TmpSignalHandler};
+pub use snapshot::{Diff, EntryState, Snapshot};
+#[cfg(feature = "sqlite")]
+pub use sqlite::create_temp_sqlite_with_schema;
+pub use sqlite::{create_temp_sqlite, TmpSqlite};
+pub use thread_owner::LocalGuard;
+#[cfg(feature = "macros")]
+pub use tmp_env_macros::tmp_env;
+pub use tree::Tree;
+pub use unused_guard::report_unused_guards;
+pub use walk::Walk;
+pub use with::{with_current_dir, with_removed_var, with_temp_dir, with_var};
 
 /// A helper datastructure for ensuring that we switch back to the current folder before the
 /// end of the current scope.
-pub struct CurrentDir(std::path::PathBuf);
+#[must_use = "the current directory is restored as soon as the guard is dropped"]
+pub struct CurrentDir {
+    original: PathBuf,
+    current: PathBuf,
+    restored: bool,
+    fallback: Fallback,
+    created: Option<PathBuf>,
+    remove_created: bool,
+    owner: ThreadOwner,
+    created_at: CreatedAt,
+    registration: Registration,
+    policy: Policy,
+    trace: Trace,
+    _lock: Option<CurrentDirLock>,
+}
+
+/// Directory to switch to when a [`CurrentDir`] can't go back to its original directory (for
+/// example because it has been deleted in the meantime).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Fallback {
+    /// Stay in the current directory
+    #[default]
+    None,
+    /// Switch to the temporary directory of your operating system
+    TempDir,
+    /// Switch to the home directory of the current user
+    Home,
+    /// Switch to the nearest ancestor of the original directory which still exists
+    NearestAncestor,
+    /// Switch to the given directory
+    Path(PathBuf),
+}
+
+impl Fallback {
+    fn path(&self, original: &Path) -> Option<PathBuf> {
+        match self {
+            Fallback::None => None,
+            Fallback::NearestAncestor => original
+                .ancestors()
+                .find(|ancestor| ancestor.is_dir())
+                .map(Path::to_path_buf),
+            Fallback::TempDir => Some(std::env::temp_dir()),
+            Fallback::Home => std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+                .map(PathBuf::from),
+            Fallback::Path(path) => Some(path.clone()),
+        }
+    }
+}
 
 impl Debug for CurrentDir {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.0)
+        write!(f, "{:?}", self.original)
+    }
+}
+
+/// Describe what happened when a [`CurrentDir`] couldn't go back to its original directory
+#[derive(Debug)]
+pub struct DirFallbackEvent {
+    /// The directory we couldn't switch back to
+    pub original: PathBuf,
+    /// Why we couldn't switch back to the original directory
+    pub error: std::io::Error,
+    /// The fallback directory we switched to instead, if any
+    pub fallback: Option<PathBuf>,
+    /// Why we couldn't switch to the fallback directory, if we tried
+    pub fallback_error: Option<std::io::Error>,
+}
+
+type DirFallbackHook = Box<dyn Fn(&DirFallbackEvent) + Send + Sync>;
+
+static DIR_FALLBACK_HOOK: RwLock<Option<DirFallbackHook>> = RwLock::new(None);
+
+/// Register a hook called every time a [`CurrentDir`] can't go back to its original directory
+/// when dropped, replacing the previous one. By default the event is printed on stderr, or
+/// emitted as a `tracing` warning with the `tracing` feature.
+/// ```
+/// tmp_env::on_dir_fallback(|event| {
+///     eprintln!("cannot go back to {:?}, now in {:?}", event.original, event.fallback)
+/// });
+/// ```
+pub fn on_dir_fallback<F: Fn(&DirFallbackEvent) + Send + Sync + 'static>(hook: F) {
+    *DIR_FALLBACK_HOOK
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(hook));
+}
+
+/// Serialize the tests registering the global hooks, each one replacing the previous hook
+#[cfg(test)]
+pub(crate) fn lock_hooks() -> std::sync::MutexGuard<'static, ()> {
+    static HOOKS: Mutex<()> = Mutex::new(());
+    HOOKS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn report_dir_fallback(event: DirFallbackEvent) {
+    match &*DIR_FALLBACK_HOOK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        Some(hook) => hook(&event),
+        None => {
+            trace::warn(format_args!(
+                "cannot go back to the previous directory {:?}: {}",
+                event.original, event.error
+            ));
+            if let (Some(fallback), Some(err)) = (&event.fallback, &event.fallback_error) {
+                trace::warn(format_args!(
+                    "cannot go to the fallback directory {:?}: {}",
+                    fallback, err
+                ));
+            }
+        }
     }
 }
 
@@ -34,27 +312,583 @@ impl Debug for CurrentDir {
 /// let current_dir = std::env::current_dir().expect("cannot get current dir from std env");
 /// assert!(!current_dir.ends_with("src"));
 /// // Because guard is dropped
-/// tmp_env::set_current_dir("target").expect("should set the new current_dir");
+/// drop(tmp_env::set_current_dir("target").expect("should set the new current_dir"));
 /// assert!(!current_dir.ends_with("target"));
 /// ```
+///
+/// A guard dropped on the line which created it, like with `let _ = tmp_env::set_current_dir(...)`,
+/// changes nothing, which debug builds report, see [`report_unused_guards`].
+#[track_caller]
 pub fn set_current_dir<P: AsRef<Path>>(path: P) -> Result<CurrentDir, std::io::Error> {
-    let current_dir = std::env::current_dir()?;
-    std::env::set_current_dir(&path)?;
-    Ok(CurrentDir(current_dir))
+    CurrentDir::builder().set(path)
+}
+
+/// Same as [`set_current_dir`] but also take the lock returned by [`lock_current_dir`] until the
+/// datastructure is dropped so other threads can't change the current directory in the meantime.
+/// ```
+/// {
+///     let _tmp_current_dir = tmp_env::set_current_dir_locked("src").expect("should set the new current_dir");
+///     let current_dir = std::env::current_dir().expect("cannot get current dir from std env");
+///     assert!(current_dir.ends_with("src"));
+/// }
+/// ```
+#[track_caller]
+pub fn set_current_dir_locked<P: AsRef<Path>>(path: P) -> Result<CurrentDir, std::io::Error> {
+    CurrentDir::builder().lock(true).set(path)
+}
+
+/// Same as [`set_current_dir`] but recursively create the given path first if it doesn't exist.
+/// The created directories can be removed once dropped with [`CurrentDir::remove_created_dirs`].
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let nested_dir = tmp_dir.join("a").join("b");
+/// {
+///     let _tmp_current_dir = tmp_env::set_current_dir_all(&nested_dir)
+///         .expect("should set the new current_dir")
+///         .remove_created_dirs(true);
+///     assert!(std::env::current_dir().unwrap().ends_with("a/b"));
+/// }
+/// // Because guard is dropped the created directories are removed
+/// assert!(!tmp_dir.join("a").exists());
+/// ```
+#[track_caller]
+pub fn set_current_dir_all<P: AsRef<Path>>(path: P) -> Result<CurrentDir, std::io::Error> {
+    CurrentDir::builder().create(true).set(path)
+}
+
+/// A builder to configure how to switch to another directory with a [`CurrentDir`]
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let _tmp_current_dir = tmp_env::CurrentDir::builder()
+///     .canonicalize(true)
+///     .create(true)
+///     .remove_created_dirs(true)
+///     .fallback(tmp_env::Fallback::TempDir)
+///     .set(tmp_dir.join("sub"))
+///     .expect("should set the new current_dir");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CurrentDirBuilder {
+    canonicalize: bool,
+    create: bool,
+    remove_created: bool,
+    lock: bool,
+    fallback: Fallback,
+}
+
+impl CurrentDirBuilder {
+    /// Canonicalize both the target and the remembered original directory, resolving symlinks
+    /// (for example `/tmp` being a symlink to `/private/tmp` on macOS)
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Recursively create the target if it doesn't exist, see [`set_current_dir_all`]
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Remove the directories created by [`CurrentDirBuilder::create`] when dropped
+    pub fn remove_created_dirs(mut self, remove: bool) -> Self {
+        self.remove_created = remove;
+        self
+    }
+
+    /// Hold the current directory lock until dropped, see [`set_current_dir_locked`]
+    pub fn lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Set the directory to switch to if the original directory can't be restored when dropped
+    pub fn fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Memorize the current path and switch to the given path
+    #[track_caller]
+    pub fn set<P: AsRef<Path>>(self, path: P) -> Result<CurrentDir, std::io::Error> {
+        if dry_run::is_dry_run() {
+            let original = std::env::current_dir()?;
+            let current = original.join(path);
+            dry_run::record(PlannedChange::SetCurrentDir(current.clone()));
+            return Ok(CurrentDir {
+                original,
+                current,
+                restored: true,
+                fallback: self.fallback,
+                created: None,
+                remove_created: false,
+                owner: ThreadOwner::current(),
+                created_at: CreatedAt::caller(),
+                registration: Registration::none(),
+                policy: Policy::default(),
+                trace: Trace::none(),
+                _lock: None,
+            });
+        }
+        let lock = if self.lock {
+            Some(lock_current_dir())
+        } else {
+            None
+        };
+        let backtrace = registry::capture_backtrace();
+        let mut original = std::env::current_dir()?;
+        let mut path = original.join(path);
+        let created = if self.create {
+            let created = path
+                .ancestors()
+                .take_while(|ancestor| !ancestor.exists())
+                .last()
+                .map(Path::to_path_buf);
+            std::fs::create_dir_all(&path)?;
+            created
+        } else {
+            None
+        };
+        if self.canonicalize {
+            original = original.canonicalize()?;
+            path = path.canonicalize()?;
+        }
+        std::env::set_current_dir(&path)?;
+        let current = if self.canonicalize {
+            path
+        } else {
+            std::env::current_dir()?
+        };
+
+        let trace = trace::span!("current_dir", original = ?original);
+        trace::event!(trace, current = ?current, "entered");
+
+        let registration = Registration::with_values(
+            ChangeKind::CurrentDir,
+            current.as_os_str(),
+            Some(original.as_os_str()),
+            Some(current.as_os_str()),
+            backtrace,
+        );
+
+        Ok(CurrentDir {
+            original,
+            current,
+            restored: false,
+            fallback: self.fallback,
+            created,
+            remove_created: self.remove_created,
+            owner: ThreadOwner::current(),
+            created_at: CreatedAt::caller(),
+            registration,
+            policy: Policy::default(),
+            trace,
+            _lock: lock,
+        })
+    }
+}
+
+static CURRENT_DIR_OWNER: Mutex<Option<(ThreadId, usize)>> = Mutex::new(None);
+static CURRENT_DIR_RELEASED: Condvar = Condvar::new();
+
+/// A helper datastructure holding the current directory lock until the end of the current scope.
+#[must_use = "the lock is released as soon as it is dropped"]
+pub struct CurrentDirLock(());
+
+impl Debug for CurrentDirLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CurrentDirLock")
+    }
+}
+
+/// Take the lock serializing changes of the current directory across threads, waiting for
+/// other threads to release it if needed. The lock is reentrant so the same thread can take it
+/// several times (for example with nested [`set_current_dir_locked`]).
+/// ```
+/// let _lock = tmp_env::lock_current_dir();
+/// // No other thread using the lock can change the current directory until `_lock` is dropped
+/// let _tmp_current_dir = tmp_env::set_current_dir_locked("src").expect("should set the new current_dir");
+/// ```
+pub fn lock_current_dir() -> CurrentDirLock {
+    let thread_id = std::thread::current().id();
+    let mut owner = CURRENT_DIR_OWNER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    loop {
+        match &mut *owner {
+            Some((owner_id, count)) if *owner_id == thread_id => {
+                *count += 1;
+                break;
+            }
+            Some(_) => {
+                owner = CURRENT_DIR_RELEASED
+                    .wait(owner)
+                    .unwrap_or_else(PoisonError::into_inner)
+            }
+            None => {
+                *owner = Some((thread_id, 1));
+                break;
+            }
+        }
+    }
+
+    CurrentDirLock(())
+}
+
+impl Drop for CurrentDirLock {
+    fn drop(&mut self) {
+        let mut owner = CURRENT_DIR_OWNER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, count)) = &mut *owner {
+            *count -= 1;
+            if *count == 0 {
+                *owner = None;
+                CURRENT_DIR_RELEASED.notify_all();
+            }
+        }
+    }
+}
+impl CurrentDir {
+    /// Create a builder to configure how to switch to another directory
+    pub fn builder() -> CurrentDirBuilder {
+        CurrentDirBuilder::default()
+    }
+
+    /// Set the directory to switch to if the original directory can't be restored when dropped
+    /// ```
+    /// let _tmp_current_dir = tmp_env::set_current_dir("src")
+    ///     .expect("should set the new current_dir")
+    ///     .fallback(tmp_env::Fallback::TempDir);
+    /// ```
+    pub fn fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Choose how the failure to switch back to the original directory (the fallback directory is
+    /// still used) or to remove the created directories when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Remove the directories created by [`set_current_dir_all`] when dropped
+    pub fn remove_created_dirs(mut self, remove: bool) -> Self {
+        self.remove_created = remove;
+        self
+    }
+
+    /// Declare that the current thread owns the datastructure, after handing it over from the
+    /// thread which created it. In debug builds, switching back to the original directory on
+    /// another thread than its owner is reported on stderr.
+    pub fn adopt_thread(&mut self) {
+        self.owner = ThreadOwner::current();
+    }
+
+    /// The directory we will switch back to when dropped
+    pub fn original(&self) -> &Path {
+        &self.original
+    }
+
+    /// The directory we switched to
+    pub fn current(&self) -> &Path {
+        &self.current
+    }
+
+    /// The canonical form of the directory we will switch back to when dropped, with all
+    /// symlinks resolved
+    pub fn canonical_original(&self) -> Result<PathBuf, std::io::Error> {
+        self.original.canonicalize()
+    }
+
+    /// Switch back to the original directory right now instead of waiting for the end of the scope
+    /// ```
+    /// let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+    /// let tmp_current_dir = tmp_env::set_current_dir("src").expect("should set the new current_dir");
+    /// assert_eq!(tmp_current_dir.original(), original_dir);
+    /// assert!(tmp_current_dir.current().ends_with("src"));
+    /// tmp_current_dir.restore_now().expect("should go back to the original dir");
+    /// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+    /// ```
+    pub fn restore_now(mut self) -> Result<(), Error> {
+        TempGuard::restore_now(&mut self)
+    }
+
+    fn remove_created(&mut self) -> Result<(), std::io::Error> {
+        match self.created.take() {
+            Some(created) if self.remove_created => ignore_not_found(remove_tree(&created)),
+            _ => Ok(()),
+        }
+    }
+
+    fn restore(&mut self) -> Result<(), std::io::Error> {
+        if !self.restored {
+            std::env::set_current_dir(&self.original)?;
+            self.restored = true;
+            self.registration.restored();
+            trace::event!(self.trace, "left");
+        }
+        Ok(())
+    }
 }
 
 impl Drop for CurrentDir {
     fn drop(&mut self) {
-        std::env::set_current_dir(&self.0).expect("cannot go back to the previous directory");
+        let unused = !self.restored;
+        if unused {
+            self.owner.check(self);
+        }
+        if let Err(error) = self.restore() {
+            let fallback = self.fallback.path(&self.original);
+            let fallback_error = fallback
+                .as_ref()
+                .and_then(|fallback| std::env::set_current_dir(fallback).err());
+            self.policy
+                .handle(Error::restore(&self.original, error), |error| {
+                    report_dir_fallback(DirFallbackEvent {
+                        original: self.original.clone(),
+                        error: error.into_source(),
+                        fallback,
+                        fallback_error,
+                    })
+                });
+        }
+        if let Some(created) = self.created.take().filter(|_| self.remove_created) {
+            if let Err(error) = remove_tree(&created) {
+                self.policy
+                    .handle(Error::restore(created, error), Error::report);
+            }
+        }
+        if unused {
+            self.created_at.check(self);
+        }
+    }
+}
+
+/// Describe a temporary resource which couldn't be deleted when dropped
+#[derive(Debug)]
+pub struct CleanupErrorEvent {
+    /// The path we couldn't delete
+    pub path: PathBuf,
+    /// Why we couldn't delete it
+    pub error: std::io::Error,
+}
+
+type CleanupErrorHook = Box<dyn Fn(&CleanupErrorEvent) + Send + Sync>;
+
+static CLEANUP_ERROR_HOOK: RwLock<Option<CleanupErrorHook>> = RwLock::new(None);
+
+/// Register a hook called every time a temporary resource ([`TmpDir`], [`TmpFile`], directories
+/// created by [`set_current_dir_all`]) can't be deleted when dropped, replacing the previous one.
+/// By default the error is printed on stderr, or emitted as a `tracing` warning with the `tracing`
+/// feature.
+/// ```
+/// tmp_env::on_cleanup_error(|event| {
+///     eprintln!("cannot delete {:?}: {}", event.path, event.error)
+/// });
+/// ```
+pub fn on_cleanup_error<F: Fn(&CleanupErrorEvent) + Send + Sync + 'static>(hook: F) {
+    *CLEANUP_ERROR_HOOK
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(hook));
+}
+
+pub(crate) fn report_cleanup_error(event: CleanupErrorEvent) {
+    match &*CLEANUP_ERROR_HOOK
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+    {
+        Some(hook) => hook(&event),
+        None => trace::warn(format_args!(
+            "cannot delete {:?}: {}",
+            event.path, event.error
+        )),
+    }
+}
+
+/// A helper datastructure to navigate through several directories like `pushd`/`popd` would do.
+/// Directories are restored in LIFO order and the whole stack is unwound when dropped.
+/// ```
+/// let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+/// {
+///     let mut dir_stack = tmp_env::DirStack::new();
+///     dir_stack.push("src").expect("should push src");
+///     assert!(std::env::current_dir().unwrap().ends_with("src"));
+///     dir_stack.push("..").expect("should push parent dir");
+///     assert_eq!(std::env::current_dir().unwrap(), original_dir);
+///     dir_stack.pop().expect("should pop parent dir");
+///     assert!(std::env::current_dir().unwrap().ends_with("src"));
+/// }
+/// // Because the stack is dropped we are back in the original directory
+/// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+/// ```
+#[derive(Debug, Default)]
+pub struct DirStack(Vec<CurrentDir>);
+
+impl DirStack {
+    /// Create an empty directory stack
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switch to the given path and remember the directory we come from
+    pub fn push<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.0.push(set_current_dir(path)?);
+        Ok(())
+    }
+
+    /// Switch back to the directory we were in before the last `push` and return it
+    pub fn pop(&mut self) -> Result<PathBuf, Error> {
+        let mut current_dir = self
+            .0
+            .pop()
+            .ok_or_else(|| std::io::Error::other("directory stack is empty"))?;
+        if let Err(err) = current_dir.restore() {
+            let err = Error::restore(&current_dir.original, err);
+            self.0.push(current_dir);
+            return Err(err);
+        }
+
+        Ok(current_dir.original.clone())
+    }
+
+    /// Number of directories pushed on the stack
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if no directory has been pushed on the stack
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for DirStack {
+    fn drop(&mut self) {
+        while let Some(current_dir) = self.0.pop() {
+            drop(current_dir);
+        }
     }
 }
 /// A helper datastructure for ensuring that we restore the current environment variable before the
 /// end of the current scope.
-pub struct CurrentEnv(OsString, Option<String>);
+#[must_use = "the environment variable is restored as soon as the guard is dropped"]
+pub struct CurrentEnv {
+    key: OsString,
+    previous_val: Option<String>,
+    restored: bool,
+    emergency: Option<u64>,
+    owner: ThreadOwner,
+    created_at: CreatedAt,
+    registration: Registration,
+    trace: Trace,
+}
+
+impl CurrentEnv {
+    #[track_caller]
+    fn new(
+        key: &OsStr,
+        previous_val: Option<String>,
+        value: Option<&OsStr>,
+        backtrace: Option<Arc<Backtrace>>,
+        trace: Trace,
+    ) -> Self {
+        let registration = Registration::with_values(
+            ChangeKind::Var,
+            key,
+            previous_val.as_deref().map(OsStr::new),
+            value,
+            backtrace,
+        );
+        Self {
+            key: key.to_owned(),
+            previous_val,
+            restored: false,
+            emergency: None,
+            owner: ThreadOwner::current(),
+            created_at: CreatedAt::caller(),
+            registration,
+            trace,
+        }
+    }
+
+    /// A guard doing nothing, for [`dry_run`]
+    #[track_caller]
+    fn planned(key: &OsStr) -> Self {
+        Self {
+            key: key.to_owned(),
+            previous_val: None,
+            restored: true,
+            emergency: None,
+            owner: ThreadOwner::current(),
+            created_at: CreatedAt::caller(),
+            registration: Registration::none(),
+            trace: Trace::none(),
+        }
+    }
+
+    /// Declare that the current thread owns the datastructure, after handing it over from the
+    /// thread which created it. In debug builds, restoring the environment variable on another
+    /// thread than its owner is reported on stderr.
+    pub fn adopt_thread(&mut self) {
+        self.owner = ThreadOwner::current();
+    }
+
+    /// Also restore the environment variable if the process exits without dropping the
+    /// datastructure, see [`emergency_cleanup`]
+    pub fn restore_on_exit(mut self) -> Self {
+        self.set_restore_on_exit(true);
+        self
+    }
+
+    /// Opt the environment variable in or out of the restoration when the process exits without
+    /// dropping the datastructure, see [`CurrentEnv::restore_on_exit`]
+    pub fn set_restore_on_exit(&mut self, enabled: bool) {
+        match self.emergency {
+            None if enabled && !self.restored => {
+                let id = emergency::register_var(self.key.clone(), self.previous_val.clone());
+                self.emergency = Some(id);
+            }
+            Some(id) if !enabled => {
+                self.emergency = None;
+                if !emergency::unregister(id) {
+                    // Already restored by `emergency_cleanup`
+                    self.restored = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn restore(&mut self) {
+        if !self.restored {
+            let already_restored = self
+                .emergency
+                .take()
+                .is_some_and(|id| !emergency::unregister(id));
+            if !already_restored {
+                match self.previous_val.take() {
+                    Some(previous_val) => std::env::set_var(&self.key, previous_val),
+                    None => std::env::remove_var(&self.key),
+                }
+                trace::event!(self.trace, "restored");
+            }
+            self.restored = true;
+            self.registration.restored();
+        }
+    }
+
+    pub(crate) fn disarm(&mut self) {
+        self.set_restore_on_exit(false);
+        self.restored = true;
+        self.registration.release();
+    }
+}
 
 impl Debug for CurrentEnv {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.0)
+        write!(f, "{:?}", self.key)
     }
 }
 
@@ -67,14 +901,35 @@ impl Debug for CurrentEnv {
 /// }
 /// assert!(std::env::var("TEST_TMP_ENV").is_err());
 /// // Because guard is dropped then the environment variable is also automatically unset (not restored because no previous value was set)
-/// tmp_env::set_var("TEST_TMP_ENV_DROPPED", "myvaluedropped");
+/// drop(tmp_env::set_var("TEST_TMP_ENV_DROPPED", "myvaluedropped"));
 /// assert!(std::env::var("TEST_TMP_ENV_DROPPED").is_err());
 /// ```
+///
+/// A guard dropped on the line which created it, like with `let _ = tmp_env::set_var(...)`,
+/// changes nothing, which debug builds report, see [`report_unused_guards`].
+///
+/// # Panics
+///
+/// Like [`std::env::set_var`] it panics if the name is empty or contains `=` or a NUL character,
+/// or if the value contains a NUL character, use [`try_set_var`] to get an error instead.
+#[track_caller]
 pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) -> CurrentEnv {
     let key = key.as_ref();
+    let value = value.as_ref();
+    if dry_run::is_dry_run() {
+        dry_run::record(PlannedChange::SetVar {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+        return CurrentEnv::planned(key);
+    }
+    let backtrace = registry::capture_backtrace();
     let previous_val = std::env::var(key).ok();
     std::env::set_var(key, value);
-    CurrentEnv(key.to_owned(), previous_val)
+    counters::count(Counter::VarsChanged, 1);
+    let trace = trace::span!("set_var", key = ?key);
+    trace::event!(trace, had_previous = previous_val.is_some(), "set");
+    CurrentEnv::new(key, previous_val, Some(value), backtrace, trace)
 }
 
 /// Removes the environment variable k for the currently running process.
@@ -87,46 +942,132 @@ pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) -> CurrentEnv
 ///     assert!(std::env::var("TEST_TMP_ENV").is_err());
 /// }
 /// // Because guard is dropped then the environment variable is also automatically restored
-/// tmp_env::remove_var("TEST_TMP_ENV");
+/// drop(tmp_env::remove_var("TEST_TMP_ENV"));
 /// assert_eq!(std::env::var("TEST_TMP_ENV"), Ok(String::from("myvalue")));
 /// ```
+///
+/// # Panics
+///
+/// Like [`std::env::remove_var`] it panics if the name is empty or contains `=` or a NUL
+/// character, use [`try_remove_var`] to get an error instead.
+#[track_caller]
 pub fn remove_var<K: AsRef<OsStr>>(key: K) -> CurrentEnv {
     let key = key.as_ref();
+    if dry_run::is_dry_run() {
+        dry_run::record(PlannedChange::RemoveVar {
+            key: key.to_owned(),
+        });
+        return CurrentEnv::planned(key);
+    }
+    let backtrace = registry::capture_backtrace();
     let previous_val = std::env::var(key).ok();
     std::env::remove_var(key);
-    CurrentEnv(key.to_owned(), previous_val)
+    counters::count(Counter::VarsChanged, 1);
+    let trace = trace::span!("remove_var", key = ?key);
+    trace::event!(trace, had_previous = previous_val.is_some(), "removed");
+    CurrentEnv::new(key, previous_val, None, backtrace, trace)
+}
+
+/// Same as [`set_var`] but it returns an error instead of panicking if the name or the value of
+/// the environment variable is invalid
+/// ```
+/// let tmp_env = tmp_env::try_set_var("TEST_TMP_ENV_TRY", "myvalue").expect("valid variable");
+/// assert_eq!(std::env::var("TEST_TMP_ENV_TRY"), Ok(String::from("myvalue")));
+/// assert!(tmp_env::try_set_var("TEST_TMP_ENV_TRY", "my\0value").is_err());
+/// ```
+#[track_caller]
+pub fn try_set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(
+    key: K,
+    value: V,
+) -> Result<CurrentEnv, Error> {
+    error::check_var(key.as_ref(), Some(value.as_ref()))?;
+    Ok(set_var(key, value))
+}
+
+/// Same as [`remove_var`] but it returns an error instead of panicking if the name of the
+/// environment variable is invalid
+/// ```
+/// assert!(tmp_env::try_remove_var("").is_err());
+/// ```
+#[track_caller]
+pub fn try_remove_var<K: AsRef<OsStr>>(key: K) -> Result<CurrentEnv, Error> {
+    error::check_var(key.as_ref(), None)?;
+    Ok(remove_var(key))
+}
+
+/// Same as [`std::env::var`] but the variables set with
+/// [`task_env::scope`](crate::task_env::scope) for the current tokio task (with the `tokio`
+/// feature) take precedence over the environment of the process
+/// ```
+/// let _tmp_env = tmp_env::set_var("TEST_TMP_ENV_VAR", "value");
+/// assert_eq!(tmp_env::var("TEST_TMP_ENV_VAR"), Ok(String::from("value")));
+/// ```
+pub fn var<K: AsRef<OsStr>>(key: K) -> Result<String, std::env::VarError> {
+    match var_os(key) {
+        Some(value) => value.into_string().map_err(std::env::VarError::NotUnicode),
+        None => Err(std::env::VarError::NotPresent),
+    }
+}
+
+/// Same as [`std::env::var_os`] but the variables set with
+/// [`task_env::scope`](crate::task_env::scope) for the current tokio task (with the `tokio`
+/// feature) take precedence over the environment of the process
+pub fn var_os<K: AsRef<OsStr>>(key: K) -> Option<OsString> {
+    let key = key.as_ref();
+    #[cfg(feature = "tokio")]
+    if let Some(value) = task_env::var_os(key) {
+        return Some(value);
+    }
+    std::env::var_os(key)
 }
 
 impl Drop for CurrentEnv {
     fn drop(&mut self) {
-        match self.1.take() {
-            Some(previous_val) => std::env::set_var(&self.0, previous_val),
-            None => std::env::remove_var(&self.0),
+        let unused = !self.restored;
+        if unused {
+            self.owner.check(self);
+        }
+        self.restore();
+        if unused {
+            self.created_at.check(self);
         }
     }
 }
 
 /// A helper datastructure for ensuring that we delete the tmp dir created before
-/// end of the current scope.
-pub struct TmpDir(pub(crate) PathBuf);
+/// end of the current scope. The symlinks inside it are removed but never followed.
+#[must_use = "the temporary directory is deleted as soon as the guard is dropped"]
+pub struct TmpDir {
+    pub(crate) path: PathBuf,
+    secure_delete: bool,
+    read_only: bool,
+    retry: RetryPolicy,
+    delete_threads: usize,
+    deferred: bool,
+    max_size: Option<u64>,
+    emergency: Option<u64>,
+    registration: Registration,
+    policy: Policy,
+    trace: Trace,
+}
 
 impl Deref for TmpDir {
     type Target = PathBuf;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.path
     }
 }
 
 impl DerefMut for TmpDir {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.path
     }
 }
 
 impl Debug for TmpDir {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.0)
+        write!(f, "{:?}", self.path)
     }
 }
 
@@ -139,27 +1080,697 @@ impl Debug for TmpDir {
 /// // The temporary directory is now removed
 /// ```
 pub fn create_temp_dir() -> Result<TmpDir, std::io::Error> {
-    let tmp_dir = std::env::temp_dir();
-    let tmp_path = tmp_dir.join(random_path());
-    std::fs::create_dir(&tmp_path)?;
+    TmpDir::builder().create()
+}
+
+/// Create a temporary directory inside the given parent directory, for example to keep it on a
+/// specific filesystem
+/// ```
+/// let parent = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// {
+///     let tmp_dir = tmp_env::create_temp_dir_in(&*parent).expect("cannot create temp dir");
+///     assert_eq!(tmp_dir.parent(), Some(parent.as_path()));
+/// }
+/// // The temporary directory is now removed
+/// assert_eq!(std::fs::read_dir(&*parent).unwrap().count(), 0);
+/// ```
+pub fn create_temp_dir_in<P: AsRef<Path>>(parent: P) -> Result<TmpDir, std::io::Error> {
+    TmpDir::builder().parent(parent).create()
+}
+
+/// Create a temporary directory in the temporary directory of your operating system with a name
+/// starting with `prefix` and ending with `suffix`, which makes leftover directories identifiable
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir_with("mytest-", ".scratch").expect("cannot create temp dir");
+/// let name = tmp_dir.file_name().unwrap().to_str().unwrap();
+/// assert!(name.starts_with("mytest-"));
+/// assert!(name.ends_with(".scratch"));
+/// ```
+pub fn create_temp_dir_with<P: AsRef<OsStr>, S: AsRef<OsStr>>(
+    prefix: P,
+    suffix: S,
+) -> Result<TmpDir, std::io::Error> {
+    TmpDir::builder().prefix(prefix).suffix(suffix).create()
+}
+
+/// Create a temporary directory in the temporary directory of your operating system after
+/// checking that at least `min_free_bytes` are free on its filesystem, to fail early with a clear
+/// error instead of in the middle of a test, see [`TmpDirBuilder::min_free_space`]
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir_checked(1024).expect("cannot create temp dir");
+/// assert!(tmp_dir.is_dir());
+/// let err = tmp_env::create_temp_dir_checked(u64::MAX).unwrap_err();
+/// assert_eq!(err.kind(), std::io::ErrorKind::StorageFull);
+/// ```
+pub fn create_temp_dir_checked(min_free_bytes: u64) -> Result<TmpDir, std::io::Error> {
+    TmpDir::builder().min_free_space(min_free_bytes).create()
+}
+
+/// Create a temporary directory in the temporary directory of your operating system
+/// pre-populated with a recursive copy of the `fixture` directory
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir_from("src").expect("cannot create temp dir");
+/// assert!(tmp_dir.join("lib.rs").is_file());
+/// ```
+pub fn create_temp_dir_from<P: AsRef<Path>>(fixture: P) -> Result<TmpDir, std::io::Error> {
+    TmpDir::builder().create_from(fixture)
+}
+
+impl TmpDir {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            registration: Registration::new(ChangeKind::TmpDir, path.as_os_str()),
+            path,
+            secure_delete: false,
+            read_only: false,
+            retry: RetryPolicy::default(),
+            delete_threads: 1,
+            deferred: false,
+            max_size: None,
+            emergency: None,
+            policy: Policy::default(),
+            trace: Trace::none(),
+        }
+    }
+
+    /// Choose how the failure to delete the temporary directory when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Create a builder to configure where and how the temporary directory is created
+    pub fn builder() -> TmpDirBuilder {
+        TmpDirBuilder::default()
+    }
+
+    /// The canonical form of the temporary directory, with all symlinks resolved (for example
+    /// `/private/var/folders/...` instead of `/var/folders/...` on macOS), to compare it with
+    /// canonicalized paths. Use [`TmpDirBuilder::canonicalize`] to directly get this form.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// assert_eq!(tmp_dir.canonical_path().unwrap(), std::fs::canonicalize(&*tmp_dir).unwrap());
+    /// ```
+    pub fn canonical_path(&self) -> Result<PathBuf, std::io::Error> {
+        self.path.canonicalize()
+    }
+
+    /// Keep the temporary directory on disk instead of deleting it when dropped and return its
+    /// path, for example to inspect the artifacts of a failing test
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// let path = tmp_dir.keep();
+    /// assert!(path.exists());
+    /// # std::fs::remove_dir(path).unwrap();
+    /// ```
+    pub fn keep(mut self) -> PathBuf {
+        // An empty path disarms the deletion when dropped
+        std::mem::take(&mut self.path)
+    }
+
+    /// Opt the temporary directory in or out of the deletion when the process exits without
+    /// dropping it, see [`TmpDirBuilder::cleanup_on_exit`]
+    /// ```
+    /// let mut tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.set_cleanup_on_exit(true);
+    /// tmp_env::emergency_cleanup();
+    /// assert!(!tmp_dir.exists());
+    /// ```
+    pub fn set_cleanup_on_exit(&mut self, enabled: bool) {
+        match self.emergency {
+            None if enabled && !self.path.as_os_str().is_empty() => {
+                self.emergency = Some(emergency::register_dir(self.path.clone()));
+            }
+            Some(id) if !enabled => {
+                self.emergency = None;
+                emergency::unregister(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Same as [`TmpDir::keep`]
+    pub fn into_path(self) -> PathBuf {
+        self.keep()
+    }
+
+    /// Delete the temporary directory right now and report the error if it fails instead of
+    /// panicking when dropped
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// let path = tmp_dir.to_path_buf();
+    /// tmp_dir.close().expect("cannot delete the tmp dir");
+    /// assert!(!path.exists());
+    /// ```
+    pub fn close(self) -> Result<(), std::io::Error> {
+        let res = self.delete();
+        self.keep();
+        res
+    }
+
+    /// Returns `true` if the temporary directory still exists, it can have been removed by the
+    /// temporary files cleaner of your operating system or by the code under test. Dropping a
+    /// temporary directory which doesn't exist anymore is not an error.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// std::fs::remove_dir(&*tmp_dir).expect("cannot remove the tmp dir");
+    /// assert!(!tmp_dir.exists());
+    /// tmp_dir.close().expect("already removed");
+    /// ```
+    pub fn exists(&self) -> bool {
+        std::fs::symlink_metadata(&self.path).is_ok()
+    }
+
+    fn delete(&self) -> Result<(), std::io::Error> {
+        let size = if counters::is_measuring_removed_bytes() {
+            walk::dir_size(&self.path).unwrap_or(0)
+        } else {
+            0
+        };
+        let res = ignore_not_found(self.remove());
+        match &res {
+            Ok(()) => {
+                counters::count(Counter::DirsDeleted, 1);
+                counters::count(Counter::BytesRemoved, size);
+                trace::event!(self.trace, "deleted")
+            }
+            Err(_err) => trace::event!(self.trace, error = %_err, "cannot delete"),
+        }
+        res
+    }
+
+    fn remove(&self) -> Result<(), std::io::Error> {
+        let path = long_path(&self.path);
+        if self.read_only {
+            read_only::set_tree_read_only(&path, false)?;
+        }
+        // The tree is removed even if a file can't be overwritten
+        let overwritten = if self.secure_delete {
+            overwrite_files(&path)
+        } else {
+            Ok(())
+        };
+        let remove = |path: &Path| {
+            if self.delete_threads > 1 {
+                parallel_delete::remove_tree_parallel(path, self.delete_threads)
+            } else {
+                remove_tree(path)
+            }
+        };
+        self.retry
+            .run(|| match remove(&path) {
+                // Files with the read-only attribute (like git objects) can't be deleted on Windows
+                #[cfg(windows)]
+                Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                    read_only::set_tree_read_only(&path, false)?;
+                    remove(&path)
+                }
+                res => res,
+            })
+            .and(overwritten)
+    }
+
+    /// Overwrite the content of every regular file inside the temporary directory with zeros
+    /// and delete it right now, so secrets don't linger on disk. Symlinks are not followed, and
+    /// the files with other hard links are only deleted since their content is shared. The
+    /// directory is deleted even if a file can't be overwritten, the error is returned
+    /// afterwards.
+    /// Note that journaling and copy-on-write filesystems or SSDs can still keep copies of the
+    /// previous content.
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("key.pem", "secret").expect("cannot write file");
+    /// let path = tmp_dir.to_path_buf();
+    /// tmp_dir.shred().expect("cannot shred the tmp dir");
+    /// assert!(!path.exists());
+    /// ```
+    pub fn shred(mut self) -> Result<(), std::io::Error> {
+        self.secure_delete = true;
+        self.close()
+    }
 
-    Ok(TmpDir(tmp_path))
+    /// Move the temporary directory to `dest` instead of deleting it, so it can be used as a
+    /// staging area before publishing its content. The directory is renamed when possible or
+    /// copied when `dest` is on another device, next to `dest` first and then renamed so `dest`
+    /// never appears partially copied. If it fails the temporary directory is deleted.
+    /// ```
+    /// let parent = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// std::fs::write(tmp_dir.join("artifact"), "content").expect("cannot write file");
+    /// let dest = tmp_dir.persist_to(parent.join("published")).expect("cannot persist temp dir");
+    /// assert!(dest.join("artifact").exists());
+    /// ```
+    pub fn persist_to<P: AsRef<Path>>(self, dest: P) -> Result<PathBuf, std::io::Error> {
+        let dest = dest.as_ref();
+        match std::fs::rename(&self.path, dest) {
+            Ok(()) => {
+                self.keep();
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                copy_dir_staged(&self.path, dest)?;
+            }
+            Err(err) => return Err(err),
+        }
+
+        Ok(dest.to_path_buf())
+    }
+}
+
+/// Copy the directory to a sibling of `dest` and rename it, removing the copy if it fails
+fn copy_dir_staged(src: &Path, dest: &Path) -> Result<(), std::io::Error> {
+    let staging_path = dest.with_file_name(random_path(".".as_ref(), ".staging".as_ref(), 10));
+    let res =
+        copy_dir_all(src, &staging_path, false).and_then(|()| std::fs::rename(&staging_path, dest));
+    if res.is_err() {
+        let _ = std::fs::remove_dir_all(&staging_path);
+    }
+    res
+}
+
+/// A builder to configure where and how a [`TmpDir`] is created
+/// ```
+/// let parent = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let tmp_dir = tmp_env::TmpDir::builder()
+///     .parent(&*parent)
+///     .prefix("x")
+///     .rand_len(16)
+///     .create()
+///     .expect("cannot create temp dir");
+/// assert_eq!(tmp_dir.file_name().unwrap().len(), 17);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TmpDirBuilder {
+    parent: Option<PathBuf>,
+    prefix: OsString,
+    suffix: OsString,
+    naming: Naming,
+    max_attempts: usize,
+    follow_symlinks: bool,
+    register: bool,
+    secure_delete: bool,
+    retry: RetryPolicy,
+    delete_threads: usize,
+    deferred: bool,
+    canonicalize: bool,
+    max_size: Option<u64>,
+    min_free_space: Option<u64>,
+    cleanup_on_exit: bool,
+    #[cfg(unix)]
+    mode: u32,
+}
+
+impl Default for TmpDirBuilder {
+    fn default() -> Self {
+        Self {
+            parent: None,
+            prefix: OsString::new(),
+            suffix: OsString::new(),
+            naming: Naming::default(),
+            max_attempts: 16,
+            follow_symlinks: false,
+            register: false,
+            secure_delete: false,
+            retry: RetryPolicy::default(),
+            delete_threads: 1,
+            deferred: false,
+            canonicalize: false,
+            max_size: None,
+            min_free_space: None,
+            cleanup_on_exit: false,
+            #[cfg(unix)]
+            mode: 0o700,
+        }
+    }
+}
+
+impl TmpDirBuilder {
+    /// Create the temporary directory inside this directory instead of the temporary directory
+    /// of your operating system
+    pub fn parent<P: AsRef<Path>>(mut self, parent: P) -> Self {
+        self.parent = Some(parent.as_ref().to_path_buf());
+        self
+    }
+
+    /// Start the name of the temporary directory with this prefix
+    pub fn prefix<P: AsRef<OsStr>>(mut self, prefix: P) -> Self {
+        self.prefix = prefix.as_ref().to_os_string();
+        self
+    }
+
+    /// End the name of the temporary directory with this suffix
+    pub fn suffix<S: AsRef<OsStr>>(mut self, suffix: S) -> Self {
+        self.suffix = suffix.as_ref().to_os_string();
+        self
+    }
+
+    /// Number of random characters in the name of the temporary directory (10 by default)
+    pub fn rand_len(mut self, rand_len: usize) -> Self {
+        self.naming = self.naming.rand_len(rand_len);
+        self
+    }
+
+    /// Generate the random part of the name of the temporary directory with this strategy
+    pub fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Number of names to try when the generated name already exists before giving up (16 by
+    /// default)
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Copy the targets of the symlinks found by [`TmpDirBuilder::create_from`] instead of
+    /// recreating the symlinks themselves
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Write the PID of the current process in a marker file inside the temporary directory so
+    /// it can be removed by [`gc_orphans`] if the process dies without dropping it
+    pub fn register(mut self, register: bool) -> Self {
+        self.register = register;
+        self
+    }
+
+    /// Overwrite the content of the files inside the temporary directory before deleting them,
+    /// see [`TmpDir::shred`]
+    pub fn secure_delete(mut self, secure_delete: bool) -> Self {
+        self.secure_delete = secure_delete;
+        self
+    }
+
+    /// How deleting the temporary directory is retried when it fails, see [`RetryPolicy`]
+    pub fn delete_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Delete the temporary directory with this number of threads (1 by default), to quickly
+    /// delete trees containing lots of files like a `node_modules` directory
+    pub fn parallel_delete(mut self, threads: usize) -> Self {
+        self.delete_threads = threads.max(1);
+        self
+    }
+
+    /// When dropped, only rename the temporary directory and let a background thread delete it
+    /// so dropping never blocks, see [`flush_cleanup`]. [`TmpDir::close`] still deletes it
+    /// right away.
+    pub fn deferred_delete(mut self, deferred: bool) -> Self {
+        self.deferred = deferred;
+        self
+    }
+
+    /// Canonicalize the parent directory so the path of the temporary directory has all its
+    /// symlinks resolved, see [`TmpDir::canonical_path`]. On Windows the canonical form is an
+    /// extended-length path (`\\?\C:\...`).
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Limit the total size of the files inside the temporary directory to this number of bytes,
+    /// checked by [`TmpDir::check_quota`] and [`TmpDir::watch_quota`]
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Check that at least this number of bytes are free on the filesystem of the parent
+    /// directory before creating the temporary directory, failing with an error of kind
+    /// [`StorageFull`](std::io::ErrorKind::StorageFull) otherwise
+    pub fn min_free_space(mut self, bytes: u64) -> Self {
+        self.min_free_space = Some(bytes);
+        self
+    }
+
+    /// Also delete the temporary directory if the process exits without dropping it, see
+    /// [`emergency_cleanup`]
+    pub fn cleanup_on_exit(mut self, cleanup_on_exit: bool) -> Self {
+        self.cleanup_on_exit = cleanup_on_exit;
+        self
+    }
+
+    /// Permissions of the temporary directory (before the umask is applied), only the owner can
+    /// access it by default (`0o700`) like `mkdtemp` does
+    #[cfg(unix)]
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Create the temporary directory
+    pub fn create(&self) -> Result<TmpDir, std::io::Error> {
+        let parent = match &self.parent {
+            Some(parent) => parent.clone(),
+            None => std::env::temp_dir(),
+        };
+        let parent = if self.canonicalize {
+            parent.canonicalize()?
+        } else {
+            parent
+        };
+        if let Some(min_free_space) = self.min_free_space {
+            free_space::check_free_space(&parent, min_free_space)?;
+        }
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut dir_builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            dir_builder.mode(self.mode);
+        }
+        let mut attempts = 0;
+        loop {
+            let tmp_path = parent.join(self.naming.name(&self.prefix, &self.suffix));
+            let tmp_path = long_path(&tmp_path).into_owned();
+            attempts += 1;
+            match dir_builder.create(&tmp_path) {
+                Ok(()) => {
+                    counters::count(Counter::DirsCreated, 1);
+                    dry_run::record_created(&tmp_path, true);
+                    let mut tmp_dir = TmpDir::new(tmp_path);
+                    tmp_dir.trace = trace::span!("tmp_dir", path = ?tmp_dir.path);
+                    trace::event!(tmp_dir.trace, "created");
+                    tmp_dir.secure_delete = self.secure_delete;
+                    tmp_dir.retry = self.retry;
+                    tmp_dir.delete_threads = self.delete_threads;
+                    tmp_dir.deferred = self.deferred;
+                    tmp_dir.max_size = self.max_size;
+                    tmp_dir.set_cleanup_on_exit(self.cleanup_on_exit);
+                    if self.register {
+                        gc::write_owner_marker(&tmp_dir)?;
+                    }
+                    return Ok(tmp_dir);
+                }
+                Err(err)
+                    if err.kind() == std::io::ErrorKind::AlreadyExists
+                        && attempts < self.max_attempts => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Create the temporary directory and recursively copy the content of `fixture` inside,
+    /// preserving permissions and symlinks (see [`TmpDirBuilder::follow_symlinks`])
+    pub fn create_from<P: AsRef<Path>>(&self, fixture: P) -> Result<TmpDir, std::io::Error> {
+        let tmp_dir = self.create()?;
+        copy_dir_content(fixture.as_ref(), &tmp_dir, self.follow_symlinks)?;
+
+        Ok(tmp_dir)
+    }
 }
 
 impl Drop for TmpDir {
     fn drop(&mut self) {
-        std::fs::remove_dir_all(&self.0).expect("cannot delete the tmp dir")
+        if let Some(id) = self.emergency.take() {
+            if !emergency::unregister(id) {
+                // Already deleted by `emergency_cleanup`
+                return;
+            }
+        }
+        if self.path.as_os_str().is_empty() || (self.deferred && deferred::defer(self)) {
+            return;
+        }
+        match self.delete() {
+            Ok(()) => self.registration.restored(),
+            Err(error) => self
+                .policy
+                .handle(Error::restore(&self.path, error), Error::report),
+        }
     }
 }
 
-fn random_path() -> PathBuf {
-    let rand_string: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(char::from)
-        .collect();
+/// Something already removed (for example by the temporary files cleaner of the operating system
+/// or by the code under test) doesn't need to be cleaned up
+pub(crate) fn ignore_not_found(res: Result<(), std::io::Error>) -> Result<(), std::io::Error> {
+    match res {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
 
-    PathBuf::from(rand_string)
+/// Recursively remove a directory without ever following the symlinks inside it, so a symlink
+/// planted in a temporary directory (for example pointing to `$HOME`) only removes the symlink.
+/// If `path` itself has been replaced by a symlink, only this symlink is removed.
+pub(crate) fn remove_tree(path: &Path) -> Result<(), std::io::Error> {
+    if std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return remove_symlink(path);
+    }
+    // Since Rust 1.58.1 it never follows symlinks, even when they are swapped in while deleting
+    std::fs::remove_dir_all(path)
+}
+
+pub(crate) fn remove_symlink(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        // Symlinks to directories are directories on Windows
+        #[cfg(windows)]
+        Err(_) if std::fs::remove_dir(path).is_ok() => Ok(()),
+        res => res,
+    }
+}
+
+/// Overwrite the regular files of the tree with zeros, without following the symlinks (even the
+/// root) and without touching the files with other hard links, whose content is shared with
+/// paths outside the tree. It goes on after an error, which is returned at the end.
+fn overwrite_files(dir: &Path) -> Result<(), std::io::Error> {
+    if !std::fs::symlink_metadata(dir)?.is_dir() {
+        return Ok(());
+    }
+    let mut result = Ok(());
+    for entry in std::fs::read_dir(dir)? {
+        let res = entry.and_then(|entry| {
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                overwrite_files(&entry.path())
+            } else if metadata.is_file() && !has_other_links(&metadata) {
+                overwrite_file(&entry.path(), metadata.len())
+            } else {
+                Ok(())
+            }
+        });
+        result = result.and(res);
+    }
+    result
+}
+
+fn overwrite_file(path: &Path, len: u64) -> Result<(), std::io::Error> {
+    const ZEROS: [u8; 8192] = [0; 8192];
+
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let mut remaining = len;
+    while remaining > 0 {
+        let len = remaining.min(ZEROS.len() as u64) as usize;
+        file.write_all(&ZEROS[..len])?;
+        remaining -= len as u64;
+    }
+    file.sync_all()
+}
+
+#[cfg(unix)]
+fn has_other_links(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+// The number of links is only available on nightly on Windows
+#[cfg(windows)]
+fn has_other_links(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+pub(crate) fn copy_dir_all(
+    src: &Path,
+    dest: &Path,
+    follow_symlinks: bool,
+) -> Result<(), std::io::Error> {
+    std::fs::create_dir(dest)?;
+    copy_dir_content(src, dest, follow_symlinks)?;
+    std::fs::set_permissions(dest, std::fs::metadata(src)?.permissions())
+}
+
+fn copy_dir_content(src: &Path, dest: &Path, follow_symlinks: bool) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let mut file_type = entry.file_type()?;
+        if file_type.is_symlink() && follow_symlinks {
+            file_type = std::fs::metadata(entry.path())?.file_type();
+        }
+        let dest = dest.join(entry.file_name());
+        if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &dest, follow_symlinks)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &dest)?;
+        } else {
+            std::fs::copy(entry.path(), dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dest: &Path) -> Result<(), std::io::Error> {
+    std::os::unix::fs::symlink(std::fs::read_link(src)?, dest)
+}
+
+#[cfg(windows)]
+fn copy_symlink(src: &Path, dest: &Path) -> Result<(), std::io::Error> {
+    let target = std::fs::read_link(src)?;
+    if std::fs::metadata(src)?.is_dir() {
+        std::os::windows::fs::symlink_dir(target, dest)
+    } else {
+        std::os::windows::fs::symlink_file(target, dest)
+    }
+}
+
+/// Convert a path too long for the Win32 APIs (`MAX_PATH`) to its extended-length form
+/// (`\\?\C:\...` or `\\?\UNC\server\share\...`) so deep temporary trees can still be
+/// created and deleted. Other paths (and all the paths on other platforms) are left untouched.
+#[cfg(windows)]
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::path::{Component, Prefix};
+
+    // Directories must leave room for a 8.3 file name
+    if path.as_os_str().len() < 248 {
+        return Cow::Borrowed(path);
+    }
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute,
+        Err(_) => return Cow::Borrowed(path),
+    };
+    let mut extended = match absolute.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(disk) => PathBuf::from(format!(r"\\?\{}:\", disk as char)),
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                PathBuf::from(extended)
+            }
+            // Already in extended-length form or a device path
+            _ => return Cow::Owned(absolute),
+        },
+        _ => return Cow::Owned(absolute),
+    };
+    // Skip the prefix and the root
+    extended.extend(absolute.components().skip(2));
+
+    Cow::Owned(extended)
+}
+
+#[cfg(not(windows))]
+pub(crate) fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+pub(crate) fn random_path(prefix: &OsStr, suffix: &OsStr, rand_len: usize) -> PathBuf {
+    Naming::new().rand_len(rand_len).name(prefix, suffix)
 }
 
 #[cfg(test)]
@@ -174,7 +1785,7 @@ mod tests {
         }
         assert!(std::env::var("TEST_TMP_ENV").is_err());
         // Because guard is dropped
-        set_var("TEST_TMP_ENV_DROPPED", "myvaluedropped");
+        drop(set_var("TEST_TMP_ENV_DROPPED", "myvaluedropped"));
         assert!(std::env::var("TEST_TMP_ENV_DROPPED").is_err());
     }
 
@@ -215,6 +1826,7 @@ mod tests {
 
     #[test]
     fn test_current_dir() {
+        let _lock = lock_current_dir();
         {
             let _tmp_current_dir = set_current_dir("src").expect("should set the new current_dir");
             let current_dir = std::env::current_dir().expect("cannot get current dir from std env");
@@ -223,19 +1835,598 @@ mod tests {
         let current_dir = std::env::current_dir().expect("cannot get current dir from std env");
         assert!(!current_dir.ends_with("src"));
         // Because guard is dropped
-        set_current_dir("target").expect("should set the new current_dir");
+        drop(set_current_dir("target").expect("should set the new current_dir"));
         assert!(!current_dir.ends_with("target"));
     }
 
+    #[test]
+    fn test_current_dir_fallback() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let removed_dir = tmp_dir.join("removed");
+        std::fs::create_dir(&removed_dir).expect("cannot create dir");
+        {
+            let _tmp_current_dir =
+                set_current_dir(&removed_dir).expect("should set the new current_dir");
+            let tmp_current_dir = set_current_dir(&*tmp_dir)
+                .expect("should set the new current_dir")
+                .fallback(Fallback::Path(tmp_dir.to_path_buf()));
+            std::fs::remove_dir(&removed_dir).expect("cannot remove dir");
+            // Must not panic even if the original directory doesn't exist anymore
+            drop(tmp_current_dir);
+            assert_eq!(std::env::current_dir().unwrap(), *tmp_dir);
+        }
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+    }
+
+    #[test]
+    fn test_current_dir_fallback_nearest_ancestor() {
+        let _lock = lock_current_dir();
+        let _hooks = lock_hooks();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let removed_dir = tmp_dir.join("removed").join("nested");
+        std::fs::create_dir_all(&removed_dir).expect("cannot create dir");
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let hook_events = events.clone();
+        let hook_original = removed_dir.clone();
+        on_dir_fallback(move |event| {
+            if event.original == hook_original {
+                hook_events
+                    .lock()
+                    .unwrap()
+                    .push((event.error.kind(), event.fallback.clone()));
+            }
+        });
+        {
+            let _tmp_current_dir =
+                set_current_dir(&removed_dir).expect("should set the new current_dir");
+            let tmp_current_dir = set_current_dir(&*tmp_dir)
+                .expect("should set the new current_dir")
+                .fallback(Fallback::NearestAncestor);
+            std::fs::remove_dir_all(tmp_dir.join("removed")).expect("cannot remove dir");
+            drop(tmp_current_dir);
+            assert_eq!(std::env::current_dir().unwrap(), *tmp_dir);
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(std::io::ErrorKind::NotFound, Some(tmp_dir.to_path_buf()))]
+        );
+    }
+
+    #[test]
+    fn test_current_dir_restore_now() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+        let tmp_current_dir = set_current_dir("src").expect("should set the new current_dir");
+        assert_eq!(tmp_current_dir.original(), original_dir);
+        assert_eq!(tmp_current_dir.current(), original_dir.join("src"));
+        tmp_current_dir
+            .restore_now()
+            .expect("should go back to the original dir");
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+    }
+
+    #[test]
+    fn test_current_dir_all() {
+        let _lock = lock_current_dir();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let nested_dir = tmp_dir.join("a").join("b").join("c");
+        {
+            let tmp_current_dir =
+                set_current_dir_all(&nested_dir).expect("should set the new current_dir");
+            assert_eq!(tmp_current_dir.current(), nested_dir);
+        }
+        // Created directories are kept by default
+        assert!(nested_dir.exists());
+        let nested_dir = nested_dir.join("d").join("e");
+        {
+            let _tmp_current_dir = set_current_dir_all(&nested_dir)
+                .expect("should set the new current_dir")
+                .remove_created_dirs(true);
+            assert_eq!(std::env::current_dir().unwrap(), nested_dir);
+        }
+        assert!(!nested_dir.parent().unwrap().exists());
+        assert!(tmp_dir.join("a").join("b").join("c").exists());
+    }
+
+    #[test]
+    fn test_current_dir_locked() {
+        let tmp_current_dir =
+            set_current_dir_locked("src").expect("should set the new current_dir");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let _lock = lock_current_dir();
+            sender.send(std::env::current_dir().unwrap()).unwrap();
+        });
+        // The other thread waits for the lock to be released
+        assert!(receiver
+            .recv_timeout(std::time::Duration::from_millis(100))
+            .is_err());
+        let original_dir = tmp_current_dir.original().to_path_buf();
+        drop(tmp_current_dir);
+        assert_eq!(receiver.recv().unwrap(), original_dir);
+        handle.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_current_dir_canonicalize() {
+        let _lock = lock_current_dir();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let target = tmp_dir.join("target");
+        std::fs::create_dir(&target).expect("cannot create dir");
+        let link = tmp_dir.join("link");
+        std::os::unix::fs::symlink(&target, &link).expect("cannot create symlink");
+        let tmp_current_dir = CurrentDir::builder()
+            .canonicalize(true)
+            .set(&link)
+            .expect("should set the new current_dir");
+        assert_eq!(tmp_current_dir.current(), target.canonicalize().unwrap());
+        assert_eq!(
+            tmp_current_dir.canonical_original().unwrap(),
+            tmp_current_dir.original()
+        );
+    }
+
+    #[test]
+    fn test_dir_stack() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+        {
+            let mut dir_stack = DirStack::new();
+            assert!(dir_stack.pop().is_err());
+            dir_stack.push("src").expect("should push src");
+            dir_stack.push("..").expect("should push parent dir");
+            dir_stack.push("target").expect("should push target");
+            assert_eq!(dir_stack.len(), 3);
+            assert!(std::env::current_dir().unwrap().ends_with("target"));
+            assert_eq!(dir_stack.pop().unwrap(), original_dir);
+            assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        }
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+    }
+
     #[test]
     fn test_tmp_dir() {
         #[allow(unused_assignments)]
         let mut tmp_dir_created: Option<PathBuf> = None;
         {
             let tmp_dir = create_temp_dir().expect("cannot create temp dir");
-            tmp_dir_created = Some(tmp_dir.0.clone());
+            tmp_dir_created = Some(tmp_dir.path.clone());
+            assert!(std::fs::metadata(&*tmp_dir).is_ok());
+        }
+        assert!(std::fs::metadata(tmp_dir_created.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_tmp_dir_in() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        #[allow(unused_assignments)]
+        let mut tmp_dir_created: Option<PathBuf> = None;
+        {
+            let tmp_dir = create_temp_dir_in(&*parent).expect("cannot create temp dir");
+            tmp_dir_created = Some(tmp_dir.path.clone());
+            assert!(tmp_dir.starts_with(&*parent));
             assert!(std::fs::metadata(&*tmp_dir).is_ok());
         }
         assert!(std::fs::metadata(tmp_dir_created.unwrap()).is_err());
+        assert!(create_temp_dir_in(parent.join("does_not_exist")).is_err());
+    }
+
+    #[test]
+    fn test_tmp_dir_with() {
+        let tmp_dir = create_temp_dir_with("mytest-", ".scratch").expect("cannot create temp dir");
+        let name = tmp_dir.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("mytest-"));
+        assert!(name.ends_with(".scratch"));
+        assert_eq!(name.len(), "mytest-".len() + 10 + ".scratch".len());
+        assert!(tmp_dir.is_dir());
+    }
+
+    #[test]
+    fn test_tmp_dir_builder() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        let builder = TmpDir::builder()
+            .parent(&*parent)
+            .prefix("pre")
+            .suffix("suf")
+            .rand_len(16);
+        #[cfg(unix)]
+        let builder = builder.mode(0o700);
+        let tmp_dir = builder.create().expect("cannot create temp dir");
+        assert_eq!(tmp_dir.parent(), Some(parent.as_path()));
+        let name = tmp_dir.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("pre") && name.ends_with("suf"));
+        assert_eq!(name.len(), 3 + 16 + 3);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&*tmp_dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[test]
+    fn test_tmp_dir_keep() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        std::fs::write(tmp_dir.join("artifact"), "content").expect("cannot write file");
+        let path = tmp_dir.keep();
+        assert!(path.join("artifact").exists());
+        std::fs::remove_dir_all(&path).expect("cannot delete the tmp dir");
+
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let path = tmp_dir.into_path();
+        assert!(path.exists());
+        std::fs::remove_dir_all(&path).expect("cannot delete the tmp dir");
+    }
+
+    #[test]
+    fn test_tmp_dir_persist_to() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        let tmp_dir = create_temp_dir_in(&*parent).expect("cannot create temp dir");
+        let tmp_path = tmp_dir.to_path_buf();
+        std::fs::create_dir(tmp_dir.join("sub")).expect("cannot create dir");
+        std::fs::write(tmp_dir.join("sub").join("artifact"), "content").expect("cannot write file");
+        let dest = tmp_dir
+            .persist_to(parent.join("published"))
+            .expect("cannot persist temp dir");
+        assert_eq!(dest, parent.join("published"));
+        assert!(!tmp_path.exists());
+        assert_eq!(
+            std::fs::read_to_string(dest.join("sub").join("artifact")).unwrap(),
+            "content"
+        );
+
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let tmp_path = tmp_dir.to_path_buf();
+        assert!(tmp_dir
+            .persist_to(parent.join("missing").join("dest"))
+            .is_err());
+        assert!(!tmp_path.exists());
+    }
+
+    #[test]
+    fn test_copy_dir_staged() {
+        let src = create_temp_dir().expect("cannot create temp dir");
+        std::fs::write(src.join("artifact"), "content").expect("cannot write file");
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        copy_dir_staged(&src, &parent.join("copy")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(parent.join("copy").join("artifact")).unwrap(),
+            "content"
+        );
+
+        // The copy succeeds but the rename fails, the staging copy doesn't stay behind
+        std::fs::write(parent.join("busy"), "").expect("cannot write file");
+        assert!(copy_dir_staged(&src, &parent.join("busy")).is_err());
+        let mut names: Vec<_> = parent
+            .read_dir()
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["busy", "copy"]);
+    }
+
+    #[test]
+    fn test_copy_dir_all() {
+        let src = create_temp_dir().expect("cannot create temp dir");
+        std::fs::create_dir(src.join("sub")).expect("cannot create dir");
+        std::fs::write(src.join("sub").join("file"), "content").expect("cannot write file");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("sub/file", src.join("link")).expect("cannot create symlink");
+        let dest = create_temp_dir().expect("cannot create temp dir");
+        copy_dir_all(&src, &dest.join("copy"), false).expect("cannot copy dir");
+        assert_eq!(
+            std::fs::read_to_string(dest.join("copy").join("sub").join("file")).unwrap(),
+            "content"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            std::fs::read_link(dest.join("copy").join("link")).unwrap(),
+            Path::new("sub/file")
+        );
+    }
+
+    #[test]
+    fn test_tmp_dir_close() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let path = tmp_dir.to_path_buf();
+        std::fs::write(tmp_dir.join("file"), "content").expect("cannot write file");
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(!path.exists());
+
+        let mut tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let path = tmp_dir.to_path_buf();
+        *tmp_dir = path.join("does_not_exist");
+        assert!(!tmp_dir.exists());
+        // Already removed, nothing to do
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        std::fs::remove_dir(path).expect("cannot delete the tmp dir");
+    }
+
+    #[test]
+    fn test_tmp_dir_removed_externally() {
+        let _hooks = lock_hooks();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let hook_events = events.clone();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let hook_path = tmp_dir.to_path_buf();
+        on_cleanup_error(move |event| {
+            if event.path == hook_path {
+                hook_events.lock().unwrap().push(event.error.kind());
+            }
+        });
+        tmp_dir.write("sub/file", "content").unwrap();
+        assert!(tmp_dir.exists());
+        std::fs::remove_dir_all(&*tmp_dir).unwrap();
+        assert!(!tmp_dir.exists());
+        drop(tmp_dir);
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tmp_dir_cleanup_error() {
+        let _hooks = lock_hooks();
+        let file = create_temp_file().expect("cannot create temp file");
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let hook_events = events.clone();
+        let hook_path = file.to_path_buf();
+        on_cleanup_error(move |event| {
+            if event.path == hook_path {
+                hook_events.lock().unwrap().push(event.path.clone());
+            }
+        });
+        let mut tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let path = tmp_dir.to_path_buf();
+        // A file can't be deleted as a directory, dropping must not panic
+        *tmp_dir = file.to_path_buf();
+        drop(tmp_dir);
+        assert_eq!(*events.lock().unwrap(), vec![file.to_path_buf()]);
+        std::fs::remove_dir(path).expect("cannot delete the tmp dir");
+    }
+
+    #[test]
+    fn test_tmp_dir_name_collision() {
+        let parent = create_temp_dir().expect("cannot create temp dir");
+        std::fs::create_dir(parent.join("taken")).expect("cannot create dir");
+        let err = TmpDir::builder()
+            .parent(&*parent)
+            .prefix("taken")
+            .rand_len(0)
+            .max_attempts(3)
+            .create()
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        // Only one single character name is still available (on case sensitive filesystems)
+        if cfg!(any(target_os = "macos", windows)) {
+            return;
+        }
+        let names: Vec<char> = ('a'..='z').chain('A'..='Z').chain('0'..='9').collect();
+        for name in &names[1..] {
+            std::fs::create_dir(parent.join(name.to_string())).expect("cannot create dir");
+        }
+        let tmp_dir = TmpDir::builder()
+            .parent(&*parent)
+            .rand_len(1)
+            .max_attempts(10_000)
+            .create()
+            .expect("cannot create temp dir");
+        assert!(tmp_dir.ends_with("a"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tmp_dir_private_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let mode = std::fs::metadata(&*tmp_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let tmp_dir = TmpDir::builder()
+            .mode(0o750)
+            .create()
+            .expect("cannot create temp dir");
+        let mode = std::fs::metadata(&*tmp_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o700, 0o700);
+        assert_eq!(mode & 0o007, 0);
+    }
+
+    #[test]
+    fn test_tmp_dir_from() {
+        let fixture = create_temp_dir().expect("cannot create temp dir");
+        std::fs::create_dir(fixture.join("sub")).expect("cannot create dir");
+        std::fs::write(fixture.join("sub").join("file"), "content").expect("cannot write file");
+        let tmp_dir = create_temp_dir_from(&*fixture).expect("cannot create temp dir");
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.join("sub").join("file")).unwrap(),
+            "content"
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(
+                fixture.join("sub").join("file"),
+                std::fs::Permissions::from_mode(0o751),
+            )
+            .expect("cannot set permissions");
+            std::os::unix::fs::symlink("sub", fixture.join("link")).expect("cannot create symlink");
+            let tmp_dir = create_temp_dir_from(&*fixture).expect("cannot create temp dir");
+            let mode = std::fs::metadata(tmp_dir.join("sub").join("file"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o751);
+            assert_eq!(
+                std::fs::read_link(tmp_dir.join("link")).unwrap(),
+                Path::new("sub")
+            );
+
+            let tmp_dir = TmpDir::builder()
+                .follow_symlinks(true)
+                .create_from(&*fixture)
+                .expect("cannot create temp dir");
+            assert!(std::fs::symlink_metadata(tmp_dir.join("link"))
+                .unwrap()
+                .is_dir());
+            assert!(tmp_dir.join("link").join("file").is_file());
+        }
+    }
+
+    #[test]
+    fn test_tmp_dir_secure_delete() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let secret = tmp_dir
+            .write("sub/secret", vec![42; 10_000])
+            .expect("cannot write file");
+        overwrite_files(&tmp_dir).expect("cannot overwrite files");
+        assert_eq!(std::fs::read(&secret).unwrap(), vec![0; 10_000]);
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.shred().expect("cannot shred the tmp dir");
+        assert!(!path.exists());
+
+        #[allow(unused_assignments)]
+        let mut tmp_dir_created: Option<PathBuf> = None;
+        {
+            let tmp_dir = TmpDir::builder()
+                .secure_delete(true)
+                .create()
+                .expect("cannot create temp dir");
+            tmp_dir_created = Some(tmp_dir.path.clone());
+            tmp_dir
+                .write("secret", "secret")
+                .expect("cannot write file");
+        }
+        assert!(std::fs::metadata(tmp_dir_created.unwrap()).is_err());
+
+        // The content shared with paths outside the directory is kept
+        #[cfg(unix)]
+        {
+            let outside = create_temp_dir().expect("cannot create temp dir");
+            let shared = outside
+                .write("shared", "shared")
+                .expect("cannot write file");
+            let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+            std::fs::hard_link(&shared, tmp_dir.join("hard_link")).unwrap();
+            std::os::unix::fs::symlink(&shared, tmp_dir.join("symlink")).unwrap();
+            let root_link = tmp_dir.join("root_link");
+            std::os::unix::fs::symlink(&*outside, &root_link).unwrap();
+            overwrite_files(&root_link).expect("cannot overwrite files");
+            tmp_dir.shred().expect("cannot shred the tmp dir");
+            assert_eq!(std::fs::read(&shared).unwrap(), b"shared");
+        }
+    }
+
+    #[test]
+    fn test_tmp_dir_read_only_files() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let object = tmp_dir
+            .write(".git/objects/ab/cdef", "object")
+            .expect("cannot write file");
+        let mut permissions = std::fs::metadata(&object).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&object, permissions).unwrap();
+        let path = tmp_dir.to_path_buf();
+        tmp_dir
+            .close()
+            .expect("cannot delete tmp dir with read-only files");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_tmp_dir_long_paths() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let deep: PathBuf = std::iter::repeat_n("a_rather_long_directory_name", 12).collect();
+        let file = tmp_dir.write(deep.join("file.txt"), "content").unwrap();
+        assert!(file.as_os_str().len() > 300);
+        assert_eq!(
+            tmp_dir.read_to_string(deep.join("file.txt")).unwrap(),
+            "content"
+        );
+        let nested = TmpDir::builder()
+            .parent(file.parent().unwrap())
+            .create()
+            .expect("cannot create temp dir");
+        drop(nested);
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(!path.exists());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path() {
+        let short = Path::new(r"C:\tmp\short");
+        assert_eq!(long_path(short), short);
+        let long_name = "a".repeat(250);
+        assert_eq!(
+            long_path(&Path::new(r"C:\tmp").join(&long_name)),
+            Path::new(r"\\?\C:\tmp").join(&long_name)
+        );
+        assert_eq!(
+            long_path(&Path::new(r"\\server\share\tmp").join(&long_name)),
+            Path::new(r"\\?\UNC\server\share\tmp").join(&long_name)
+        );
+        let verbatim = Path::new(r"\\?\C:\tmp").join(&long_name);
+        assert_eq!(long_path(&verbatim), verbatim);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tmp_dir_canonicalize() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let real = tmp_dir.create_dir("real").unwrap();
+        let link = tmp_dir.join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        let linked = TmpDir::builder()
+            .parent(&link)
+            .create()
+            .expect("cannot create temp dir");
+        assert!(linked.starts_with(&link));
+        let canonical = linked.canonical_path().unwrap();
+        assert_eq!(
+            canonical,
+            real.canonicalize()
+                .unwrap()
+                .join(linked.file_name().unwrap())
+        );
+
+        let canonicalized = TmpDir::builder()
+            .parent(&link)
+            .canonicalize(true)
+            .create()
+            .expect("cannot create temp dir");
+        assert!(canonicalized.starts_with(real.canonicalize().unwrap()));
+        assert_eq!(canonicalized.canonical_path().unwrap(), *canonicalized);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tmp_dir_symlinks_not_followed() {
+        let outside = create_temp_dir().expect("cannot create temp dir");
+        let precious = outside.write("sub/precious.txt", "precious").unwrap();
+
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        std::os::unix::fs::symlink(&*outside, tmp_dir.join("home")).unwrap();
+        std::os::unix::fs::symlink(&precious, tmp_dir.join("file")).unwrap();
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(&precious).unwrap(), "precious");
+
+        // The tmp dir itself replaced by a symlink
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        std::fs::remove_dir(&*tmp_dir).unwrap();
+        std::os::unix::fs::symlink(&*outside, &*tmp_dir).unwrap();
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(std::fs::symlink_metadata(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&precious).unwrap(), "precious");
     }
 }
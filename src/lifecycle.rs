@@ -0,0 +1,177 @@
+use std::{
+    ffi::OsString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+use crate::ChangeKind;
+
+/// Describe a temporary change applied or restored, see [`on_apply`] and [`on_restore`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct LifecycleEvent {
+    /// What the guard changed
+    pub kind: ChangeKind,
+    /// The name of the environment variable, or the path of the directory or file. Empty for the
+    /// current directory with [`redact_values`], since it is the new value.
+    pub target: OsString,
+    /// The value replaced by the change: the previous value of the environment variable or the
+    /// previous current directory, `None` if there was none or with [`redact_values`]
+    pub old: Option<OsString>,
+    /// The value set by the change: the new value of the environment variable or the new current
+    /// directory, `None` if it is removed or with [`redact_values`]
+    pub new: Option<OsString>,
+    /// Whether `old` and `new` have been hidden by [`redact_values`]
+    pub redacted: bool,
+}
+
+type LifecycleHook = Box<dyn Fn(&LifecycleEvent) + Send + Sync>;
+
+static APPLY_HOOK: RwLock<Option<LifecycleHook>> = RwLock::new(None);
+static RESTORE_HOOK: RwLock<Option<LifecycleHook>> = RwLock::new(None);
+static REDACT: AtomicBool = AtomicBool::new(false);
+
+/// Register a hook called every time a temporary change (environment variable, current directory,
+/// temporary directory or file) is applied, replacing the previous one, for example to write an
+/// audit log or to report the changes made by each test
+/// ```
+/// tmp_env::on_apply(|event| eprintln!("applied {:?} {:?}", event.kind, event.target));
+/// let _tmp_env = tmp_env::set_var("TEST_ON_APPLY", "value");
+/// ```
+pub fn on_apply<F: Fn(&LifecycleEvent) + Send + Sync + 'static>(hook: F) {
+    *APPLY_HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(hook));
+}
+
+/// Register a hook called every time a temporary change is undone, replacing the previous one.
+/// `old` is then the value undone and `new` the value restored. Nothing is reported for the
+/// changes kept or which can't be undone.
+/// ```
+/// tmp_env::on_restore(|event| eprintln!("restored {:?} to {:?}", event.target, event.new));
+/// drop(tmp_env::set_var("TEST_ON_RESTORE", "value"));
+/// ```
+pub fn on_restore<F: Fn(&LifecycleEvent) + Send + Sync + 'static>(hook: F) {
+    *RESTORE_HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(hook));
+}
+
+/// Hide the values of the environment variables and current directories from the events passed
+/// to [`on_apply`] and [`on_restore`], when they may contain secrets, including the target of the
+/// current directory changes
+/// ```
+/// tmp_env::redact_values(true);
+/// ```
+pub fn redact_values(redact: bool) {
+    REDACT.store(redact, Ordering::Relaxed);
+}
+
+/// A change applied or restored, only kept when a hook is registered
+#[derive(Debug, Default)]
+pub(crate) struct Lifecycle {
+    event: Option<LifecycleEvent>,
+}
+
+impl Lifecycle {
+    /// Report the change applied
+    pub(crate) fn apply(
+        kind: ChangeKind,
+        target: OsString,
+        old: Option<OsString>,
+        new: Option<OsString>,
+    ) -> Self {
+        let is_hooked = |hook: &RwLock<Option<LifecycleHook>>| {
+            hook.read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_some()
+        };
+        if !is_hooked(&APPLY_HOOK) && !is_hooked(&RESTORE_HOOK) {
+            return Self { event: None };
+        }
+        let redacted = REDACT.load(Ordering::Relaxed) && (old.is_some() || new.is_some());
+        let event = LifecycleEvent {
+            kind,
+            target: match kind {
+                ChangeKind::CurrentDir if redacted => OsString::new(),
+                _ => target,
+            },
+            old: old.filter(|_| !redacted),
+            new: new.filter(|_| !redacted),
+            redacted,
+        };
+        if let Some(hook) = &*APPLY_HOOK.read().unwrap_or_else(PoisonError::into_inner) {
+            hook(&event);
+        }
+        Self { event: Some(event) }
+    }
+
+    /// Report the change undone, only once
+    pub(crate) fn restore(&mut self) {
+        if let Some(mut event) = self.event.take() {
+            std::mem::swap(&mut event.old, &mut event.new);
+            if let Some(hook) = &*RESTORE_HOOK.read().unwrap_or_else(PoisonError::into_inner) {
+                hook(&event);
+            }
+        }
+    }
+
+    /// The change is kept, nothing is reported
+    pub(crate) fn forget(&mut self) {
+        self.event = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        create_temp_dir, lock_current_dir, lock_hooks, set_current_dir, set_var, TempGuard,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_lifecycle() {
+        let _lock = lock_current_dir();
+        let _hooks = lock_hooks();
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let events = Arc::clone(&events);
+            move |event: &LifecycleEvent| {
+                if event.kind == ChangeKind::CurrentDir && event.redacted {
+                    let values = (event.old.clone(), event.new.clone(), true);
+                    events.lock().unwrap().push((name, values));
+                    assert!(event.target.is_empty());
+                } else if event.target == "TEST_LIFECYCLE_VAR" {
+                    let values = (event.old.clone(), event.new.clone(), event.redacted);
+                    events.lock().unwrap().push((name, values));
+                }
+            }
+        };
+        on_apply(record("apply"));
+        on_restore(record("restore"));
+
+        std::env::set_var("TEST_LIFECYCLE_VAR", "previous");
+        drop(set_var("TEST_LIFECYCLE_VAR", "value"));
+        redact_values(true);
+        drop(set_var("TEST_LIFECYCLE_VAR", "secret"));
+        drop(set_current_dir(&*tmp_dir).expect("cannot set current dir"));
+        redact_values(false);
+        let mut kept = set_var("TEST_LIFECYCLE_VAR", "kept");
+        kept.keep();
+        std::env::remove_var("TEST_LIFECYCLE_VAR");
+
+        let value = |value: &str| Some(OsString::from(value));
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ("apply", (value("previous"), value("value"), false)),
+                ("restore", (value("value"), value("previous"), false)),
+                ("apply", (None, None, true)),
+                ("restore", (None, None, true)),
+                ("apply", (None, None, true)),
+                ("restore", (None, None, true)),
+                ("apply", (value("previous"), value("kept"), false)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{ignore_not_found, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we remove the symlink created before
+/// end of the current scope.
+#[must_use = "the symlink is removed as soon as the guard is dropped"]
+pub struct TmpSymlink {
+    path: PathBuf,
+    policy: Policy,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    is_dir: bool,
+}
+
+impl Deref for TmpSymlink {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpSymlink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Create a symlink at `link_path` pointing to `target`. It returns a datastructure to keep the
+/// symlink, when dropped the symlink is removed (but not its target). On Windows a directory
+/// symlink is created if `target` is a directory.
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// std::fs::write(tmp_dir.join("target"), "content").expect("cannot write file");
+/// {
+///     let link = tmp_env::create_temp_symlink(tmp_dir.join("target"), tmp_dir.join("link"))
+///         .expect("cannot create symlink");
+///     assert_eq!(std::fs::read_to_string(&*link).unwrap(), "content");
+/// }
+/// // The symlink is now removed
+/// assert!(!tmp_dir.join("link").exists());
+/// assert!(tmp_dir.join("target").exists());
+/// ```
+pub fn create_temp_symlink<T: AsRef<Path>, L: AsRef<Path>>(
+    target: T,
+    link_path: L,
+) -> Result<TmpSymlink, std::io::Error> {
+    let target = target.as_ref();
+    let link_path = link_path.as_ref();
+    // A relative target is relative to the directory of the link
+    let is_dir = link_path
+        .parent()
+        .map(|parent| parent.join(target))
+        .unwrap_or_else(|| target.to_path_buf())
+        .is_dir();
+    symlink(target, link_path, is_dir)?;
+
+    Ok(TmpSymlink {
+        path: link_path.to_path_buf(),
+        is_dir,
+        policy: Policy::default(),
+    })
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link_path: &Path, _is_dir: bool) -> Result<(), std::io::Error> {
+    std::os::unix::fs::symlink(target, link_path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link_path: &Path, is_dir: bool) -> Result<(), std::io::Error> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link_path)
+    } else {
+        std::os::windows::fs::symlink_file(target, link_path)
+    }
+}
+
+impl TmpSymlink {
+    /// Choose how the failure to remove the symlink when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[cfg(windows)]
+    fn remove(&self) -> Result<(), std::io::Error> {
+        if self.is_dir {
+            std::fs::remove_dir(&self.path)
+        } else {
+            std::fs::remove_file(&self.path)
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn remove(&self) -> Result<(), std::io::Error> {
+        std::fs::remove_file(&self.path)
+    }
+}
+
+impl TempGuard for TmpSymlink {
+    fn keep(&mut self) {
+        // An empty path disarms the removal when dropped
+        std::mem::take(&mut self.path);
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let res = ignore_not_found(self.remove());
+        let path = std::mem::take(&mut self.path);
+        res.map_err(|err| Error::restore(path, err))
+    }
+
+    fn is_active(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+}
+
+impl Drop for TmpSymlink {
+    fn drop(&mut self) {
+        if let Err(error) = self.restore_now() {
+            self.policy.handle(error, Error::report);
+        }
+    }
+}
+
+/// A helper datastructure for ensuring that we remove the hard link created before
+/// end of the current scope.
+#[must_use = "the hard link is removed as soon as the guard is dropped"]
+pub struct TmpHardLink {
+    path: PathBuf,
+    policy: Policy,
+}
+
+impl Deref for TmpHardLink {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpHardLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Create a hard link at `link_path` to the existing file `src`. It returns a datastructure to
+/// keep the link, when dropped the link is removed (but not `src`, which keeps the content).
+/// ```
+/// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+/// let src = tmp_dir.write("src", "content").expect("cannot write file");
+/// {
+///     let link = tmp_env::create_temp_hardlink(&src, tmp_dir.join("link"))
+///         .expect("cannot create hard link");
+///     assert_eq!(std::fs::read_to_string(&*link).unwrap(), "content");
+/// }
+/// // The hard link is now removed
+/// assert!(!tmp_dir.join("link").exists());
+/// assert!(src.exists());
+/// ```
+pub fn create_temp_hardlink<S: AsRef<Path>, L: AsRef<Path>>(
+    src: S,
+    link_path: L,
+) -> Result<TmpHardLink, std::io::Error> {
+    std::fs::hard_link(src, link_path.as_ref())?;
+
+    Ok(TmpHardLink {
+        path: link_path.as_ref().to_path_buf(),
+        policy: Policy::default(),
+    })
+}
+
+impl TmpHardLink {
+    /// Choose how the failure to remove the hard link when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpHardLink {
+    fn keep(&mut self) {
+        // An empty path disarms the removal when dropped
+        std::mem::take(&mut self.path);
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let res = ignore_not_found(std::fs::remove_file(&self.path));
+        let path = std::mem::take(&mut self.path);
+        res.map_err(|err| Error::restore(path, err))
+    }
+
+    fn is_active(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+}
+
+impl Drop for TmpHardLink {
+    fn drop(&mut self) {
+        if let Err(error) = self.restore_now() {
+            self.policy.handle(error, Error::report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmp_symlink() {
+        let tmp_dir = crate::create_temp_dir().expect("cannot create temp dir");
+        std::fs::create_dir(tmp_dir.join("target_dir")).expect("cannot create dir");
+        std::fs::write(tmp_dir.join("target_dir").join("file"), "content")
+            .expect("cannot write file");
+        {
+            let link = create_temp_symlink("target_dir", tmp_dir.join("link"))
+                .expect("cannot create symlink");
+            assert!(std::fs::symlink_metadata(&*link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(
+                std::fs::read_to_string(link.join("file")).unwrap(),
+                "content"
+            );
+            assert!(create_temp_symlink("target_dir", tmp_dir.join("link")).is_err());
+        }
+        assert!(std::fs::symlink_metadata(tmp_dir.join("link")).is_err());
+        assert!(tmp_dir.join("target_dir").join("file").exists());
+    }
+    #[test]
+    fn test_tmp_hardlink() {
+        let tmp_dir = crate::create_temp_dir().expect("cannot create temp dir");
+        let src = tmp_dir.write("src", "content").unwrap();
+        {
+            let link =
+                create_temp_hardlink(&src, tmp_dir.join("link")).expect("cannot create hard link");
+            std::fs::write(&*link, "changed").unwrap();
+            assert_eq!(std::fs::read_to_string(&src).unwrap(), "changed");
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                assert_eq!(std::fs::metadata(&src).unwrap().nlink(), 2);
+                assert_eq!(
+                    std::fs::metadata(&src).unwrap().ino(),
+                    std::fs::metadata(&*link).unwrap().ino()
+                );
+            }
+            assert!(create_temp_hardlink(&src, tmp_dir.join("link")).is_err());
+            assert!(create_temp_hardlink(tmp_dir.join("missing"), tmp_dir.join("other")).is_err());
+        }
+        assert!(!tmp_dir.join("link").exists());
+        assert_eq!(std::fs::read_to_string(&src).unwrap(), "changed");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(std::fs::metadata(&src).unwrap().nlink(), 1);
+        }
+    }
+}
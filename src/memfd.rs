@@ -0,0 +1,43 @@
+use std::{ffi::CString, fs::File, os::unix::io::FromRawFd};
+
+/// Create an anonymous temporary file living purely in memory (with `memfd_create`). There is no
+/// path to clean up, the memory is released once the returned file is closed.
+/// ```
+/// use std::io::{Read, Seek, SeekFrom, Write};
+///
+/// let mut file = tmp_env::create_temp_memfd().expect("cannot create memfd");
+/// file.write_all(b"content").unwrap();
+/// file.seek(SeekFrom::Start(0)).unwrap();
+/// let mut content = String::new();
+/// file.read_to_string(&mut content).unwrap();
+/// assert_eq!(content, "content");
+/// ```
+pub fn create_temp_memfd() -> Result<File, std::io::Error> {
+    let name = CString::new("tmp_env").expect("the name doesn't contain any NUL byte");
+    // SAFETY: `name` is a valid NUL terminated string living until the end of the call
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: `fd` has just been created and is owned by nobody else
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_memfd() {
+        let mut file = create_temp_memfd().expect("cannot create memfd");
+        file.write_all(&[42; 4096]).unwrap();
+        file.set_len(8192).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 8192);
+        file.seek(SeekFrom::Start(4095)).unwrap();
+        let mut content = [0; 2];
+        file.read_exact(&mut content).unwrap();
+        assert_eq!(content, [42, 0]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    ffi::CString,
+    fmt::Debug,
+    ops::Deref,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::{create_temp_dir, Error, Policy, TmpDir};
+
+/// A helper datastructure for ensuring that we unmount the filesystem mounted on a temporary
+/// directory and remove this directory before end of the current scope.
+#[must_use = "the mount is undone as soon as the guard is dropped"]
+pub struct TmpMount {
+    /// Temporary directory containing the mount point (or being the mount point)
+    dir: TmpDir,
+    target: PathBuf,
+    policy: Policy,
+}
+
+impl Deref for TmpMount {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.target
+    }
+}
+
+impl Debug for TmpMount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.target)
+    }
+}
+
+/// Mount a tmpfs of at most `size` bytes on a new temporary directory. It returns a
+/// datastructure to keep the mount, when dropped the tmpfs is unmounted and the directory
+/// removed. It needs the `CAP_SYS_ADMIN` capability (for example running as root).
+/// ```no_run
+/// let tmpfs = tmp_env::mount_tmpfs(64 * 1024 * 1024).expect("cannot mount tmpfs");
+/// std::fs::write(tmpfs.join("a.txt"), "in memory").expect("cannot write file");
+/// ```
+pub fn mount_tmpfs(size: u64) -> Result<TmpMount, std::io::Error> {
+    let dir = create_temp_dir()?;
+    mount(
+        "tmpfs",
+        &dir,
+        "tmpfs",
+        libc::MS_NOSUID | libc::MS_NODEV,
+        &format!("size={},mode=0700", size),
+    )?;
+    let target = dir.to_path_buf();
+
+    Ok(TmpMount {
+        dir,
+        target,
+        policy: Policy::default(),
+    })
+}
+
+/// Mount an overlayfs over `lower_dir`, with the upper and work directories in a new temporary
+/// directory. Writes through the returned mount point never reach `lower_dir`, so a large shared
+/// fixture can be "mutated" without copying it. When dropped the overlay is unmounted and all its
+/// changes removed. It needs the `CAP_SYS_ADMIN` capability (for example running as root).
+/// ```no_run
+/// let overlay = tmp_env::overlay_over("tests/fixtures").expect("cannot mount overlay");
+/// std::fs::write(overlay.join("new.txt"), "only in the overlay").expect("cannot write file");
+/// assert!(!std::path::Path::new("tests/fixtures/new.txt").exists());
+/// ```
+pub fn overlay_over<P: AsRef<Path>>(lower_dir: P) -> Result<TmpMount, std::io::Error> {
+    let lower_dir = std::fs::canonicalize(lower_dir)?;
+    let dir = create_temp_dir()?;
+    let upper_dir = dir.create_dir("upper")?;
+    let work_dir = dir.create_dir("work")?;
+    let target = dir.create_dir("merged")?;
+    let options = format!(
+        "lowerdir={},upperdir={},workdir={}",
+        overlay_option(&lower_dir)?,
+        overlay_option(&upper_dir)?,
+        overlay_option(&work_dir)?
+    );
+    mount("overlay", &target, "overlay", 0, &options)?;
+
+    Ok(TmpMount {
+        dir,
+        target,
+        policy: Policy::default(),
+    })
+}
+
+impl TmpMount {
+    /// Choose how the failure to unmount the filesystem when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+fn overlay_option(path: &Path) -> Result<&str, std::io::Error> {
+    path.to_str()
+        .filter(|path| !path.contains(&[',', ':'][..]))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} cannot be used as an overlay directory", path),
+            )
+        })
+}
+
+fn cstring(bytes: &[u8]) -> Result<CString, std::io::Error> {
+    CString::new(bytes).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+}
+
+fn mount(
+    source: &str,
+    target: &Path,
+    fs_type: &str,
+    flags: libc::c_ulong,
+    options: &str,
+) -> Result<(), std::io::Error> {
+    let source = cstring(source.as_bytes())?;
+    let target = cstring(target.as_os_str().as_bytes())?;
+    let fs_type = cstring(fs_type.as_bytes())?;
+    let options = cstring(options.as_bytes())?;
+    // SAFETY: all the pointers are valid nul terminated strings for the duration of the call
+    let res = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            fs_type.as_ptr(),
+            flags,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn unmount(target: &Path) -> Result<(), std::io::Error> {
+    let target = cstring(target.as_os_str().as_bytes())?;
+    // SAFETY: the pointer is a valid nul terminated string for the duration of the call
+    if unsafe { libc::umount2(target.as_ptr(), 0) } == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    // Still busy, detach it so it goes away once no longer used
+    // SAFETY: same as above
+    if err.raw_os_error() == Some(libc::EBUSY)
+        && unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } == 0
+    {
+        return Ok(());
+    }
+
+    Err(err)
+}
+
+impl Drop for TmpMount {
+    fn drop(&mut self) {
+        if let Err(error) = unmount(&self.target) {
+            let error = Error::restore(&self.target, error);
+            self.policy.handle(error, Error::report);
+            // Never remove the content of a filesystem still mounted
+            std::mem::take(&mut self.dir.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_tmpfs() {
+        let tmpfs = match mount_tmpfs(1024 * 1024) {
+            Ok(tmpfs) => tmpfs,
+            // Not privileged enough to mount filesystems
+            Err(err) if err.raw_os_error() == Some(libc::EPERM) => return,
+            Err(err) => panic!("cannot mount tmpfs: {}", err),
+        };
+        let path = tmpfs.to_path_buf();
+        let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap();
+        assert!(mounts
+            .lines()
+            .any(|line| line.starts_with("tmpfs ") && line.contains(&*path.to_string_lossy())));
+        std::fs::write(tmpfs.join("a.txt"), "content").unwrap();
+        // Larger than the tmpfs
+        assert!(std::fs::write(tmpfs.join("b.bin"), vec![0; 2 * 1024 * 1024]).is_err());
+        drop(tmpfs);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_overlay_over() {
+        let lower = create_temp_dir().expect("cannot create temp dir");
+        lower.write("fixture.txt", "original").unwrap();
+        let overlay = match overlay_over(&*lower) {
+            Ok(overlay) => overlay,
+            // Not privileged enough or overlayfs not available
+            Err(err)
+                if [libc::EPERM, libc::ENODEV, libc::EINVAL]
+                    .contains(&err.raw_os_error().unwrap_or(0)) =>
+            {
+                return
+            }
+            Err(err) => panic!("cannot mount overlay: {}", err),
+        };
+        let path = overlay.to_path_buf();
+        assert_eq!(
+            std::fs::read_to_string(overlay.join("fixture.txt")).unwrap(),
+            "original"
+        );
+        std::fs::write(overlay.join("fixture.txt"), "changed").unwrap();
+        std::fs::write(overlay.join("new.txt"), "new").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(overlay.join("fixture.txt")).unwrap(),
+            "changed"
+        );
+        assert_eq!(lower.read_to_string("fixture.txt").unwrap(), "original");
+        assert!(!lower.join("new.txt").exists());
+        drop(overlay);
+        assert!(!path.parent().unwrap().exists());
+        assert_eq!(lower.read_to_string("fixture.txt").unwrap(), "original");
+    }
+}
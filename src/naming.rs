@@ -0,0 +1,291 @@
+#[cfg(feature = "rand")]
+use std::sync::{Arc, Mutex, PoisonError};
+use std::{ffi::OsStr, fmt::Debug, path::PathBuf};
+
+#[cfg(feature = "rand")]
+use rand::{rngs::StdRng, thread_rng, RngCore, SeedableRng};
+
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Strategy used to generate the random part of the names of the temporary directories and
+/// files: 10 alphanumeric characters from a thread local generator by default. Without the
+/// `rand` feature the characters come straight from the random generator of the operating
+/// system (`getrandom`, `/dev/urandom` or `BCryptGenRandom`).
+/// ```
+/// use tmp_env::{Naming, TmpDir};
+///
+/// let naming = Naming::new().alphabet("abcdef0123456789").rand_len(32);
+/// let tmp_dir = TmpDir::builder().naming(naming).create().expect("cannot create temp dir");
+/// assert_eq!(tmp_dir.file_name().unwrap().len(), 32);
+/// ```
+#[derive(Clone)]
+pub struct Naming {
+    rand_len: usize,
+    alphabet: Option<Vec<char>>,
+    uuid: bool,
+    #[cfg(feature = "rand")]
+    rng: Option<Arc<Mutex<dyn RngCore + Send>>>,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Self {
+            rand_len: 10,
+            alphabet: None,
+            uuid: false,
+            #[cfg(feature = "rand")]
+            rng: None,
+        }
+    }
+}
+
+impl Debug for Naming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Naming");
+        debug
+            .field("rand_len", &self.rand_len)
+            .field("alphabet", &self.alphabet)
+            .field("uuid", &self.uuid);
+        #[cfg(feature = "rand")]
+        debug.field("custom_rng", &self.rng.is_some());
+        debug.finish()
+    }
+}
+
+impl Naming {
+    /// Same as [`Naming::default`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a random (version 4) UUID like `0f8fad5b-d9cb-469f-a165-70867728950e` as the random
+    /// part of the names, [`Naming::rand_len`] and [`Naming::alphabet`] are then ignored
+    pub fn uuid() -> Self {
+        Self {
+            uuid: true,
+            ..Self::default()
+        }
+    }
+
+    /// Number of random characters (10 by default)
+    pub fn rand_len(mut self, rand_len: usize) -> Self {
+        self.rand_len = rand_len;
+        self
+    }
+
+    /// Pick the random characters from this alphabet instead of `[a-zA-Z0-9]`, an empty alphabet
+    /// keeps the default one. Keep in mind that some filesystems are case insensitive.
+    pub fn alphabet<S: AsRef<str>>(mut self, alphabet: S) -> Self {
+        let alphabet: Vec<char> = alphabet.as_ref().chars().collect();
+        self.alphabet = if alphabet.is_empty() {
+            None
+        } else {
+            Some(alphabet)
+        };
+        self
+    }
+
+    /// Draw the random characters from this generator instead of a thread local one. The
+    /// generator is shared by the clones of this strategy (and of the builders using it).
+    #[cfg(feature = "rand")]
+    pub fn rng<R: RngCore + Send + 'static>(mut self, rng: R) -> Self {
+        self.rng = Some(Arc::new(Mutex::new(rng)));
+        self
+    }
+
+    /// Draw the random characters from a generator seeded with `seed` so the sequence of
+    /// generated names is the same across runs, see [`Naming::rng`]
+    /// ```
+    /// use tmp_env::{Naming, TmpDir};
+    ///
+    /// let first = TmpDir::builder().naming(Naming::new().seed(42)).create().unwrap();
+    /// let path = first.to_path_buf();
+    /// drop(first);
+    /// let second = TmpDir::builder().naming(Naming::new().seed(42)).create().unwrap();
+    /// assert_eq!(*second, path);
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn seed(self, seed: u64) -> Self {
+        self.rng(StdRng::seed_from_u64(seed))
+    }
+
+    pub(crate) fn name(&self, prefix: &OsStr, suffix: &OsStr) -> PathBuf {
+        #[cfg(feature = "rand")]
+        let rand_string = match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().unwrap_or_else(PoisonError::into_inner);
+                self.rand_string(&mut || rng.next_u64())
+            }
+            None => {
+                let mut rng = thread_rng();
+                self.rand_string(&mut || rng.next_u64())
+            }
+        };
+        #[cfg(not(feature = "rand"))]
+        let rand_string = self.rand_string(&mut os_random_u64);
+        let mut name = prefix.to_os_string();
+        name.push(rand_string);
+        name.push(suffix);
+
+        PathBuf::from(name)
+    }
+
+    fn rand_string(&self, next_u64: &mut dyn FnMut() -> u64) -> String {
+        if self.uuid {
+            let mut bytes = [0; 16];
+            bytes[..8].copy_from_slice(&next_u64().to_le_bytes());
+            bytes[8..].copy_from_slice(&next_u64().to_le_bytes());
+            // Version 4 and RFC 4122 variant
+            bytes[6] = (bytes[6] & 0x0f) | 0x40;
+            bytes[8] = (bytes[8] & 0x3f) | 0x80;
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            return format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            );
+        }
+        // The modulo bias is negligible with alphabets of reasonable sizes
+        match &self.alphabet {
+            Some(alphabet) => (0..self.rand_len)
+                .map(|_| alphabet[(next_u64() % alphabet.len() as u64) as usize])
+                .collect(),
+            None => (0..self.rand_len)
+                .map(|_| {
+                    char::from(ALPHANUMERIC[(next_u64() % ALPHANUMERIC.len() as u64) as usize])
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Random number read from the random generator of the operating system: `getrandom` on Linux
+/// and Android, `/dev/urandom` on the other Unix systems and `BCryptGenRandom` on Windows. The
+/// names are not delegated to `mkdtemp` or `GetTempFileNameW`, which cannot honor the prefix,
+/// suffix, alphabet and length chosen with [`Naming`].
+///
+/// Panics when the operating system cannot provide random bytes, as the standard library does.
+#[cfg(not(feature = "rand"))]
+fn os_random_u64() -> u64 {
+    let mut bytes = [0; 8];
+    if let Err(err) = fill_os_random(&mut bytes) {
+        panic!("cannot get random bytes from the operating system: {}", err);
+    }
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(all(not(feature = "rand"), any(target_os = "linux", target_os = "android")))]
+fn fill_os_random(mut bytes: &mut [u8]) -> std::io::Result<()> {
+    use std::io;
+
+    while !bytes.is_empty() {
+        // SAFETY: the pointer and length describe the writable remainder of `bytes`
+        let read = unsafe { libc::getrandom(bytes.as_mut_ptr().cast(), bytes.len(), 0) };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                // Kernels older than 3.17 do not have the syscall
+                Some(libc::ENOSYS) => return fill_dev_urandom(bytes),
+                _ => return Err(err),
+            }
+        }
+        bytes = &mut bytes[read as usize..];
+    }
+    Ok(())
+}
+
+#[cfg(all(
+    not(feature = "rand"),
+    unix,
+    not(any(target_os = "linux", target_os = "android"))
+))]
+fn fill_os_random(bytes: &mut [u8]) -> std::io::Result<()> {
+    fill_dev_urandom(bytes)
+}
+
+#[cfg(all(not(feature = "rand"), unix))]
+fn fill_dev_urandom(bytes: &mut [u8]) -> std::io::Result<()> {
+    use std::{fs::File, io::Read};
+
+    File::open("/dev/urandom")?.read_exact(bytes)
+}
+
+#[cfg(all(not(feature = "rand"), windows))]
+fn fill_os_random(bytes: &mut [u8]) -> std::io::Result<()> {
+    use std::{io, ptr};
+    use windows_sys::Win32::Security::Cryptography::{
+        BCryptGenRandom, BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+    };
+
+    for chunk in bytes.chunks_mut(u32::MAX as usize) {
+        // SAFETY: the buffer is valid for `chunk.len()` bytes and no algorithm handle is needed
+        // with the system preferred generator
+        let status = unsafe {
+            BCryptGenRandom(
+                ptr::null_mut(),
+                chunk.as_mut_ptr(),
+                chunk.len() as u32,
+                BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+            )
+        };
+        if status < 0 {
+            return Err(io::Error::other(format!(
+                "BCryptGenRandom failed with status {:#x}",
+                status
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TmpDir;
+
+    #[test]
+    fn test_naming() {
+        let name = Naming::new().name("pre".as_ref(), ".suf".as_ref());
+        let name = name.to_str().unwrap();
+        assert_eq!(name.len(), 17);
+        assert!(name.starts_with("pre") && name.ends_with(".suf"));
+
+        let name = Naming::new()
+            .alphabet("ab")
+            .rand_len(50)
+            .name("".as_ref(), "".as_ref());
+        assert_eq!(name.to_str().unwrap().len(), 50);
+        assert!(name.to_str().unwrap().chars().all(|c| c == 'a' || c == 'b'));
+
+        let name = Naming::uuid().name("".as_ref(), "".as_ref());
+        let name = name.to_str().unwrap();
+        let groups: Vec<_> = name.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert_eq!(&name[14..15], "4");
+        assert!("89ab".contains(&name[19..20]));
+
+        #[cfg(feature = "rand")]
+        {
+            let parent = crate::create_temp_dir().expect("cannot create temp dir");
+            let names = || {
+                let builder = TmpDir::builder()
+                    .parent(&*parent)
+                    .naming(Naming::new().seed(1234));
+                let first = builder.create().expect("cannot create temp dir");
+                let second = builder.create().expect("cannot create temp dir");
+                assert_ne!(*first, *second);
+                vec![first.to_path_buf(), second.to_path_buf()]
+            };
+            assert_eq!(names(), names());
+        }
+        let tmp_dir = TmpDir::builder()
+            .naming(Naming::uuid())
+            .create()
+            .expect("cannot create temp dir");
+        assert_eq!(tmp_dir.file_name().unwrap().len(), 36);
+    }
+}
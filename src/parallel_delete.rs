@@ -0,0 +1,143 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, PoisonError},
+};
+
+use crate::remove_symlink;
+
+struct State {
+    /// Directories left to empty
+    queue: Vec<PathBuf>,
+    /// Number of directories being emptied
+    busy: usize,
+    /// Directories already emptied or being emptied, a parent always before its children
+    dirs: Vec<PathBuf>,
+    error: Option<std::io::Error>,
+}
+
+/// Same as [`remove_tree`](crate::remove_tree) but the directories are emptied by `threads`
+/// threads, which is a lot faster for trees containing many files
+pub(crate) fn remove_tree_parallel(path: &Path, threads: usize) -> Result<(), std::io::Error> {
+    if std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return remove_symlink(path);
+    }
+    let state = Mutex::new(State {
+        queue: vec![path.to_path_buf()],
+        busy: 0,
+        dirs: Vec::new(),
+        error: None,
+    });
+    let work_available = Condvar::new();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| worker(&state, &work_available));
+        }
+    });
+    let state = state.into_inner().unwrap_or_else(PoisonError::into_inner);
+    if let Some(err) = state.error {
+        return Err(err);
+    }
+    // Children first
+    for dir in state.dirs.iter().rev() {
+        std::fs::remove_dir(dir)?;
+    }
+
+    Ok(())
+}
+
+fn worker(state: &Mutex<State>, work_available: &Condvar) {
+    loop {
+        let dir = {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if state.error.is_some() {
+                    return;
+                }
+                if let Some(dir) = state.queue.pop() {
+                    state.busy += 1;
+                    state.dirs.push(dir.clone());
+                    break dir;
+                }
+                if state.busy == 0 {
+                    return;
+                }
+                state = work_available
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        };
+        let res = empty_dir(&dir);
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.busy -= 1;
+        match res {
+            Ok(subdirs) => state.queue.extend(subdirs),
+            Err(err) => {
+                state.error.get_or_insert(err);
+            }
+        }
+        // Wake up the others to take the new directories or to stop
+        work_available.notify_all();
+    }
+}
+
+/// Remove everything but the sub directories inside `dir`, which are returned
+fn empty_dir(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut subdirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            subdirs.push(entry.path());
+        } else if file_type.is_symlink() {
+            remove_symlink(&entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(subdirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_temp_dir, TmpDir};
+
+    #[test]
+    fn test_tmp_dir_parallel_delete() {
+        let outside = create_temp_dir().expect("cannot create temp dir");
+        let precious = outside.write("precious.txt", "precious").unwrap();
+
+        let tmp_dir = TmpDir::builder()
+            .parallel_delete(4)
+            .create()
+            .expect("cannot create temp dir");
+        for package in 0..20 {
+            for file in 0..20 {
+                tmp_dir
+                    .write(
+                        format!("node_modules/pkg{}/lib/nested/file{}.js", package, file),
+                        "content",
+                    )
+                    .unwrap();
+            }
+        }
+        tmp_dir.create_dir("empty/dirs/only").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&*outside, tmp_dir.join("node_modules/outside")).unwrap();
+            std::os::unix::fs::symlink(&precious, tmp_dir.join("link.txt")).unwrap();
+        }
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(!path.exists());
+        assert_eq!(std::fs::read_to_string(&precious).unwrap(), "precious");
+
+        let tmp_dir = TmpDir::builder()
+            .parallel_delete(4)
+            .create()
+            .expect("cannot create temp dir");
+        let path = tmp_dir.to_path_buf();
+        drop(tmp_dir);
+        assert!(!path.exists());
+    }
+}
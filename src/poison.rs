@@ -0,0 +1,108 @@
+use std::{
+    fmt::Display,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{
+    counters::{count, Counter},
+    Error,
+};
+
+static POISON: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The restorations which failed when their guard was dropped, see [`take_poison`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poison {
+    failures: Vec<String>,
+}
+
+impl Poison {
+    /// Why each restoration failed, in the order they failed
+    pub fn failures(&self) -> &[String] {
+        &self.failures
+    }
+}
+
+impl Display for Poison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the environment is poisoned, {} restorations failed: {}",
+            self.failures.len(),
+            self.failures.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for Poison {}
+
+/// Whether a guard failed to undo its change when dropped (environment variable, current
+/// directory, temporary file or directory left behind...), whatever its
+/// [`Policy`](crate::Policy), since the poison was last taken. The next tests can skip or fail
+/// fast instead of running against a corrupted environment.
+/// ```
+/// assert!(!tmp_env::is_poisoned());
+/// ```
+pub fn is_poisoned() -> bool {
+    !POISON
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_empty()
+}
+
+/// Take the details of the failed restorations, clearing the flag returned by [`is_poisoned`]
+/// ```
+/// fn check_environment() -> Result<(), tmp_env::Poison> {
+///     match tmp_env::take_poison() {
+///         Some(poison) => Err(poison),
+///         None => Ok(()),
+///     }
+/// }
+/// check_environment().expect("a previous test corrupted the environment");
+/// ```
+pub fn take_poison() -> Option<Poison> {
+    let failures = std::mem::take(&mut *POISON.lock().unwrap_or_else(PoisonError::into_inner));
+    if failures.is_empty() {
+        None
+    } else {
+        Some(Poison { failures })
+    }
+}
+
+/// Record the failure of a guard dropped
+pub(crate) fn poison(error: &Error) {
+    count(Counter::RestoreFailures, 1);
+    POISON
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(error.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_file, Policy};
+
+    #[test]
+    fn test_poison() {
+        let tmp_file = create_temp_file()
+            .expect("cannot create temp file")
+            .on_restore_failure(Policy::Ignore);
+        let path = tmp_file.to_path_buf();
+        // Replaced by a directory so it can't be deleted
+        std::fs::remove_file(&path).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        drop(tmp_file);
+        std::fs::remove_dir(&path).unwrap();
+
+        assert!(is_poisoned());
+        let poison = take_poison().expect("the environment should be poisoned");
+        assert!(poison
+            .failures()
+            .iter()
+            .any(|failure| failure.contains(&*path.to_string_lossy())));
+        assert!(poison
+            .to_string()
+            .starts_with("the environment is poisoned"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fmt::Debug, panic::RefUnwindSafe, sync::Arc};
+
+use crate::{poison::poison, Error};
+
+// Unwind safe to keep the guards unwind safe
+type Callback = Arc<dyn Fn(&Error) + Send + Sync + RefUnwindSafe>;
+
+/// How a guard handles the failure to undo its change when dropped, see for example
+/// [`TmpDir::on_restore_failure`](crate::TmpDir::on_restore_failure)
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static FAILURES: AtomicUsize = AtomicUsize::new(0);
+/// let tmp_dir = tmp_env::create_temp_dir()
+///     .expect("cannot create temp dir")
+///     .on_restore_failure(tmp_env::Policy::callback(|err| {
+///         eprintln!("{}", err);
+///         FAILURES.fetch_add(1, Ordering::SeqCst);
+///     }));
+/// drop(tmp_dir);
+/// assert_eq!(FAILURES.load(Ordering::SeqCst), 0);
+/// ```
+#[derive(Clone, Default)]
+pub enum Policy {
+    /// Report the failure to the hooks registered with [`on_cleanup_error`](crate::on_cleanup_error)
+    /// and [`on_dir_fallback`](crate::on_dir_fallback), or as a warning without hook
+    #[default]
+    Log,
+    /// Panic, for example to make the tests fail in CI. The failure is reported like with
+    /// [`Policy::Log`] instead if the thread is already panicking (a panic would abort the
+    /// process) or with the `no-panic` feature.
+    Panic,
+    /// Silently ignore the failure
+    Ignore,
+    /// Call this function with the failure
+    Callback(Callback),
+}
+
+impl Policy {
+    /// Same as [`Policy::Callback`] without wrapping the function in an [`Arc`]
+    pub fn callback<F: Fn(&Error) + Send + Sync + RefUnwindSafe + 'static>(callback: F) -> Self {
+        Policy::Callback(Arc::new(callback))
+    }
+
+    /// Handle the failure of a guard being dropped, `log` reports it like [`Policy::Log`]
+    pub(crate) fn handle<L: FnOnce(Error)>(&self, error: Error, log: L) {
+        poison(&error);
+        match self {
+            Policy::Panic if !cfg!(feature = "no-panic") && !std::thread::panicking() => {
+                panic!("tmp_env: {}", error)
+            }
+            Policy::Log | Policy::Panic => log(error),
+            Policy::Ignore => {}
+            Policy::Callback(callback) => callback(&error),
+        }
+    }
+}
+
+impl Debug for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Log => write!(f, "Log"),
+            Policy::Panic => write!(f, "Panic"),
+            Policy::Ignore => write!(f, "Ignore"),
+            Policy::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_file, TmpFile};
+    use std::{panic::AssertUnwindSafe, sync::Mutex};
+
+    // The file is replaced by a directory so it can't be deleted, even by root
+    fn undeletable_file(policy: Policy) -> TmpFile {
+        let tmp_file = create_temp_file()
+            .expect("cannot create temp file")
+            .on_restore_failure(policy);
+        std::fs::remove_file(&*tmp_file).unwrap();
+        std::fs::create_dir(&*tmp_file).unwrap();
+        tmp_file
+    }
+
+    #[test]
+    fn test_policy() {
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&failures);
+        let tmp_file = undeletable_file(Policy::callback(move |err| {
+            recorded.lock().unwrap().push(err.to_string());
+        }));
+        let path = tmp_file.to_path_buf();
+        drop(tmp_file);
+        assert_eq!(failures.lock().unwrap().len(), 1);
+        assert!(failures.lock().unwrap()[0].contains(&*path.to_string_lossy()));
+        std::fs::remove_dir(&path).unwrap();
+
+        let tmp_file = undeletable_file(Policy::Ignore);
+        let path = tmp_file.to_path_buf();
+        drop(tmp_file);
+        std::fs::remove_dir(&path).unwrap();
+
+        let tmp_file = undeletable_file(Policy::Panic);
+        let path = tmp_file.to_path_buf();
+        let res = std::panic::catch_unwind(AssertUnwindSafe(move || drop(tmp_file)));
+        assert_eq!(res.is_err(), !cfg!(feature = "no-panic"));
+        std::fs::remove_dir(&path).unwrap();
+        assert_eq!(format!("{:?}", Policy::default()), "Log");
+    }
+}
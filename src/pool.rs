@@ -0,0 +1,168 @@
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use crate::{
+    create_temp_dir, remove_symlink, remove_tree, report_cleanup_error, CleanupErrorEvent, TmpDir,
+};
+
+struct Pool {
+    root: TmpDir,
+    free: Mutex<Vec<PathBuf>>,
+}
+
+/// A pool of temporary directories which are emptied and reused instead of being deleted, because
+/// creating and deleting thousands of directories is slow on some filesystems. All the
+/// directories live in a temporary directory deleted once the pool and all the directories it
+/// handed out are dropped.
+/// ```
+/// let pool = tmp_env::TmpDirPool::new().expect("cannot create pool");
+/// for _ in 0..100 {
+///     let tmp_dir = pool.get().expect("cannot get temp dir");
+///     tmp_dir.write("a.txt", "content").expect("cannot write file");
+/// }
+/// // Only one directory has been created
+/// assert_eq!(pool.available(), 1);
+/// ```
+#[derive(Clone)]
+pub struct TmpDirPool(Arc<Pool>);
+
+impl Debug for TmpDirPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0.root.path)
+    }
+}
+
+impl TmpDirPool {
+    /// Create an empty pool in the temporary directory of your operating system
+    pub fn new() -> Result<Self, std::io::Error> {
+        Ok(Self(Arc::new(Pool {
+            root: create_temp_dir()?,
+            free: Mutex::new(Vec::new()),
+        })))
+    }
+
+    /// Get an empty directory from the pool, or create a new one if none is available. It is
+    /// emptied and given back to the pool when dropped.
+    pub fn get(&self) -> Result<PooledTmpDir, std::io::Error> {
+        let free = self
+            .0
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let dir = match free {
+            Some(path) => TmpDir::new(path),
+            None => TmpDir::builder().parent(&*self.0.root).create()?,
+        };
+
+        Ok(PooledTmpDir {
+            dir,
+            pool: Arc::clone(&self.0),
+        })
+    }
+
+    /// Number of empty directories waiting to be reused
+    pub fn available(&self) -> usize {
+        self.0
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// A helper datastructure for ensuring that we empty the directory taken from a [`TmpDirPool`]
+/// and give it back to the pool before end of the current scope.
+#[must_use = "the directory goes back to the pool as soon as it is dropped"]
+pub struct PooledTmpDir {
+    dir: TmpDir,
+    pool: Arc<Pool>,
+}
+
+impl Deref for PooledTmpDir {
+    type Target = TmpDir;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dir
+    }
+}
+
+impl Debug for PooledTmpDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.dir.path)
+    }
+}
+
+impl Drop for PooledTmpDir {
+    fn drop(&mut self) {
+        match empty_dir(&self.dir.path) {
+            Ok(()) => {
+                // Disarm the deletion and give it back
+                let path = std::mem::take(&mut self.dir.path);
+                self.pool
+                    .free
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(path);
+            }
+            // Never reused, the directory itself is deleted when the TmpDir is dropped
+            Err(error) => report_cleanup_error(CleanupErrorEvent {
+                path: self.dir.to_path_buf(),
+                error,
+            }),
+        }
+    }
+}
+
+/// Remove the content of `dir` without following symlinks
+fn empty_dir(dir: &Path) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            remove_tree(&entry.path())?;
+        } else if file_type.is_symlink() {
+            remove_symlink(&entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmp_dir_pool() {
+        let pool = TmpDirPool::new().expect("cannot create pool");
+        let root = pool.0.root.to_path_buf();
+        let first = pool.get().expect("cannot get temp dir");
+        let second = pool.get().expect("cannot get temp dir");
+        assert_ne!(**first, **second);
+        assert!(first.starts_with(&root));
+        first.write("a/b/c.txt", "content").unwrap();
+        let first_path = first.to_path_buf();
+        drop(first);
+        assert!(first_path.is_dir());
+        assert_eq!(pool.available(), 1);
+
+        let reused = pool.get().expect("cannot get temp dir");
+        assert_eq!(**reused, first_path);
+        assert_eq!(reused.walk().count(), 0);
+        assert_eq!(pool.available(), 0);
+
+        // The pool is torn down once everything is dropped
+        drop(pool);
+        assert!(root.exists());
+        drop(reused);
+        drop(second);
+        assert!(!root.exists());
+    }
+}
@@ -0,0 +1,231 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// A helper datastructure for ensuring that we restore the previous priority of the process
+/// before end of the current scope.
+#[must_use = "the previous priority is restored as soon as the guard is dropped"]
+pub struct TmpPriority {
+    // The nice value on Unix, the priority class on Windows. `None` once restored or kept.
+    previous: Option<sys::Priority>,
+    // On Linux the priority is the one of the thread which created the guard
+    _not_send: PhantomData<*const ()>,
+    policy: Policy,
+}
+
+/// The priority of the process as a nice value, from -20 (highest priority) to 19 (lowest
+/// priority). On Windows it is derived from the priority class of the process.
+/// ```
+/// let priority = tmp_env::current_priority().expect("cannot get the priority");
+/// assert!((-20..=19).contains(&priority));
+/// ```
+pub fn current_priority() -> Result<i32, std::io::Error> {
+    sys::get().map(sys::to_nice)
+}
+
+/// Set the priority of the process to the nice value, from -20 (highest priority) to 19 (lowest
+/// priority), and return a datastructure restoring the previous priority when dropped, so a
+/// benchmark can change its priority for a scope only. It uses `setpriority` on Unix (on Linux
+/// it only changes the priority of the calling thread) and `SetPriorityClass` with the closest
+/// priority class on Windows.
+///
+/// Raising the priority usually needs privileges (`CAP_SYS_NICE` on Linux), and on Unix so does
+/// lowering the nice value back, so restoring a lowered priority can fail for an unprivileged
+/// process. The guard can't be sent to another thread, which would restore its own priority
+/// instead on Linux.
+/// ```
+/// let priority = tmp_env::current_priority().expect("cannot get the priority");
+/// {
+///     let _priority = tmp_env::set_priority(priority).expect("cannot set the priority");
+/// }
+/// // Because guard is dropped the previous priority is restored
+/// assert_eq!(tmp_env::current_priority().unwrap(), priority);
+/// ```
+/// ```compile_fail
+/// let priority = tmp_env::set_nice(0).expect("cannot set the priority");
+/// std::thread::spawn(move || drop(priority));
+/// ```
+pub fn set_priority(nice: i32) -> Result<TmpPriority, std::io::Error> {
+    let previous = sys::get()?;
+    sys::set(sys::from_nice(nice.clamp(-20, 19)))?;
+    Ok(TmpPriority {
+        previous: Some(previous),
+        _not_send: PhantomData,
+        policy: Policy::default(),
+    })
+}
+
+/// Add `delta` to the nice value of the process, see [`set_priority`]. A positive `delta` lowers
+/// the priority of the process.
+pub fn set_nice(delta: i32) -> Result<TmpPriority, std::io::Error> {
+    set_priority(current_priority()?.saturating_add(delta))
+}
+
+impl TmpPriority {
+    /// The priority of the process before it was changed, as a nice value, or `None` once it is
+    /// restored or kept
+    pub fn previous(&self) -> Option<i32> {
+        self.previous.map(sys::to_nice)
+    }
+
+    /// Choose how the failure to restore the previous priority when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpPriority {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => Ok(sys::set(previous)?),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.previous() {
+            Some(previous) => write!(f, "TmpPriority({})", previous),
+            None => write!(f, "TmpPriority"),
+        }
+    }
+}
+
+impl Drop for TmpPriority {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!("cannot restore the priority: {}", err))
+            });
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    pub(super) type Priority = i32;
+
+    pub(super) fn get() -> Result<Priority, std::io::Error> {
+        // -1 is a valid priority, only errno tells if it failed
+        clear_errno();
+        // SAFETY: getpriority only reads the priority of the current process
+        let priority = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        if priority == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() != Some(0) {
+                return Err(err);
+            }
+        }
+        Ok(priority)
+    }
+
+    pub(super) fn set(priority: Priority) -> Result<(), std::io::Error> {
+        // SAFETY: setpriority only changes the priority of the current process
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, priority) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn to_nice(priority: Priority) -> i32 {
+        priority
+    }
+
+    pub(super) fn from_nice(nice: i32) -> Priority {
+        nice
+    }
+
+    fn clear_errno() {
+        // SAFETY: the errno location of the current thread is always valid
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        unsafe {
+            *libc::__errno_location() = 0;
+        }
+        // SAFETY: the errno location of the current thread is always valid
+        #[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
+        unsafe {
+            *libc::__error() = 0;
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, GetPriorityClass, SetPriorityClass, ABOVE_NORMAL_PRIORITY_CLASS,
+        BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS,
+        NORMAL_PRIORITY_CLASS, REALTIME_PRIORITY_CLASS,
+    };
+
+    pub(super) type Priority = u32;
+
+    pub(super) fn get() -> Result<Priority, std::io::Error> {
+        // SAFETY: the pseudo handle of the current process is always valid
+        match unsafe { GetPriorityClass(GetCurrentProcess()) } {
+            0 => Err(std::io::Error::last_os_error()),
+            class => Ok(class),
+        }
+    }
+
+    pub(super) fn set(priority: Priority) -> Result<(), std::io::Error> {
+        // SAFETY: the pseudo handle of the current process is always valid
+        if unsafe { SetPriorityClass(GetCurrentProcess(), priority) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn to_nice(priority: Priority) -> i32 {
+        match priority {
+            IDLE_PRIORITY_CLASS => 19,
+            BELOW_NORMAL_PRIORITY_CLASS => 10,
+            ABOVE_NORMAL_PRIORITY_CLASS => -10,
+            HIGH_PRIORITY_CLASS => -15,
+            REALTIME_PRIORITY_CLASS => -20,
+            _ => 0,
+        }
+    }
+
+    pub(super) fn from_nice(nice: i32) -> Priority {
+        match nice {
+            15.. => IDLE_PRIORITY_CLASS,
+            1..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+            0 => NORMAL_PRIORITY_CLASS,
+            -14..=-1 => ABOVE_NORMAL_PRIORITY_CLASS,
+            _ => HIGH_PRIORITY_CLASS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority() {
+        let priority = current_priority().expect("cannot get the priority");
+        if priority >= 19 {
+            return;
+        }
+        let mut lowered = set_nice(1).expect("cannot lower the priority");
+        assert_eq!(lowered.previous(), Some(priority));
+        assert!(current_priority().unwrap() > priority);
+        match lowered.restore_now() {
+            Ok(()) => assert_eq!(current_priority().unwrap(), priority),
+            // Unprivileged processes can't raise their priority back on Unix
+            Err(err) => assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied),
+        }
+        assert!(!lowered.is_active());
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt::Debug;
+
+use crate::{trace, Error, Policy, TempGuard};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Id {
+    User,
+    Group,
+}
+
+/// A helper datastructure for ensuring that we restore the previous effective user or group ID of
+/// the process before end of the current scope.
+#[must_use = "the previous ID is restored as soon as the guard is dropped"]
+pub struct TmpEffectiveId {
+    id: Id,
+    // `None` once restored or kept
+    previous: Option<u32>,
+    policy: Policy,
+}
+
+/// Switch the effective user ID of the process with `seteuid` and return a datastructure
+/// switching back to the previous one when dropped, so a test suite run as root can check the
+/// permission denied paths and regain its privileges afterwards. The real and saved IDs are
+/// untouched, which is what allows to switch back.
+///
+/// The effective IDs are shared by all the threads of the process. To drop both, change the
+/// group first since an unprivileged user can only switch to its real and saved IDs, the guards
+/// being dropped in the reverse order restore the user first.
+/// ```no_run
+/// {
+///     let _egid = tmp_env::set_egid(65534).expect("cannot set the effective group");
+///     let _euid = tmp_env::set_euid(65534).expect("cannot set the effective user");
+///     assert!(std::fs::write("/root/file", "").is_err());
+/// }
+/// // Because guards are dropped the privileges are regained
+/// ```
+pub fn set_euid(uid: u32) -> Result<TmpEffectiveId, std::io::Error> {
+    // SAFETY: getting the effective user ID has no requirements
+    let previous = unsafe { libc::geteuid() };
+    // SAFETY: seteuid only changes the credentials of the process
+    if unsafe { libc::seteuid(uid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(TmpEffectiveId {
+        id: Id::User,
+        previous: Some(previous),
+        policy: Policy::default(),
+    })
+}
+
+/// Switch the effective group ID of the process with `setegid` and return a datastructure
+/// switching back to the previous one when dropped, see [`set_euid`]
+pub fn set_egid(gid: u32) -> Result<TmpEffectiveId, std::io::Error> {
+    // SAFETY: getting the effective group ID has no requirements
+    let previous = unsafe { libc::getegid() };
+    // SAFETY: setegid only changes the credentials of the process
+    if unsafe { libc::setegid(gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(TmpEffectiveId {
+        id: Id::Group,
+        previous: Some(previous),
+        policy: Policy::default(),
+    })
+}
+
+impl TmpEffectiveId {
+    /// The effective ID before it was changed, or `None` once it is restored or kept
+    pub fn previous(&self) -> Option<u32> {
+        self.previous
+    }
+
+    /// Choose how the failure to restore the previous ID when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpEffectiveId {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => {
+                // SAFETY: seteuid and setegid only change the credentials of the process
+                let result = match self.id {
+                    Id::User => unsafe { libc::seteuid(previous) },
+                    Id::Group => unsafe { libc::setegid(previous) },
+                };
+                if result != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpEffectiveId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.id {
+            Id::User => "euid",
+            Id::Group => "egid",
+        };
+        match self.previous {
+            Some(previous) => write!(f, "TmpEffectiveId({} {})", name, previous),
+            None => write!(f, "TmpEffectiveId({})", name),
+        }
+    }
+}
+
+impl Drop for TmpEffectiveId {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            let name = match self.id {
+                Id::User => "user",
+                Id::Group => "group",
+            };
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!(
+                    "cannot restore the effective {} ID: {}",
+                    name, err
+                ))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_in_child, EnvChanges};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_effective_id() {
+        // The effective IDs are shared by the threads running the other tests
+        let status = run_in_child(&EnvChanges::new(), || {
+            // SAFETY: getting the effective IDs has no requirements
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            if uid != 0 {
+                let err = set_euid(uid + 1).unwrap_err();
+                assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+                return;
+            }
+            {
+                let _egid = set_egid(65534).unwrap();
+                let euid = set_euid(65534).unwrap();
+                assert_eq!(euid.previous(), Some(0));
+                // SAFETY: getting the effective IDs has no requirements
+                assert_eq!(
+                    unsafe { (libc::geteuid(), libc::getegid()) },
+                    (65534, 65534)
+                );
+                // Only the real and saved IDs are allowed without privileges
+                assert!(set_egid(1).is_err());
+            }
+            // SAFETY: getting the effective IDs has no requirements
+            assert_eq!(unsafe { (libc::geteuid(), libc::getegid()) }, (uid, gid));
+
+            // Restoring a group other than the real one once the user isn't privileged fails
+            static FAILED: AtomicBool = AtomicBool::new(false);
+            let _first_egid = set_egid(65533).unwrap();
+            let egid = set_egid(65534)
+                .unwrap()
+                .on_restore_failure(Policy::callback(|_| FAILED.store(true, Ordering::SeqCst)));
+            let euid = set_euid(65534).unwrap();
+            drop(egid);
+            assert!(FAILED.load(Ordering::SeqCst));
+            drop(euid);
+        })
+        .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt::Debug,
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{long_path, walk::dir_size, TmpDir};
+
+/// A helper datastructure for ensuring that we stop watching the size of the temporary directory
+/// before end of the current scope, see [`TmpDir::watch_quota`].
+#[must_use = "the watcher stops as soon as it is dropped"]
+pub struct QuotaWatcher {
+    // Dropped to wake up and stop the watching thread
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Debug for QuotaWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotaWatcher").finish_non_exhaustive()
+    }
+}
+
+impl Drop for QuotaWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl TmpDir {
+    /// The quota of the temporary directory in bytes, set with
+    /// [`TmpDirBuilder::max_size`](crate::TmpDirBuilder::max_size)
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Return the total size of the files inside the temporary directory, or an error of kind
+    /// [`QuotaExceeded`](std::io::ErrorKind::QuotaExceeded) if it is over the quota set with
+    /// [`TmpDirBuilder::max_size`](crate::TmpDirBuilder::max_size)
+    /// ```
+    /// let tmp_dir = tmp_env::TmpDir::builder()
+    ///     .max_size(1024)
+    ///     .create()
+    ///     .expect("cannot create temp dir");
+    /// tmp_dir.write("small.bin", vec![0; 1000]).expect("cannot write file");
+    /// assert_eq!(tmp_dir.check_quota().unwrap(), 1000);
+    /// tmp_dir.write("more.bin", vec![0; 100]).expect("cannot write file");
+    /// assert!(tmp_dir.check_quota().is_err());
+    /// ```
+    pub fn check_quota(&self) -> Result<u64, std::io::Error> {
+        let size = self.size()?;
+        match self.max_size {
+            Some(max_size) if size > max_size => Err(quota_exceeded(self, size, max_size)),
+            _ => Ok(size),
+        }
+    }
+
+    /// Check the size of the temporary directory every `interval` in a background thread and
+    /// call `on_exceeded` with the size once it is over the quota set with
+    /// [`TmpDirBuilder::max_size`](crate::TmpDirBuilder::max_size), for example to abort a
+    /// runaway test before it fills the disk. The watching stops when the returned
+    /// datastructure is dropped or after calling `on_exceeded`, nothing is watched without
+    /// quota.
+    /// ```
+    /// let tmp_dir = tmp_env::TmpDir::builder()
+    ///     .max_size(10)
+    ///     .create()
+    ///     .expect("cannot create temp dir");
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// let _watcher = tmp_dir.watch_quota(std::time::Duration::from_millis(10), move |size| {
+    ///     sender.send(size).unwrap();
+    /// });
+    /// tmp_dir.write("big.bin", vec![0; 100]).expect("cannot write file");
+    /// assert_eq!(receiver.recv().unwrap(), 100);
+    /// ```
+    pub fn watch_quota<F>(&self, interval: Duration, mut on_exceeded: F) -> QuotaWatcher
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        let (stop, stopped) = channel::<()>();
+        let thread = self.max_size.and_then(|max_size| {
+            let path = long_path(&self.path).into_owned();
+            std::thread::Builder::new()
+                .name("tmp_env-quota".to_string())
+                .spawn(move || {
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        // The size can't be computed while files are removed concurrently, the
+                        // next check will
+                        match dir_size(&path) {
+                            Ok(size) if size > max_size => return on_exceeded(size),
+                            _ => {}
+                        }
+                    }
+                })
+                .ok()
+        });
+
+        QuotaWatcher {
+            stop: Some(stop),
+            thread,
+        }
+    }
+}
+
+fn quota_exceeded(tmp_dir: &TmpDir, size: u64, max_size: u64) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::QuotaExceeded,
+        format!(
+            "{:?} uses {} bytes, over its quota of {} bytes",
+            tmp_dir, size, max_size
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_tmp_dir_quota() {
+        let unlimited = crate::create_temp_dir().expect("cannot create temp dir");
+        unlimited.write("a.bin", vec![0; 100]).unwrap();
+        assert_eq!(unlimited.max_size(), None);
+        assert_eq!(unlimited.check_quota().unwrap(), 100);
+        let watcher = unlimited.watch_quota(Duration::from_millis(1), |_| unreachable!());
+        assert!(watcher.thread.is_none());
+
+        let tmp_dir = TmpDir::builder()
+            .max_size(100)
+            .create()
+            .expect("cannot create temp dir");
+        assert_eq!(tmp_dir.max_size(), Some(100));
+        let exceeded = Arc::new(AtomicU64::new(0));
+        let watcher = {
+            let exceeded = Arc::clone(&exceeded);
+            tmp_dir.watch_quota(Duration::from_millis(5), move |size| {
+                exceeded.fetch_add(size, Ordering::SeqCst);
+            })
+        };
+        tmp_dir.write("sub/a.bin", vec![0; 100]).unwrap();
+        assert_eq!(tmp_dir.check_quota().unwrap(), 100);
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(exceeded.load(Ordering::SeqCst), 0);
+
+        tmp_dir.write("sub/b.bin", vec![0; 1]).unwrap();
+        let err = tmp_dir.check_quota().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::QuotaExceeded);
+        assert!(err.to_string().contains("101 bytes"));
+        while exceeded.load(Ordering::SeqCst) == 0 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        drop(watcher);
+        // Called only once
+        assert_eq!(exceeded.load(Ordering::SeqCst), 101);
+    }
+}
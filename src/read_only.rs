@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use crate::TmpDir;
+
+impl TmpDir {
+    /// Recursively make the temporary directory and its content read-only (or writable again),
+    /// for example to test how code behaves against unwritable inputs once populated. The
+    /// permissions are relaxed again before the temporary directory is deleted. Note that
+    /// privileged users (like root on Unix) can still write in it.
+    /// ```
+    /// let mut tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("input.txt", "content").expect("cannot write file");
+    /// tmp_dir.set_read_only(true).expect("cannot make tmp dir read-only");
+    /// assert!(std::fs::metadata(tmp_dir.join("input.txt")).unwrap().permissions().readonly());
+    /// // When dropped the tmp dir is writable again so it can be deleted
+    /// ```
+    pub fn set_read_only(&mut self, read_only: bool) -> Result<(), std::io::Error> {
+        set_tree_read_only(&self.path, read_only)?;
+        self.read_only = read_only;
+        Ok(())
+    }
+
+    /// Returns `true` if the temporary directory has been made read-only with
+    /// [`TmpDir::set_read_only`]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+pub(crate) fn set_tree_read_only(path: &Path, read_only: bool) -> Result<(), std::io::Error> {
+    let file_type = std::fs::symlink_metadata(path)?.file_type();
+    if file_type.is_symlink() {
+        return Ok(());
+    }
+    // A read-only directory can't be listed on some platforms so relax it before its content and
+    // make it read-only after its content
+    if !read_only {
+        set_read_only(path, false)?;
+    }
+    if file_type.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            set_tree_read_only(&entry?.path(), read_only)?;
+        }
+    }
+    if read_only {
+        set_read_only(path, true)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_read_only(path: &Path, read_only: bool) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    let mode = permissions.mode();
+    // Only give the write permission back to the owner instead of everyone
+    permissions.set_mode(if read_only {
+        mode & !0o222
+    } else {
+        mode | 0o200
+    });
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn set_read_only(path: &Path, read_only: bool) -> Result<(), std::io::Error> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(read_only);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_temp_dir;
+
+    #[test]
+    fn test_tmp_dir_read_only() {
+        #[allow(unused_assignments)]
+        let mut tmp_dir_created = None;
+        {
+            let mut tmp_dir = create_temp_dir().expect("cannot create temp dir");
+            tmp_dir_created = Some(tmp_dir.to_path_buf());
+            let file = tmp_dir
+                .write("sub/file", "content")
+                .expect("cannot write file");
+            tmp_dir
+                .set_read_only(true)
+                .expect("cannot make tmp dir read-only");
+            assert!(tmp_dir.is_read_only());
+            for path in &[tmp_dir.to_path_buf(), tmp_dir.join("sub"), file.clone()] {
+                assert!(std::fs::metadata(path).unwrap().permissions().readonly());
+            }
+            tmp_dir
+                .set_read_only(false)
+                .expect("cannot make tmp dir writable");
+            assert!(!std::fs::metadata(&file).unwrap().permissions().readonly());
+            tmp_dir
+                .set_read_only(true)
+                .expect("cannot make tmp dir read-only");
+        }
+        assert!(std::fs::metadata(tmp_dir_created.unwrap()).is_err());
+
+        let mut tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        tmp_dir.write("file", "content").expect("cannot write file");
+        tmp_dir
+            .set_read_only(true)
+            .expect("cannot make tmp dir read-only");
+        let path = tmp_dir.to_path_buf();
+        tmp_dir.close().expect("cannot delete the tmp dir");
+        assert!(!path.exists());
+    }
+}
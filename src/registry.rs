@@ -0,0 +1,217 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use crate::lifecycle::Lifecycle;
+
+static ACTIVE: Mutex<BTreeMap<u64, ActiveChange>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// What a guard listed by [`active_changes`] changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChangeKind {
+    /// An environment variable set or removed, see [`set_var`](crate::set_var)
+    Var,
+    /// The current directory, see [`set_current_dir`](crate::set_current_dir)
+    CurrentDir,
+    /// A temporary directory, see [`create_temp_dir`](crate::create_temp_dir)
+    TmpDir,
+    /// A temporary file, see [`create_temp_file`](crate::create_temp_file)
+    TmpFile,
+}
+
+/// A temporary change whose guard is still alive, see [`active_changes`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ActiveChange {
+    /// What the guard changed
+    pub kind: ChangeKind,
+    /// The name of the environment variable, or the path of the directory or file
+    pub target: OsString,
+    /// The name of the thread which created the guard
+    pub thread: Option<String>,
+    /// Where the guard was created, only captured in debug builds when enabled with the
+    /// `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl Display for ActiveChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} {:?} created on thread {}",
+            self.kind,
+            self.target,
+            self.thread.as_deref().unwrap_or("<unnamed>")
+        )?;
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, " at\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// The changes whose guard is still alive (not yet restored nor kept), in the order they were
+/// made, so a test harness can print the temporary state left behind when a suite hangs or
+/// leaks
+/// ```
+/// let _tmp_env = tmp_env::set_var("TEST_ACTIVE_CHANGES", "value");
+/// let changes = tmp_env::active_changes();
+/// assert!(changes.iter().any(|change| change.target == "TEST_ACTIVE_CHANGES"));
+/// for change in changes {
+///     eprintln!("{}", change);
+/// }
+/// ```
+pub fn active_changes() -> Vec<ActiveChange> {
+    ACTIVE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// The backtrace of [`ActiveChange::backtrace`], captured by the guards changing the state of the
+/// whole process before they change it so capturing it doesn't delay the change
+pub(crate) fn capture_backtrace() -> Option<Arc<Backtrace>> {
+    if cfg!(debug_assertions) {
+        Some(Backtrace::capture())
+            .filter(|backtrace| backtrace.status() == BacktraceStatus::Captured)
+            .map(Arc::new)
+    } else {
+        None
+    }
+}
+
+/// The entry of a guard in [`active_changes`], removed when dropped. It also reports the change
+/// to the hooks registered with [`on_apply`](crate::on_apply) and
+/// [`on_restore`](crate::on_restore).
+#[derive(Debug)]
+pub(crate) struct Registration {
+    id: Option<u64>,
+    lifecycle: Lifecycle,
+}
+
+impl Registration {
+    /// Nothing registered, for the guards doing nothing
+    pub(crate) fn none() -> Self {
+        Self {
+            id: None,
+            lifecycle: Lifecycle::default(),
+        }
+    }
+
+    pub(crate) fn new(kind: ChangeKind, target: &OsStr) -> Self {
+        Self::with_values(kind, target, None, None, capture_backtrace())
+    }
+
+    /// Register a change replacing the `old` value by the `new` one, made where `backtrace` was
+    /// captured
+    pub(crate) fn with_values(
+        kind: ChangeKind,
+        target: &OsStr,
+        old: Option<&OsStr>,
+        new: Option<&OsStr>,
+        backtrace: Option<Arc<Backtrace>>,
+    ) -> Self {
+        let lifecycle = Lifecycle::apply(
+            kind,
+            target.to_owned(),
+            old.map(OsStr::to_owned),
+            new.map(OsStr::to_owned),
+        );
+        let change = ActiveChange {
+            kind,
+            target: target.to_owned(),
+            thread: std::thread::current().name().map(String::from),
+            backtrace,
+        };
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        ACTIVE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, change);
+        Self {
+            id: Some(id),
+            lifecycle,
+        }
+    }
+
+    /// The guard has undone its change
+    pub(crate) fn restored(&mut self) {
+        self.lifecycle.restore();
+        self.release();
+    }
+
+    /// The guard has kept its change, or couldn't undo it
+    pub(crate) fn release(&mut self) {
+        self.lifecycle.forget();
+        if let Some(id) = self.id.take() {
+            ACTIVE
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&id);
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_temp_dir, create_temp_file, lock_current_dir, set_current_dir, set_var};
+
+    #[test]
+    fn test_active_changes() {
+        let _lock = lock_current_dir();
+        let is_active = |target: &OsStr| {
+            active_changes()
+                .into_iter()
+                .find(|change| change.target == target)
+                .map(|change| change.kind)
+        };
+        let env = set_var("TEST_ACTIVE_CHANGES_VAR", "value");
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        let tmp_file = create_temp_file().expect("cannot create temp file");
+        let current_dir = set_current_dir(&*tmp_dir).expect("cannot set current dir");
+        let dir_target = current_dir.current().as_os_str().to_owned();
+        let file_target = tmp_file.as_os_str().to_owned();
+        let env_target = OsStr::new("TEST_ACTIVE_CHANGES_VAR");
+        assert_eq!(is_active(env_target), Some(ChangeKind::Var));
+        assert_eq!(is_active(tmp_dir.as_os_str()), Some(ChangeKind::TmpDir));
+        assert_eq!(is_active(&file_target), Some(ChangeKind::TmpFile));
+        assert!(active_changes()
+            .iter()
+            .any(|change| change.kind == ChangeKind::CurrentDir && change.target == dir_target));
+        assert!(active_changes()
+            .iter()
+            .any(|change| change.to_string().contains("TEST_ACTIVE_CHANGES_VAR")));
+
+        current_dir.restore_now().unwrap();
+        drop(env);
+        let mut tmp_file = tmp_file;
+        crate::TempGuard::keep(&mut tmp_file);
+        assert_eq!(is_active(env_target), None);
+        assert_eq!(is_active(&file_target), None);
+        assert!(!active_changes()
+            .iter()
+            .any(|change| change.kind == ChangeKind::CurrentDir && change.target == dir_target));
+        let dir_target = tmp_dir.as_os_str().to_owned();
+        drop(tmp_dir);
+        assert_eq!(is_active(&dir_target), None);
+        std::fs::remove_file(&file_target).unwrap();
+    }
+}
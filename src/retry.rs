@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+/// How many times deleting a temporary directory is attempted before giving up, and how long to
+/// wait between attempts. On Windows antivirus scanners, indexers or lingering handles often make
+/// the first attempt fail with a sharing violation, so deletion is attempted 5 times starting
+/// with a 10ms backoff by default there, and only once on other platforms.
+/// ```
+/// use std::time::Duration;
+///
+/// let tmp_dir = tmp_env::TmpDir::builder()
+///     .delete_retry(tmp_env::RetryPolicy::new(10, Duration::from_millis(50)))
+///     .create()
+///     .expect("cannot create temp dir");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Try at most `attempts` times (at least once), waiting `backoff` after the first failure
+    /// and doubling the wait after each following failure
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            backoff,
+        }
+    }
+
+    /// Only try once
+    pub fn never() -> Self {
+        Self::new(1, Duration::from_millis(0))
+    }
+
+    /// Maximum number of attempts
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Wait after the first failed attempt
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    pub(crate) fn run<T, F: FnMut() -> Result<T, std::io::Error>>(
+        &self,
+        mut f: F,
+    ) -> Result<T, std::io::Error> {
+        let mut backoff = self.backoff;
+        let mut attempts = 1;
+        loop {
+            match f() {
+                Err(err)
+                    if err.kind() != std::io::ErrorKind::NotFound && attempts < self.attempts =>
+                {
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempts += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::new(5, Duration::from_millis(10))
+        } else {
+            Self::never()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let mut calls = 0;
+        let res: Result<(), _> = policy.run(|| {
+            calls += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        });
+        assert_eq!(
+            res.unwrap_err().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(calls, 3);
+
+        calls = 0;
+        let res = policy.run(|| {
+            calls += 1;
+            if calls < 2 {
+                Err(std::io::Error::other("sharing violation"))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(res.unwrap(), 2);
+
+        calls = 0;
+        let res: Result<(), _> = policy.run(|| {
+            calls += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+        assert_eq!(RetryPolicy::new(0, Duration::from_millis(1)).attempts(), 1);
+    }
+}
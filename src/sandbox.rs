@@ -0,0 +1,190 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Debug,
+    ops::Deref,
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{command::Vars, create_temp_dir, EnvChanges, Error, TmpDir};
+
+/// The variables kept by [`Sandbox::clear_env`], needed to find and run most programs
+#[cfg(unix)]
+const KEPT_VARS: &[&str] = &["PATH"];
+#[cfg(windows)]
+const KEPT_VARS: &[&str] = &["PATH", "PATHEXT", "SystemRoot", "ComSpec"];
+
+/// A helper datastructure for ensuring that we delete the temporary directory of a sandbox before
+/// end of the current scope. The sandbox combines this directory, with `home` and `tmp`
+/// directories inside it, and changes to the environment given to the commands run inside it.
+/// ```
+/// let sandbox = tmp_env::Sandbox::new()
+///     .expect("cannot create sandbox")
+///     .env(tmp_env::EnvChanges::new().set_var("APP_MODE", "test"));
+/// let command = sandbox.command("app").expect("cannot configure the command");
+/// assert_eq!(command.get_current_dir(), Some(sandbox.as_path()));
+/// ```
+#[must_use = "the sandbox is deleted as soon as it is dropped"]
+pub struct Sandbox {
+    dir: TmpDir,
+    env: EnvChanges,
+    clear_env: bool,
+}
+
+impl Deref for Sandbox {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.dir
+    }
+}
+
+impl Debug for Sandbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sandbox({:?})", self.dir.path)
+    }
+}
+
+impl Sandbox {
+    /// Create the temporary directory of the sandbox with its `home` and `tmp` directories
+    pub fn new() -> Result<Self, std::io::Error> {
+        let dir = create_temp_dir()?;
+        std::fs::create_dir(dir.join("home"))?;
+        std::fs::create_dir(dir.join("tmp"))?;
+        Ok(Self {
+            dir,
+            env: EnvChanges::new(),
+            clear_env: false,
+        })
+    }
+
+    /// Changes applied to the environment of the commands, after the variables pointing inside
+    /// the sandbox so they can be overridden. A relative current directory is inside the sandbox.
+    pub fn env(mut self, env: EnvChanges) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Don't inherit the environment of the current process in the commands, except the
+    /// variables needed to find and run programs (`PATH`, and `SystemRoot` on Windows...)
+    pub fn clear_env(mut self, clear: bool) -> Self {
+        self.clear_env = clear;
+        self
+    }
+
+    /// The directory used as home directory by the commands
+    pub fn home(&self) -> PathBuf {
+        self.dir.join("home")
+    }
+
+    /// The directory used for the temporary files of the commands
+    pub fn tmp(&self) -> PathBuf {
+        self.dir.join("tmp")
+    }
+
+    /// The temporary directory of the sandbox
+    pub fn dir(&self) -> &TmpDir {
+        &self.dir
+    }
+
+    /// A command running the program inside the sandbox: its current directory is the sandbox,
+    /// `HOME` (`USERPROFILE` on Windows) points to [`Sandbox::home`] and `TMPDIR` (`TMP` and
+    /// `TEMP` on Windows) to [`Sandbox::tmp`], then the changes given to [`Sandbox::env`] are
+    /// applied
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Result<Command, Error> {
+        // The whole environment is given, so the paths are prepended to the inherited values only
+        let (vars, current_dir) = self.changes().resolve(self.inherited_vars())?;
+        let mut command = Command::new(program);
+        command.env_clear().envs(vars);
+        if let Some(current_dir) = current_dir {
+            command.current_dir(current_dir);
+        }
+        Ok(command)
+    }
+
+    /// The variables of the current process given to the commands
+    pub(crate) fn inherited_vars(&self) -> Vars {
+        if self.clear_env {
+            KEPT_VARS
+                .iter()
+                .filter_map(|key| Some((OsString::from(key), std::env::var_os(key)?)))
+                .collect()
+        } else {
+            std::env::vars_os().collect()
+        }
+    }
+
+    /// The changes applied to the environment of the commands, see [`Sandbox::command`]
+    pub(crate) fn changes(&self) -> EnvChanges {
+        let home = self.home();
+        let tmp = self.tmp();
+        let mut changes = EnvChanges::new().current_dir(self.as_path());
+        changes = if cfg!(windows) {
+            changes
+                .set_var("USERPROFILE", home)
+                .set_var("TMP", &tmp)
+                .set_var("TEMP", tmp)
+        } else {
+            changes.set_var("HOME", home).set_var("TMPDIR", tmp)
+        };
+        changes.extend(&self.env);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_command() {
+        std::env::set_var("TEST_SANDBOX_INHERITED", "value");
+        std::env::set_var("TEST_SANDBOX_LIBS", "/parent/lib");
+        let sandbox = Sandbox::new()
+            .expect("cannot create sandbox")
+            .clear_env(true)
+            .env(
+                EnvChanges::new()
+                    .set_var("TEST_SANDBOX_SET", "value")
+                    .prepend_path("TEST_SANDBOX_LIBS", "/sandbox/lib")
+                    .current_dir("home"),
+            );
+        assert!(sandbox.home().is_dir());
+        assert!(sandbox.tmp().is_dir());
+        let command = sandbox.command("program").unwrap();
+        let value = |key: &str| {
+            command
+                .get_envs()
+                .find(|(name, _)| *name == key)
+                .and_then(|(_, value)| value)
+                .map(PathBuf::from)
+        };
+        let home_var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        assert_eq!(value(home_var), Some(sandbox.home()));
+        assert_eq!(value("TEST_SANDBOX_SET"), Some(PathBuf::from("value")));
+        assert_eq!(command.get_current_dir(), Some(sandbox.home().as_path()));
+
+        // The inherited variables are only left out by the child process itself
+        let output = if cfg!(windows) {
+            sandbox.command("cmd").unwrap().args(["/C", "set"]).output()
+        } else {
+            sandbox.command("env").unwrap().output()
+        }
+        .expect("cannot run the command");
+        std::env::remove_var("TEST_SANDBOX_INHERITED");
+        std::env::remove_var("TEST_SANDBOX_LIBS");
+        assert!(output.status.success());
+        let env = String::from_utf8_lossy(&output.stdout);
+        assert!(env.lines().any(|line| line == "TEST_SANDBOX_SET=value"));
+        assert!(!env.contains("TEST_SANDBOX_INHERITED"));
+        // The cleared value isn't prepended to
+        assert!(env
+            .lines()
+            .any(|line| line == "TEST_SANDBOX_LIBS=/sandbox/lib"));
+
+        let path = sandbox.to_path_buf();
+        drop(command);
+        drop(sandbox);
+        assert!(!path.exists());
+    }
+}
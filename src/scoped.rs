@@ -0,0 +1,107 @@
+/// Run a block with several temporary changes, undone in the reverse order they are declared
+/// once the block ends, and return the value of the block. The changes are separated by commas
+/// and followed by `=>` and the block:
+/// - `var KEY = VALUE` sets an environment variable, see [`set_var`](crate::set_var)
+/// - `remove KEY` removes an environment variable, see [`remove_var`](crate::remove_var)
+/// - `cd PATH` switches the current directory, taking the lock of
+///   [`lock_current_dir`](crate::lock_current_dir), see
+///   [`set_current_dir_locked`](crate::set_current_dir_locked)
+/// - `tmp_dir NAME` creates a temporary directory bound to `NAME` in the block, see
+///   [`create_temp_dir`](crate::create_temp_dir)
+///
+/// It panics if a change can't be made, which is what a test wants. `KEY` must be a single
+/// token (a literal or a variable), wrap other expressions in parentheses.
+/// ```
+/// let original_dir = std::env::current_dir().unwrap();
+/// let len = tmp_env::scoped! {
+///     var "TEST_SCOPED_LOG" = "debug",
+///     remove "TEST_SCOPED_REMOVED",
+///     tmp_dir out,
+///     cd &*out => {
+///         assert_eq!(std::env::var("TEST_SCOPED_LOG").unwrap(), "debug");
+///         assert_eq!(std::env::current_dir().unwrap(), *out);
+///         std::fs::write("result.txt", "done").unwrap();
+///         std::fs::read_to_string(out.join("result.txt")).unwrap().len()
+///     }
+/// };
+/// assert_eq!(len, 4);
+/// assert!(std::env::var("TEST_SCOPED_LOG").is_err());
+/// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+/// ```
+#[macro_export]
+macro_rules! scoped {
+    (=> $body:block) => {
+        $body
+    };
+    (var $key:tt = $value:expr, $($rest:tt)*) => {{
+        let _guard = $crate::set_var($key, $value);
+        $crate::scoped!($($rest)*)
+    }};
+    (var $key:tt = $value:expr => $body:block) => {
+        $crate::scoped!(var $key = $value, => $body)
+    };
+    (remove $key:tt, $($rest:tt)*) => {{
+        let _guard = $crate::remove_var($key);
+        $crate::scoped!($($rest)*)
+    }};
+    (remove $key:tt => $body:block) => {
+        $crate::scoped!(remove $key, => $body)
+    };
+    (cd $path:expr, $($rest:tt)*) => {{
+        let path = $path;
+        let path: &::std::path::Path = path.as_ref();
+        let _guard = $crate::set_current_dir_locked(path)
+            .unwrap_or_else(|err| panic!("cannot switch to {:?}: {}", path, err));
+        $crate::scoped!($($rest)*)
+    }};
+    (cd $path:expr => $body:block) => {
+        $crate::scoped!(cd $path, => $body)
+    };
+    (tmp_dir $name:ident, $($rest:tt)*) => {{
+        let $name = $crate::create_temp_dir().expect("cannot create temp dir");
+        $crate::scoped!($($rest)*)
+    }};
+    (tmp_dir $name:ident => $body:block) => {
+        $crate::scoped!(tmp_dir $name, => $body)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock_current_dir;
+
+    #[test]
+    fn test_scoped() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        std::env::set_var("TEST_SCOPED_MACRO_REMOVED", "restored");
+        let key = "TEST_SCOPED_MACRO_VAR";
+        let dir_path = crate::scoped! {
+            var key = "outer",
+            var ("TEST_SCOPED_MACRO_".to_string() + "OTHER") = 1.to_string(),
+            remove "TEST_SCOPED_MACRO_REMOVED",
+            tmp_dir out,
+            cd out.as_path(),
+            tmp_dir nested,
+            var key = nested.to_str().unwrap(), => {
+                assert_eq!(std::env::var(key).unwrap(), nested.to_str().unwrap());
+                assert_eq!(std::env::var("TEST_SCOPED_MACRO_OTHER").unwrap(), "1");
+                assert!(std::env::var("TEST_SCOPED_MACRO_REMOVED").is_err());
+                assert_eq!(std::env::current_dir().unwrap(), *out);
+                out.to_path_buf()
+            }
+        };
+        assert!(!dir_path.exists());
+        assert!(std::env::var(key).is_err());
+        assert!(std::env::var("TEST_SCOPED_MACRO_OTHER").is_err());
+        assert_eq!(
+            std::env::var("TEST_SCOPED_MACRO_REMOVED").unwrap(),
+            "restored"
+        );
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        std::env::remove_var("TEST_SCOPED_MACRO_REMOVED");
+
+        let value = crate::scoped!(remove "TEST_SCOPED_MACRO_VAR" => { 42 });
+        assert_eq!(value, 42);
+    }
+}
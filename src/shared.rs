@@ -0,0 +1,80 @@
+use std::{fmt::Debug, ops::Deref, sync::Arc};
+
+use crate::TmpDir;
+
+/// A cheaply cloneable handle to a temporary directory, which is removed when the last clone is
+/// dropped
+/// ```
+/// let shared = tmp_env::create_temp_dir().expect("cannot create temp dir").into_shared();
+/// let path = shared.to_path_buf();
+/// let clone = shared.clone();
+/// drop(shared);
+/// assert!(path.exists());
+/// drop(clone);
+/// assert!(!path.exists());
+/// ```
+#[derive(Clone)]
+#[must_use = "the temporary directory is deleted as soon as the last clone is dropped"]
+pub struct TmpDirShared(Arc<TmpDir>);
+
+impl TmpDirShared {
+    /// Number of clones currently holding the temporary directory
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl From<TmpDir> for TmpDirShared {
+    fn from(tmp_dir: TmpDir) -> Self {
+        Self(Arc::new(tmp_dir))
+    }
+}
+
+impl Deref for TmpDirShared {
+    type Target = TmpDir;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Debug for TmpDirShared {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0.path)
+    }
+}
+
+impl TmpDir {
+    /// Convert the temporary directory into a [`TmpDirShared`] which can be cloned and is
+    /// removed when the last clone is dropped
+    pub fn into_shared(self) -> TmpDirShared {
+        TmpDirShared::from(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_temp_dir;
+
+    #[test]
+    fn test_tmp_dir_shared() {
+        let shared = create_temp_dir()
+            .expect("cannot create temp dir")
+            .into_shared();
+        let path = shared.to_path_buf();
+        let clones: Vec<_> = (0..4).map(|_| shared.clone()).collect();
+        assert_eq!(shared.strong_count(), 5);
+        let handle = std::thread::spawn({
+            let shared = shared.clone();
+            move || shared.write("from_thread.txt", "content").unwrap()
+        });
+        handle.join().unwrap();
+        drop(shared);
+        assert_eq!(
+            clones[0].read_to_string("from_thread.txt").unwrap(),
+            "content"
+        );
+        drop(clones);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::Debug;
+
+use crate::{trace, Error, Policy, TempGuard};
+
+/// What to do when a signal is received, see [`set_signal_handler`]
+#[derive(Debug, Clone, Copy)]
+pub enum SignalHandler {
+    /// The default action of the signal (`SIG_DFL`)
+    Default,
+    /// Ignore the signal (`SIG_IGN`)
+    Ignore,
+    /// Call this function, which must only do async-signal-safe operations (like storing an
+    /// atomic)
+    Handler(extern "C" fn(i32)),
+}
+
+/// A helper datastructure for ensuring that we restore the previous disposition of a signal
+/// before end of the current scope.
+#[must_use = "the previous handler is restored as soon as the guard is dropped"]
+pub struct TmpSignalHandler {
+    signal: i32,
+    // `None` once restored or kept
+    previous: Option<libc::sigaction>,
+    policy: Policy,
+}
+
+/// Install the handler of the signal (like `libc::SIGPIPE`) with `sigaction` and return a
+/// datastructure restoring the previous disposition when dropped, so intercepting a signal in a
+/// test doesn't leak into the next ones. The disposition of a signal is global to the process.
+/// ```
+/// {
+///     let _sigpipe = tmp_env::set_signal_handler(libc::SIGPIPE, tmp_env::SignalHandler::Ignore)
+///         .expect("cannot set the signal handler");
+///     // Writing to a closed pipe returns an error instead of killing the process
+/// }
+/// // Because guard is dropped the previous disposition is restored
+/// ```
+pub fn set_signal_handler(
+    signal: i32,
+    handler: SignalHandler,
+) -> Result<TmpSignalHandler, std::io::Error> {
+    // SAFETY: all zeroes is a valid sigaction and the pointers are valid for the calls
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = match handler {
+            SignalHandler::Default => libc::SIG_DFL,
+            SignalHandler::Ignore => libc::SIG_IGN,
+            SignalHandler::Handler(handler) => handler as libc::sighandler_t,
+        };
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(signal, &action, &mut previous) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(TmpSignalHandler {
+            signal,
+            previous: Some(previous),
+            policy: Policy::default(),
+        })
+    }
+}
+
+impl TmpSignalHandler {
+    /// The signal whose handler is replaced
+    pub fn signal(&self) -> i32 {
+        self.signal
+    }
+
+    /// Choose how the failure to restore the previous disposition when dropped is handled, see
+    /// [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl TempGuard for TmpSignalHandler {
+    fn keep(&mut self) {
+        self.previous = None;
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.previous.take() {
+            Some(previous) => {
+                // SAFETY: the previous action was filled by sigaction in set_signal_handler
+                if unsafe { libc::sigaction(self.signal, &previous, std::ptr::null_mut()) } != 0 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.previous.is_some()
+    }
+}
+
+impl Debug for TmpSignalHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TmpSignalHandler({})", self.signal)
+    }
+}
+
+impl Drop for TmpSignalHandler {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_now() {
+            self.policy.handle(err, |err| {
+                trace::warn(format_args!(
+                    "cannot restore the handler of the signal {}: {}",
+                    self.signal, err
+                ))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_signal(_signal: i32) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    fn current_handler(signal: i32) -> libc::sighandler_t {
+        // SAFETY: all zeroes is a valid sigaction, a null new action only reads the current one
+        unsafe {
+            let mut current: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, std::ptr::null(), &mut current);
+            current.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn test_signal_handler() {
+        let previous = current_handler(libc::SIGUSR2);
+        {
+            let handler = set_signal_handler(libc::SIGUSR2, SignalHandler::Handler(on_signal))
+                .expect("cannot set the signal handler");
+            assert_eq!(handler.signal(), libc::SIGUSR2);
+            // SAFETY: raising a signal has no memory requirements
+            unsafe { libc::raise(libc::SIGUSR2) };
+            assert!(RECEIVED.load(Ordering::SeqCst));
+        }
+        assert_eq!(current_handler(libc::SIGUSR2), previous);
+
+        let mut ignored = set_signal_handler(libc::SIGUSR2, SignalHandler::Ignore)
+            .expect("cannot set the signal handler");
+        assert_eq!(current_handler(libc::SIGUSR2), libc::SIG_IGN);
+        ignored.restore_now().unwrap();
+        assert!(!ignored.is_active());
+        assert_eq!(current_handler(libc::SIGUSR2), previous);
+        assert!(set_signal_handler(-1, SignalHandler::Default).is_err());
+    }
+}
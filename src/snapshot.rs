@@ -0,0 +1,186 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::Hasher,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{long_path, TmpDir};
+
+/// State of an entry captured by a [`Snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryState {
+    /// A regular file with its size and a hash of its content
+    File {
+        /// Size in bytes
+        size: u64,
+        /// Hash of the content, only meant to detect changes
+        hash: u64,
+    },
+    /// A directory
+    Dir,
+    /// A symlink (never followed) with its target
+    Symlink(PathBuf),
+}
+
+/// The tree of a temporary directory captured by [`TmpDir::snapshot`], with the paths relative
+/// to the temporary directory
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Snapshot {
+    entries: BTreeMap<PathBuf, EntryState>,
+}
+
+impl Snapshot {
+    /// The captured entries sorted by path
+    pub fn entries(&self) -> &BTreeMap<PathBuf, EntryState> {
+        &self.entries
+    }
+}
+
+/// Entries created, modified or deleted since a [`Snapshot`], see [`TmpDir::diff`]. The paths
+/// are relative to the temporary directory and sorted.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Diff {
+    /// Entries which didn't exist in the snapshot
+    pub created: Vec<PathBuf>,
+    /// Entries whose content, size, kind or symlink target changed
+    pub modified: Vec<PathBuf>,
+    /// Entries which don't exist anymore
+    pub deleted: Vec<PathBuf>,
+}
+
+impl Diff {
+    /// Returns `true` if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+impl TmpDir {
+    /// Capture the paths, sizes and content hashes of everything inside the temporary directory,
+    /// to later check what changed with [`TmpDir::diff`]. Symlinks are not followed.
+    pub fn snapshot(&self) -> Result<Snapshot, std::io::Error> {
+        let root = long_path(&self.path);
+        let mut entries = BTreeMap::new();
+        for entry in self.walk() {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let state = if file_type.is_symlink() {
+                EntryState::Symlink(std::fs::read_link(&path)?)
+            } else if file_type.is_dir() {
+                EntryState::Dir
+            } else {
+                let (size, hash) = hash_file(&path)?;
+                EntryState::File { size, hash }
+            };
+            let relative = path.strip_prefix(&root).unwrap_or(&path).to_path_buf();
+            entries.insert(relative, state);
+        }
+
+        Ok(Snapshot { entries })
+    }
+
+    /// Compare the current content of the temporary directory with a snapshot taken before
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("kept.txt", "content").unwrap();
+    /// tmp_dir.write("changed.txt", "before").unwrap();
+    /// let snapshot = tmp_dir.snapshot().expect("cannot take snapshot");
+    /// tmp_dir.write("changed.txt", "after").unwrap();
+    /// tmp_dir.write("new.txt", "content").unwrap();
+    /// let diff = tmp_dir.diff(&snapshot).expect("cannot diff");
+    /// assert_eq!(diff.created, vec![std::path::PathBuf::from("new.txt")]);
+    /// assert_eq!(diff.modified, vec![std::path::PathBuf::from("changed.txt")]);
+    /// assert!(diff.deleted.is_empty());
+    /// ```
+    pub fn diff(&self, snapshot: &Snapshot) -> Result<Diff, std::io::Error> {
+        let current = self.snapshot()?;
+        let mut diff = Diff::default();
+        for (path, state) in &current.entries {
+            match snapshot.entries.get(path) {
+                None => diff.created.push(path.clone()),
+                Some(previous) if previous != state => diff.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.deleted = snapshot
+            .entries
+            .keys()
+            .filter(|path| !current.entries.contains_key(*path))
+            .cloned()
+            .collect();
+
+        Ok(diff)
+    }
+}
+
+fn hash_file(path: &Path) -> Result<(u64, u64), std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 8192];
+    let mut size = 0;
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hasher.write(&buf[..len]);
+        size += len as u64;
+    }
+
+    Ok((size, hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_temp_dir;
+
+    #[test]
+    fn test_tmp_dir_snapshot() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        tmp_dir.write("a/kept.txt", "kept").unwrap();
+        tmp_dir.write("a/changed.txt", "same size 1").unwrap();
+        tmp_dir.write("deleted/file.txt", "deleted").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a", tmp_dir.join("link")).unwrap();
+        let snapshot = tmp_dir.snapshot().expect("cannot take snapshot");
+        assert_eq!(
+            snapshot.entries()[Path::new("a/kept.txt")],
+            EntryState::File {
+                size: 4,
+                hash: {
+                    let mut hasher = DefaultHasher::new();
+                    hasher.write(b"kept");
+                    hasher.finish()
+                }
+            }
+        );
+        assert_eq!(snapshot.entries()[Path::new("a")], EntryState::Dir);
+        assert!(tmp_dir.diff(&snapshot).unwrap().is_empty());
+
+        tmp_dir.write("a/changed.txt", "same size 2").unwrap();
+        std::fs::remove_dir_all(tmp_dir.join("deleted")).unwrap();
+        tmp_dir.write("a/b/new.txt", "new").unwrap();
+        #[cfg(unix)]
+        {
+            std::fs::remove_file(tmp_dir.join("link")).unwrap();
+            std::os::unix::fs::symlink("a/b", tmp_dir.join("link")).unwrap();
+        }
+        let diff = tmp_dir.diff(&snapshot).unwrap();
+        assert_eq!(
+            diff.created,
+            vec![PathBuf::from("a/b"), PathBuf::from("a/b/new.txt")]
+        );
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut modified = vec![PathBuf::from("a/changed.txt")];
+        #[cfg(unix)]
+        modified.push(PathBuf::from("link"));
+        assert_eq!(diff.modified, modified);
+        assert_eq!(
+            diff.deleted,
+            vec![PathBuf::from("deleted"), PathBuf::from("deleted/file.txt")]
+        );
+    }
+}
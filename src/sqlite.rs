@@ -0,0 +1,179 @@
+use std::{ffi::OsString, fmt::Debug, ops::Deref, path::PathBuf};
+
+use crate::{ignore_not_found, Error, Policy, TempGuard, TmpFile};
+
+/// Suffixes of the files SQLite creates next to a database while it is opened
+const SIDE_FILE_SUFFIXES: &[&str] = &["-wal", "-shm", "-journal"];
+
+/// A helper datastructure for ensuring that we delete the temporary SQLite database created, with
+/// its `-wal`, `-shm` and `-journal` files, before end of the current scope.
+#[must_use = "the temporary database is deleted as soon as the guard is dropped"]
+pub struct TmpSqlite {
+    path: PathBuf,
+    policy: Policy,
+}
+
+impl Deref for TmpSqlite {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &Self::Target {
+        &self.path
+    }
+}
+
+impl Debug for TmpSqlite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.path)
+    }
+}
+
+/// Create a uniquely named empty `.db` file, which is a valid empty SQLite database, in the
+/// temporary directory of your operating system. It returns a datastructure to keep the database,
+/// when dropped the database is removed with the `-wal`, `-shm` and `-journal` files SQLite
+/// created next to it. The connections to the database must be closed before.
+/// ```
+/// let db = tmp_env::create_temp_sqlite().expect("cannot create temp database");
+/// assert_eq!(db.extension().unwrap(), "db");
+/// let path = db.to_path_buf();
+/// drop(db);
+/// assert!(!path.exists());
+/// ```
+pub fn create_temp_sqlite() -> Result<TmpSqlite, std::io::Error> {
+    let file = TmpFile::builder().extension("db").create()?;
+
+    Ok(TmpSqlite {
+        path: file.keep(),
+        policy: Policy::default(),
+    })
+}
+
+/// Same as [`create_temp_sqlite`] but the SQL statements of `schema` are run in the database
+/// ```
+/// let db = tmp_env::create_temp_sqlite_with_schema("CREATE TABLE users (name TEXT);")
+///     .expect("cannot create temp database");
+/// let connection = db.connect().expect("cannot open database");
+/// connection.execute("INSERT INTO users VALUES ('a')", []).unwrap();
+/// ```
+#[cfg(feature = "sqlite")]
+pub fn create_temp_sqlite_with_schema(schema: &str) -> Result<TmpSqlite, std::io::Error> {
+    let db = create_temp_sqlite()?;
+    db.connect()?
+        .execute_batch(schema)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+
+    Ok(db)
+}
+
+impl TmpSqlite {
+    /// Choose how the failure to delete the database or one of its side files when dropped is handled, see [`Policy`]
+    pub fn on_restore_failure(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Open a new connection to the database
+    #[cfg(feature = "sqlite")]
+    pub fn connect(&self) -> Result<rusqlite::Connection, std::io::Error> {
+        rusqlite::Connection::open(&self.path).map_err(std::io::Error::other)
+    }
+
+    fn side_file(&self, suffix: &str) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+}
+
+impl TempGuard for TmpSqlite {
+    fn keep(&mut self) {
+        // An empty path disarms the deletion when dropped
+        std::mem::take(&mut self.path);
+    }
+
+    /// Delete the database and its side files, returning the first error
+    fn restore_now(&mut self) -> Result<(), Error> {
+        match self.delete().into_iter().next() {
+            Some((path, error)) => Err(Error::restore(path, error)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        !self.path.as_os_str().is_empty()
+    }
+}
+
+impl TmpSqlite {
+    fn delete(&mut self) -> Vec<(PathBuf, std::io::Error)> {
+        if !self.is_active() {
+            return Vec::new();
+        }
+        let side_files = SIDE_FILE_SUFFIXES
+            .iter()
+            .map(|suffix| self.side_file(suffix));
+        let errors = side_files
+            .chain(std::iter::once(self.path.clone()))
+            .filter_map(|path| {
+                ignore_not_found(std::fs::remove_file(&path))
+                    .err()
+                    .map(|error| (path, error))
+            })
+            .collect();
+        std::mem::take(&mut self.path);
+        errors
+    }
+}
+
+impl Drop for TmpSqlite {
+    fn drop(&mut self) {
+        for (path, error) in self.delete() {
+            self.policy
+                .handle(Error::restore(path, error), Error::report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_sqlite() {
+        let db = create_temp_sqlite().expect("cannot create temp database");
+        assert!(db.is_file());
+        assert!(db.starts_with(std::env::temp_dir()));
+        let path = db.to_path_buf();
+        let side_files: Vec<_> = SIDE_FILE_SUFFIXES
+            .iter()
+            .map(|suffix| db.side_file(suffix))
+            .collect();
+        assert_eq!(side_files[0].file_name().unwrap(), {
+            let mut name = path.file_name().unwrap().to_os_string();
+            name.push("-wal");
+            name
+        });
+
+        #[cfg(feature = "sqlite")]
+        {
+            let connection = db.connect().unwrap();
+            connection
+                .execute_batch("PRAGMA journal_mode=WAL; CREATE TABLE t (v INTEGER);")
+                .unwrap();
+            connection.execute("INSERT INTO t VALUES (1)", []).unwrap();
+            assert!(side_files[0].exists());
+            drop(connection);
+            let connection = create_temp_sqlite_with_schema("CREATE TABLE x (v INTEGER);")
+                .unwrap()
+                .connect();
+            assert!(connection.is_ok());
+            assert!(create_temp_sqlite_with_schema("NOT SQL").is_err());
+        }
+        for side_file in &side_files {
+            std::fs::write(side_file, "").unwrap();
+        }
+
+        drop(db);
+        assert!(!path.exists());
+        assert!(side_files.iter().all(|side_file| !side_file.exists()));
+    }
+}
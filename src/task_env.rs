@@ -0,0 +1,94 @@
+//! Environment variables local to a tokio task.
+//!
+//! Environment variables are global to the process so changing them with
+//! [`set_var`](crate::set_var) is visible from every async test running on the runtime. Code
+//! reading its variables with [`var`](crate::var) can instead rely on [`scope`] which overlays
+//! variables for a single future only.
+//! ```
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let value = tmp_env::task_env::scope([("TEST_TASK_ENV", "value")], async {
+//!     tmp_env::var("TEST_TASK_ENV").unwrap()
+//! })
+//! .await;
+//! assert_eq!(value, "value");
+//! // The environment of the process is untouched
+//! assert!(std::env::var("TEST_TASK_ENV").is_err());
+//! assert!(tmp_env::var("TEST_TASK_ENV").is_err());
+//! # });
+//! ```
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    future::Future,
+};
+
+tokio::task_local! {
+    static OVERLAY: HashMap<OsString, OsString>;
+}
+
+/// Run the future with the given variables overlaying the environment of the process, for the
+/// [`var`](crate::var) and [`var_os`](crate::var_os) calls made while polling it. A nested scope
+/// sees the variables of the outer scopes, but the tasks spawned from the future don't.
+pub async fn scope<I, K, V, F>(vars: I, future: F) -> F::Output
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+    F: Future,
+{
+    let mut overlay = OVERLAY.try_with(Clone::clone).unwrap_or_default();
+    overlay.extend(
+        vars.into_iter()
+            .map(|(key, value)| (key.as_ref().to_os_string(), value.as_ref().to_os_string())),
+    );
+    OVERLAY.scope(overlay, future).await
+}
+
+/// The value of the variable in the scope of the current task, if any
+pub(crate) fn var_os(key: &OsStr) -> Option<OsString> {
+    OVERLAY
+        .try_with(|overlay| overlay.get(key).cloned())
+        .ok()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_env() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let read = |name: &'static str| {
+                scope([("TEST_TASK_ENV_NAME", name)], async move {
+                    tokio::task::yield_now().await;
+                    let outer = crate::var("TEST_TASK_ENV_NAME").unwrap();
+                    let inner = scope([("TEST_TASK_ENV_INNER", "inner")], async {
+                        (
+                            crate::var("TEST_TASK_ENV_NAME").unwrap(),
+                            crate::var("TEST_TASK_ENV_INNER").unwrap(),
+                        )
+                    })
+                    .await;
+                    let spawned = tokio::spawn(async { crate::var("TEST_TASK_ENV_NAME").is_err() });
+                    (outer, inner, spawned.await.unwrap())
+                })
+            };
+            let first = tokio::spawn(read("first"));
+            let second = tokio::spawn(read("second"));
+            assert_eq!(
+                first.await.unwrap(),
+                ("first".into(), ("first".into(), "inner".into()), true)
+            );
+            assert_eq!(
+                second.await.unwrap(),
+                ("second".into(), ("second".into(), "inner".into()), true)
+            );
+            assert!(crate::var("TEST_TASK_ENV_NAME").is_err());
+            assert!(crate::var("TEST_TASK_ENV_INNER").is_err());
+        });
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{Error, TempGuard};
+
+/// The thread owning a guard changing the state of the whole process (environment variables,
+/// current directory). It is only tracked in debug builds, where dropping the guard on another
+/// thread is reported on stderr.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ThreadOwner {
+    #[cfg(debug_assertions)]
+    thread: std::thread::ThreadId,
+}
+
+impl ThreadOwner {
+    pub(crate) fn current() -> Self {
+        Self {
+            #[cfg(debug_assertions)]
+            thread: std::thread::current().id(),
+        }
+    }
+
+    /// Returns `false` if the guard is used on another thread than its owner
+    #[cfg(debug_assertions)]
+    pub(crate) fn is_current(&self) -> bool {
+        self.thread == std::thread::current().id()
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub(crate) fn is_current(&self) -> bool {
+        true
+    }
+
+    /// Report that the guard is restored on another thread than the one which created
+    /// it (or adopted it), its restoration can then interleave with the changes of its thread
+    pub(crate) fn check<G: Debug>(&self, guard: &G) {
+        if !self.is_current() {
+            crate::trace::warn(format_args!(
+                "{:?} is restored on {:?} but belongs to another thread, call `adopt_thread()` after handing it over",
+                guard,
+                std::thread::current().id()
+            ));
+        }
+    }
+}
+
+/// A wrapper making a guard `!Send` so the compiler refuses to move it to another thread, for the
+/// guards which must be restored on the thread which created them
+/// ```
+/// let guard = tmp_env::LocalGuard::new(tmp_env::set_var("TEST_LOCAL_GUARD", "value"));
+/// assert_eq!(std::env::var("TEST_LOCAL_GUARD").unwrap(), "value");
+/// drop(guard);
+/// assert!(std::env::var("TEST_LOCAL_GUARD").is_err());
+/// ```
+/// ```compile_fail
+/// let guard = tmp_env::LocalGuard::new(tmp_env::set_var("TEST_LOCAL_GUARD", "value"));
+/// std::thread::spawn(move || drop(guard));
+/// ```
+#[must_use = "the change is undone as soon as the guard is dropped"]
+pub struct LocalGuard<G> {
+    guard: G,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<G> LocalGuard<G> {
+    /// Wrap the guard so it can't be sent to another thread
+    pub fn new(guard: G) -> Self {
+        Self {
+            guard,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Unwrap the guard, which can be sent to another thread again
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+}
+
+impl<G> Deref for LocalGuard<G> {
+    type Target = G;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G> DerefMut for LocalGuard<G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<G: Debug> Debug for LocalGuard<G> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.guard)
+    }
+}
+
+impl<G: TempGuard> TempGuard for LocalGuard<G> {
+    fn keep(&mut self) {
+        self.guard.keep();
+    }
+
+    fn restore_now(&mut self) -> Result<(), Error> {
+        self.guard.restore_now()
+    }
+
+    fn is_active(&self) -> bool {
+        self.guard.is_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::set_var;
+
+    #[test]
+    fn test_thread_owner() {
+        let owner = ThreadOwner::current();
+        assert!(owner.is_current());
+        let on_other_thread = std::thread::spawn(move || owner.is_current())
+            .join()
+            .unwrap();
+        assert_eq!(on_other_thread, !cfg!(debug_assertions));
+
+        let mut guard = set_var("TEST_THREAD_OWNER", "value");
+        let guard = std::thread::spawn(move || {
+            guard.adopt_thread();
+            guard
+        })
+        .join()
+        .unwrap();
+        assert_eq!(guard.owner.is_current(), !cfg!(debug_assertions));
+        drop(guard);
+        assert!(std::env::var("TEST_THREAD_OWNER").is_err());
+
+        let mut local = LocalGuard::new(set_var("TEST_THREAD_OWNER", "value"));
+        local.restore_now().unwrap();
+        assert!(!local.is_active());
+        assert!(!local.into_inner().is_active());
+    }
+}
@@ -0,0 +1,119 @@
+/// The span tying together the events of a guard, from its creation to its restoration. It is
+/// empty without the `tracing` feature.
+pub(crate) struct Trace {
+    // A span is only used to record events, it can't be left in an inconsistent state
+    #[cfg(feature = "tracing")]
+    pub(crate) span: std::panic::AssertUnwindSafe<tracing::Span>,
+}
+
+impl Trace {
+    /// For the guards created without event, like the adopted ones
+    pub(crate) fn none() -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: std::panic::AssertUnwindSafe(tracing::Span::none()),
+        }
+    }
+}
+
+/// Create a [`Trace`] with a span named `tmp_env::$name` and these fields, like
+/// `tracing::debug_span!`
+macro_rules! span {
+    ($name:literal, $($fields:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let trace = $crate::trace::Trace {
+            span: ::std::panic::AssertUnwindSafe(::tracing::debug_span!(
+                target: "tmp_env",
+                concat!("tmp_env::", $name),
+                $($fields)*
+            )),
+        };
+        #[cfg(not(feature = "tracing"))]
+        let trace = $crate::trace::Trace::none();
+        trace
+    }};
+}
+
+/// Emit a debug event inside the span of a [`Trace`], like `tracing::debug!`
+macro_rules! event {
+    ($trace:expr, $($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        $trace
+            .span
+            .in_scope(|| ::tracing::debug!(target: "tmp_env", $($args)*));
+        #[cfg(not(feature = "tracing"))]
+        let _ = &$trace;
+    }};
+}
+
+pub(crate) use {event, span};
+
+/// Report a problem which can't be returned to the caller, like a failure when dropped, as a
+/// `tracing` warning with the `tracing` feature or on stderr otherwise
+pub(crate) fn warn(message: std::fmt::Arguments<'_>) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(target: "tmp_env", "{}", message);
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("tmp_env: {}", message);
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::{create_temp_dir, lock_current_dir, remove_var, set_current_dir, set_var};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tracing_events() {
+        let _lock = lock_current_dir();
+        let logs = Logs::default();
+        let subscriber = {
+            let logs = logs.clone();
+            tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::DEBUG)
+                .with_ansi(false)
+                .without_time()
+                .with_writer(move || logs.clone())
+                .finish()
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            // The callsites already hit by the other tests cached that nothing is interested
+            tracing::callsite::rebuild_interest_cache();
+            let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+            let current_dir = set_current_dir(&*tmp_dir).expect("cannot set current dir");
+            drop(set_var("TEST_TRACING_VAR", "value"));
+            drop(remove_var("TEST_TRACING_VAR"));
+            drop(current_dir);
+            tmp_dir.close().unwrap();
+        });
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = logs.lines().collect();
+        let expected: [&[&str]; 8] = [
+            &["tmp_env::tmp_dir{path=", "created"],
+            &["tmp_env::current_dir{original=", "entered"],
+            &["tmp_env::set_var{key=\"TEST_TRACING_VAR\"}: tmp_env: set"],
+            &["tmp_env::set_var{key=\"TEST_TRACING_VAR\"}: tmp_env: restored"],
+            &["tmp_env::remove_var{key=\"TEST_TRACING_VAR\"}: tmp_env: removed"],
+            &["tmp_env::remove_var{key=\"TEST_TRACING_VAR\"}: tmp_env: restored"],
+            &["tmp_env::current_dir{original=", "left"],
+            &["tmp_env::tmp_dir{path=", "deleted"],
+        ];
+        assert_eq!(lines.len(), expected.len(), "{}", logs);
+        for (line, parts) in lines.iter().zip(expected.iter()) {
+            assert!(parts.iter().all(|part| line.contains(part)), "{}", logs);
+        }
+    }
+}
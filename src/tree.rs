@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use crate::{create_temp_dir, TmpDir};
+
+/// A directory tree declared inline which can be materialized on disk, usually with the
+/// [`tree!`](crate::tree!) macro.
+/// ```
+/// let tmp_dir = tmp_env::Tree::new()
+///     .file("Cargo.toml", "[package]")
+///     .dir("src", tmp_env::Tree::new().file("main.rs", "fn main() {}"))
+///     .create()
+///     .expect("cannot create temp dir");
+/// assert_eq!(std::fs::read_to_string(tmp_dir.join("src").join("main.rs")).unwrap(), "fn main() {}");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tree {
+    /// A file with its content
+    File(Vec<u8>),
+    /// A directory with its entries
+    Dir(Vec<(PathBuf, Tree)>),
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Tree::Dir(Vec::new())
+    }
+}
+
+impl Tree {
+    /// Create an empty directory tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file with the given content in this directory
+    pub fn file<P: AsRef<Path>, C: AsRef<[u8]>>(self, name: P, content: C) -> Self {
+        self.entry(name, Tree::File(content.as_ref().to_vec()))
+    }
+
+    /// Add a sub directory in this directory
+    pub fn dir<P: AsRef<Path>>(self, name: P, tree: Tree) -> Self {
+        self.entry(name, tree)
+    }
+
+    fn entry<P: AsRef<Path>>(self, name: P, tree: Tree) -> Self {
+        match self {
+            Tree::Dir(mut entries) => {
+                entries.push((name.as_ref().to_path_buf(), tree));
+                Tree::Dir(entries)
+            }
+            Tree::File(_) => panic!("cannot add {:?} in a file", name.as_ref()),
+        }
+    }
+
+    /// Materialize the tree at the given path, creating the missing parent directories
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        match self {
+            Tree::File(content) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, content)
+            }
+            Tree::Dir(entries) => {
+                std::fs::create_dir_all(path)?;
+                for (name, tree) in entries {
+                    tree.write_to(path.join(name))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Create a temporary directory in the temporary directory of your operating system and
+    /// materialize the tree inside
+    pub fn create(&self) -> Result<TmpDir, std::io::Error> {
+        let tmp_dir = create_temp_dir()?;
+        self.write_to(&*tmp_dir)?;
+
+        Ok(tmp_dir)
+    }
+}
+
+/// Declare a [`Tree`] inline, nested braces declare directories and any other expression is the
+/// content of a file.
+/// ```
+/// const CARGO_TOML: &str = "[package]";
+/// let tmp_dir = tmp_env::tree! {
+///     "src" => { "main.rs" => "fn main() {}" },
+///     "Cargo.toml" => CARGO_TOML,
+/// }
+/// .create()
+/// .expect("cannot create temp dir");
+/// assert_eq!(std::fs::read_to_string(tmp_dir.join("Cargo.toml")).unwrap(), CARGO_TOML);
+/// assert!(tmp_dir.join("src").join("main.rs").is_file());
+/// ```
+#[macro_export]
+macro_rules! tree {
+    (@chain $tree:expr;) => {
+        $tree
+    };
+    (@chain $tree:expr; $name:expr => { $($inner:tt)* } $(, $($rest:tt)*)?) => {
+        $crate::tree!(@chain $tree.dir($name, $crate::tree!($($inner)*)); $($($rest)*)?)
+    };
+    (@chain $tree:expr; $name:expr => $content:expr $(, $($rest:tt)*)?) => {
+        $crate::tree!(@chain $tree.file($name, $content); $($($rest)*)?)
+    };
+    ($($tokens:tt)*) => {
+        $crate::tree!(@chain $crate::Tree::new(); $($tokens)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_macro() {
+        let content = vec![0u8, 1, 2];
+        let tree = tree! {
+            "empty" => {},
+            "a" => {
+                "b" => { "c.txt" => "c" },
+                "bytes" => &content,
+            },
+            "nested/path.txt" => "nested"
+        };
+        assert_eq!(
+            tree,
+            Tree::new()
+                .dir("empty", Tree::new())
+                .dir(
+                    "a",
+                    Tree::new()
+                        .dir("b", Tree::new().file("c.txt", "c"))
+                        .file("bytes", &content)
+                )
+                .file("nested/path.txt", "nested")
+        );
+        let tmp_dir = tree.create().expect("cannot create temp dir");
+        assert!(tmp_dir.join("empty").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.join("a/b/c.txt")).unwrap(),
+            "c"
+        );
+        assert_eq!(std::fs::read(tmp_dir.join("a/bytes")).unwrap(), content);
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.join("nested").join("path.txt")).unwrap(),
+            "nested"
+        );
+        assert_eq!(tree!(), Tree::new());
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static REPORT_UNUSED_GUARDS: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+
+/// Guards dropped later than this after their creation are never reported, so only the guards
+/// dropped right away pay for the backtrace
+#[cfg(debug_assertions)]
+const MAX_UNUSED_DELAY: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Report on stderr the guards changing the state of the whole process (environment variables,
+/// current directory) dropped on the statement which created them, like with
+/// `let _ = tmp_env::set_var(...)`, so nothing is changed. Enabled by default in debug builds,
+/// pass `false` to silence it.
+///
+/// The check is best-effort: it needs debug info, relies on the format of the backtraces of the
+/// standard library and only looks at the guards dropped within a millisecond of their creation,
+/// so the guards kept alive don't pay for a backtrace. It never runs in release builds.
+/// ```
+/// tmp_env::report_unused_guards(true);
+/// let _ = tmp_env::set_var("TEST_REPORT_UNUSED_GUARDS", "value"); // Reported on stderr
+/// ```
+pub fn report_unused_guards(enabled: bool) {
+    REPORT_UNUSED_GUARDS.store(enabled, Ordering::Relaxed);
+}
+
+/// Where and when a guard changing the state of the whole process (environment variables,
+/// current directory) was created, see [`report_unused_guards`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CreatedAt {
+    location: &'static std::panic::Location<'static>,
+    #[cfg(debug_assertions)]
+    instant: std::time::Instant,
+}
+
+impl CreatedAt {
+    #[track_caller]
+    pub(crate) fn caller() -> Self {
+        Self {
+            location: std::panic::Location::caller(),
+            #[cfg(debug_assertions)]
+            instant: std::time::Instant::now(),
+        }
+    }
+
+    /// Returns `true` if the code dropping the guard is on the same line as its creation but not
+    /// at the same column, the columns are the same when both come from the expansion of a macro
+    #[cfg(debug_assertions)]
+    fn is_dropped_on_creation(&self) -> bool {
+        if self.instant.elapsed() > MAX_UNUSED_DELAY {
+            return false;
+        }
+        // Without debug info the backtrace has no line, and the guard is never reported
+        let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+        let lines: Vec<&str> = backtrace.lines().map(str::trim).collect();
+        let drop_glue = match lines
+            .iter()
+            .rposition(|line| line.contains("core::ptr::drop_in_place"))
+        {
+            Some(drop_glue) => drop_glue,
+            None => return false,
+        };
+        // Skip the location of the drop glue and the name of the function dropping the guard
+        let mut dropped_at = lines[drop_glue + 1..]
+            .iter()
+            .skip_while(|line| line.starts_with("at "))
+            .skip(1);
+        let dropped_at = match dropped_at.next().and_then(|line| line.strip_prefix("at ")) {
+            Some(dropped_at) => dropped_at,
+            None => return false,
+        };
+        let mut parts = dropped_at.rsplitn(3, ':');
+        let (column, line, file) = (parts.next(), parts.next(), parts.next());
+        // The backtrace paths are relative to the current directory, which the guard can change
+        file.and_then(|file| std::path::Path::new(file).file_name())
+            == std::path::Path::new(self.location.file()).file_name()
+            && line == Some(&*self.location.line().to_string())
+            && column != Some(&*self.location.column().to_string())
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn is_dropped_on_creation(&self) -> bool {
+        false
+    }
+
+    /// Report that the guard is dropped on the statement which created it, so the
+    /// change is undone right away. Called once the change is undone, so the backtrace doesn't
+    /// delay it.
+    pub(crate) fn check<G: Debug>(&self, guard: &G) {
+        if REPORT_UNUSED_GUARDS.load(Ordering::Relaxed) && self.is_dropped_on_creation() {
+            crate::trace::warn(format_args!(
+                "{:?} created at {} is dropped right away so nothing is changed, bind it to a named variable like `let _guard = ...` (`let _ = ...` drops it immediately)",
+                guard, self.location
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_created_at() {
+        // Without debug info the backtrace has no line, and the guards are never reported
+        if !std::backtrace::Backtrace::force_capture()
+            .to_string()
+            .contains("unused_guard.rs")
+        {
+            return;
+        }
+        struct Guard(CreatedAt, std::rc::Rc<std::cell::Cell<bool>>);
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.1.set(self.0.is_dropped_on_creation());
+            }
+        }
+        let dropped_right_away = std::rc::Rc::new(std::cell::Cell::new(false));
+
+        let _ = Guard(CreatedAt::caller(), dropped_right_away.clone());
+        assert_eq!(dropped_right_away.get(), cfg!(debug_assertions));
+        {
+            let _guard = Guard(CreatedAt::caller(), dropped_right_away.clone());
+        }
+        assert!(!dropped_right_away.get());
+        drop(Guard(CreatedAt::caller(), dropped_right_away.clone()));
+        assert!(!dropped_right_away.get());
+    }
+}
@@ -0,0 +1,126 @@
+//! A virtual working directory local to the current thread.
+//!
+//! The current directory is global to the process so changing it with
+//! [`set_current_dir`](crate::set_current_dir) is visible from every thread (and breaks tests
+//! running in parallel). Code resolving its relative paths with [`resolve`] can instead rely on
+//! [`set`] which only changes the working directory of the current thread.
+//! ```
+//! let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+//! {
+//!     let _vcwd = tmp_env::vcwd::set(&*tmp_dir).expect("should set the virtual current dir");
+//!     assert_eq!(tmp_env::vcwd::current().unwrap(), *tmp_dir);
+//!     assert_eq!(tmp_env::vcwd::resolve("file.txt").unwrap(), tmp_dir.join("file.txt"));
+//!     // The real current directory of the process is untouched
+//!     assert_ne!(std::env::current_dir().unwrap(), *tmp_dir);
+//! }
+//! // Because guard is dropped we fall back to the current directory of the process
+//! assert_eq!(tmp_env::vcwd::current().unwrap(), std::env::current_dir().unwrap());
+//! ```
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    static CURRENT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// A helper datastructure for ensuring that we switch back to the previous virtual current
+/// directory of the thread before the end of the current scope.
+#[must_use = "the virtual current directory is restored as soon as the guard is dropped"]
+pub struct VirtualDir {
+    previous: Option<PathBuf>,
+    // The virtual current directory is local to the thread which created the guard
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Debug for VirtualDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.previous)
+    }
+}
+
+/// Switch the virtual current directory of the current thread to the given path. A relative path
+/// is resolved against the current virtual directory. Once the datastructure is dropped, switch
+/// back to the previous virtual current directory automatically.
+pub fn set<P: AsRef<Path>>(path: P) -> Result<VirtualDir, std::io::Error> {
+    let path = resolve(path)?;
+    if !std::fs::metadata(&path)?.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{:?} is not a directory",
+            path
+        )));
+    }
+    let previous = CURRENT.with(|current| current.replace(Some(path)));
+
+    Ok(VirtualDir {
+        previous,
+        _not_send: PhantomData,
+    })
+}
+
+/// Returns the virtual current directory of the current thread or the current directory of the
+/// process if none has been set.
+pub fn current() -> Result<PathBuf, std::io::Error> {
+    match CURRENT.with(|current| current.borrow().clone()) {
+        Some(path) => Ok(path),
+        None => std::env::current_dir(),
+    }
+}
+
+/// Resolve the given path against the virtual current directory of the current thread. Absolute
+/// paths are returned as is.
+pub fn resolve<P: AsRef<Path>>(path: P) -> Result<PathBuf, std::io::Error> {
+    let path = path.as_ref();
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(current()?.join(path))
+    }
+}
+
+impl Drop for VirtualDir {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_current_dir;
+
+    #[test]
+    fn test_vcwd() {
+        let _lock = lock_current_dir();
+        let process_dir = std::env::current_dir().expect("cannot get current dir from std env");
+        {
+            let _vcwd = set("src").expect("should set the virtual current dir");
+            assert_eq!(current().unwrap(), process_dir.join("src"));
+            {
+                let _vcwd = set("..").expect("should set the virtual current dir");
+                assert_eq!(
+                    resolve("Cargo.toml").unwrap(),
+                    process_dir.join("src/../Cargo.toml")
+                );
+            }
+            assert_eq!(resolve("lib.rs").unwrap(), process_dir.join("src/lib.rs"));
+            assert!(set("lib.rs").is_err());
+            assert!(set("does_not_exist").is_err());
+        }
+        assert_eq!(current().unwrap(), process_dir);
+    }
+
+    #[test]
+    fn test_vcwd_is_thread_local() {
+        let _lock = lock_current_dir();
+        let _vcwd = set("src").expect("should set the virtual current dir");
+        let process_dir = std::env::current_dir().expect("cannot get current dir from std env");
+        let other_thread_dir = std::thread::spawn(|| current().unwrap()).join().unwrap();
+        assert_eq!(other_thread_dir, process_dir);
+        assert_eq!(current().unwrap(), process_dir.join("src"));
+    }
+}
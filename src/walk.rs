@@ -0,0 +1,140 @@
+use std::{
+    fs::{DirEntry, ReadDir},
+    path::Path,
+};
+
+use crate::{long_path, TmpDir};
+
+/// An iterator over the entries of a directory and all its sub directories, depth first. Symlinks
+/// are returned but never followed. See [`TmpDir::walk`].
+#[derive(Debug)]
+pub struct Walk {
+    stack: Vec<ReadDir>,
+    pending_error: Option<std::io::Error>,
+}
+
+impl Walk {
+    pub(crate) fn new(dir: &Path) -> Self {
+        match std::fs::read_dir(dir) {
+            Ok(read_dir) => Self {
+                stack: vec![read_dir],
+                pending_error: None,
+            },
+            Err(err) => Self {
+                stack: Vec::new(),
+                pending_error: Some(err),
+            },
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<DirEntry, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.pending_error.take() {
+            return Some(Err(err));
+        }
+        loop {
+            let entry = match self.stack.last_mut()?.next() {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err)),
+            };
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => match std::fs::read_dir(entry.path()) {
+                    Ok(read_dir) => self.stack.push(read_dir),
+                    Err(err) => self.pending_error = Some(err),
+                },
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+            return Some(Ok(entry));
+        }
+    }
+}
+
+impl TmpDir {
+    /// Iterate over the entries directly inside the temporary directory
+    pub fn entries(&self) -> Result<ReadDir, std::io::Error> {
+        std::fs::read_dir(&self.path)
+    }
+
+    /// Recursively iterate over all the entries inside the temporary directory, without
+    /// following symlinks
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("a/b.txt", "content").expect("cannot write file");
+    /// let mut paths: Vec<_> = tmp_dir.walk().map(|entry| entry.unwrap().path()).collect();
+    /// paths.sort();
+    /// assert_eq!(paths, vec![tmp_dir.join("a"), tmp_dir.join("a").join("b.txt")]);
+    /// ```
+    pub fn walk(&self) -> Walk {
+        Walk::new(&long_path(&self.path))
+    }
+
+    /// Total size in bytes of the files inside the temporary directory, symlinks are not followed
+    /// ```
+    /// let tmp_dir = tmp_env::create_temp_dir().expect("cannot create temp dir");
+    /// tmp_dir.write("a.txt", "12345").expect("cannot write file");
+    /// tmp_dir.write("sub/b.txt", "123").expect("cannot write file");
+    /// assert_eq!(tmp_dir.size().unwrap(), 8);
+    /// ```
+    pub fn size(&self) -> Result<u64, std::io::Error> {
+        dir_size(&long_path(&self.path))
+    }
+}
+
+/// Total size in bytes of the files inside `dir`, see [`TmpDir::size`]
+pub(crate) fn dir_size(dir: &Path) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in Walk::new(dir) {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_temp_dir;
+
+    #[test]
+    fn test_tmp_dir_walk() {
+        let tmp_dir = create_temp_dir().expect("cannot create temp dir");
+        assert_eq!(tmp_dir.walk().count(), 0);
+        assert_eq!(tmp_dir.size().unwrap(), 0);
+        tmp_dir.write("a/b/c.txt", vec![0; 1000]).unwrap();
+        tmp_dir.write("a/d.txt", vec![0; 24]).unwrap();
+        tmp_dir.create_dir("e").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a", tmp_dir.join("link")).unwrap();
+
+        let mut paths: Vec<_> = tmp_dir.walk().map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut expected = vec![
+            tmp_dir.join("a"),
+            tmp_dir.join("a/b"),
+            tmp_dir.join("a/b/c.txt"),
+            tmp_dir.join("a/d.txt"),
+            tmp_dir.join("e"),
+        ];
+        #[cfg(unix)]
+        expected.push(tmp_dir.join("link"));
+        assert_eq!(paths, expected);
+        assert_eq!(tmp_dir.entries().unwrap().count(), expected.len() - 3);
+        assert_eq!(tmp_dir.size().unwrap(), 1024);
+
+        assert!(Walk::new(&tmp_dir.join("missing")).next().unwrap().is_err());
+    }
+}
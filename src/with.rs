@@ -0,0 +1,110 @@
+use std::{
+    ffi::OsStr,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
+use crate::{create_temp_dir, remove_var, set_current_dir, set_var, Error, TempGuard, TmpDir};
+
+/// Run `f` while the guard is alive and restore it right after, even if `f` panics. When `f`
+/// panics the guard is dropped (reporting its errors like when dropped) before the panic
+/// resumes, otherwise the error of the restoration is returned.
+fn with_guard<G: TempGuard, R, F: FnOnce(&G) -> R>(mut guard: G, f: F) -> Result<R, Error> {
+    match catch_unwind(AssertUnwindSafe(|| f(&guard))) {
+        Ok(value) => guard.restore_now().map(|()| value),
+        Err(panic) => {
+            drop(guard);
+            resume_unwind(panic)
+        }
+    }
+}
+
+/// Run `f` with the environment variable `key` set to `value` and restore it once `f` returns or
+/// panics, see [`set_var`]
+/// ```
+/// let value = tmp_env::with_var("TEST_WITH_VAR", "value", || std::env::var("TEST_WITH_VAR").unwrap());
+/// assert_eq!(value, "value");
+/// assert!(std::env::var("TEST_WITH_VAR").is_err());
+/// ```
+pub fn with_var<K: AsRef<OsStr>, V: AsRef<OsStr>, R, F: FnOnce() -> R>(
+    key: K,
+    value: V,
+    f: F,
+) -> R {
+    with_guard(set_var(key, value), |_| f()).expect("restoring an environment variable can't fail")
+}
+
+/// Run `f` with the environment variable `key` removed and restore it once `f` returns or
+/// panics, see [`remove_var`]
+pub fn with_removed_var<K: AsRef<OsStr>, R, F: FnOnce() -> R>(key: K, f: F) -> R {
+    with_guard(remove_var(key), |_| f()).expect("restoring an environment variable can't fail")
+}
+
+/// Run `f` in the directory `path` and switch back to the original directory once `f` returns or
+/// panics, see [`set_current_dir`]. It fails if the current directory can't be changed or
+/// restored.
+/// ```
+/// let original_dir = std::env::current_dir().expect("cannot get current dir from std env");
+/// let in_src = tmp_env::with_current_dir("src", || std::env::current_dir().unwrap().ends_with("src"))
+///     .expect("cannot switch to src");
+/// assert!(in_src);
+/// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+/// ```
+pub fn with_current_dir<P: AsRef<Path>, R, F: FnOnce() -> R>(path: P, f: F) -> Result<R, Error> {
+    with_guard(set_current_dir(path)?, |_| f())
+}
+
+/// Run `f` with a new temporary directory and delete it once `f` returns or panics, see
+/// [`create_temp_dir`]. It fails if the directory can't be created or deleted.
+/// ```
+/// let path = tmp_env::with_temp_dir(|tmp_dir| {
+///     tmp_dir.write("a.txt", "content").expect("cannot write file");
+///     tmp_dir.to_path_buf()
+/// })
+/// .expect("cannot create temp dir");
+/// assert!(!path.exists());
+/// ```
+pub fn with_temp_dir<R, F: FnOnce(&TmpDir) -> R>(f: F) -> Result<R, Error> {
+    with_guard(create_temp_dir()?, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_current_dir;
+
+    #[test]
+    fn test_with_restores_on_panic() {
+        let _lock = lock_current_dir();
+        let original_dir = std::env::current_dir().unwrap();
+        std::env::set_var("TEST_WITH_REMOVED", "restored");
+        let mut dir_path = None;
+        let panic = catch_unwind(AssertUnwindSafe(|| {
+            with_var("TEST_WITH_PANIC", "value", || {
+                with_removed_var("TEST_WITH_REMOVED", || {
+                    with_temp_dir(|tmp_dir| {
+                        dir_path = Some(tmp_dir.to_path_buf());
+                        with_current_dir(&**tmp_dir, || panic!("boom")).unwrap()
+                    })
+                    .unwrap()
+                })
+            })
+        }));
+        assert_eq!(*panic.unwrap_err().downcast::<&str>().unwrap(), "boom");
+        assert!(std::env::var("TEST_WITH_PANIC").is_err());
+        assert_eq!(std::env::var("TEST_WITH_REMOVED").unwrap(), "restored");
+        assert_eq!(std::env::current_dir().unwrap(), original_dir);
+        assert!(!dir_path.unwrap().exists());
+        std::env::remove_var("TEST_WITH_REMOVED");
+
+        assert!(with_current_dir(original_dir.join("missing-dir"), || ()).is_err());
+        // Restoring fails if the original directory has been removed in the meantime
+        let removed = create_temp_dir().expect("cannot create temp dir");
+        let _back = set_current_dir(&*removed).unwrap();
+        let res = with_current_dir(&original_dir, || {
+            std::fs::remove_dir(&*removed).unwrap();
+            42
+        });
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! The `#[tmp_env]` attribute macro of the [tmp_env](https://docs.rs/tmp_env) crate, use it
+//! through the `macros` feature of `tmp_env`.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Error, Expr, FnArg, Ident, ItemFn, LitStr, Token, Type,
+};
+
+/// Create temporary changes around a function, usually a test, and undo them in the reverse
+/// order once it returns or panics. The lock of `tmp_env::lock_current_dir()` is held during the
+/// whole function so the tests using this attribute don't change the environment of each other.
+/// The changes are made in the order they are written:
+/// - `set(KEY = "value", ...)` sets environment variables, see `tmp_env::set_var()`
+/// - `remove(KEY, ...)` removes environment variables, see `tmp_env::remove_var()`
+/// - `temp_dir` creates a temporary directory, passed to the function if it takes a `&TmpDir`
+///   (or `TmpDir`) argument, see `tmp_env::create_temp_dir()`
+/// - `current_dir = "path"` switches the current directory, or to the temporary directory with
+///   just `current_dir`, see `tmp_env::set_current_dir()`
+///
+/// The keys are identifiers or string literals. The function panics if a change can't be made.
+/// ```
+/// use tmp_env::TmpDir;
+///
+/// #[tmp_env::tmp_env(set(RUST_LOG = "debug"), remove("HOME"), temp_dir, current_dir)]
+/// fn my_test(tmp: &TmpDir) {
+///     assert_eq!(std::env::var("RUST_LOG").unwrap(), "debug");
+///     assert!(std::env::var("HOME").is_err());
+///     assert_eq!(std::env::current_dir().unwrap(), **tmp);
+/// }
+///
+/// let original_dir = std::env::current_dir().unwrap();
+/// my_test();
+/// assert!(std::env::var("RUST_LOG").is_err());
+/// assert_eq!(std::env::current_dir().unwrap(), original_dir);
+/// ```
+#[proc_macro_attribute]
+pub fn tmp_env(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let changes = parse_macro_input!(attr with Punctuated::<Change, Token![,]>::parse_terminated);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(changes.into_iter().collect(), function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Key {
+    Ident(Ident),
+    Str(LitStr),
+}
+
+impl Parse for Key {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            Ok(Key::Str(input.parse()?))
+        } else {
+            Ok(Key::Ident(input.parse()?))
+        }
+    }
+}
+
+impl ToTokens for Key {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        match self {
+            Key::Ident(ident) => LitStr::new(&ident.to_string(), ident.span()).to_tokens(tokens),
+            Key::Str(lit) => lit.to_tokens(tokens),
+        }
+    }
+}
+
+struct SetVar {
+    key: Key,
+    value: Expr,
+}
+
+impl Parse for SetVar {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(SetVar {
+            key,
+            value: input.parse()?,
+        })
+    }
+}
+
+enum Change {
+    Set(Vec<SetVar>),
+    Remove(Vec<Key>),
+    TempDir,
+    CurrentDir(Ident, Option<Expr>),
+}
+
+impl Parse for Change {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        match name.to_string().as_str() {
+            "set" => {
+                let content;
+                parenthesized!(content in input);
+                let vars = Punctuated::<SetVar, Token![,]>::parse_terminated(&content)?;
+                Ok(Change::Set(vars.into_iter().collect()))
+            }
+            "remove" => {
+                let content;
+                parenthesized!(content in input);
+                let keys = Punctuated::<Key, Token![,]>::parse_terminated(&content)?;
+                Ok(Change::Remove(keys.into_iter().collect()))
+            }
+            "temp_dir" => Ok(Change::TempDir),
+            "current_dir" => {
+                let path = if input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    Some(input.parse()?)
+                } else {
+                    None
+                };
+                Ok(Change::CurrentDir(name, path))
+            }
+            _ => Err(Error::new(
+                name.span(),
+                "expected `set(..)`, `remove(..)`, `temp_dir` or `current_dir`",
+            )),
+        }
+    }
+}
+
+fn expand(changes: Vec<Change>, mut function: ItemFn) -> syn::Result<TokenStream2> {
+    let tmp_dir = quote!(__tmp_env_dir);
+    let has_tmp_dir = changes
+        .iter()
+        .any(|change| matches!(change, Change::TempDir));
+    let mut guards = Vec::new();
+    for change in &changes {
+        match change {
+            Change::Set(vars) => {
+                for SetVar { key, value } in vars {
+                    guards.push(quote!(let _guard = ::tmp_env::set_var(#key, #value);));
+                }
+            }
+            Change::Remove(keys) => {
+                for key in keys {
+                    guards.push(quote!(let _guard = ::tmp_env::remove_var(#key);));
+                }
+            }
+            Change::TempDir => guards.push(quote! {
+                let #tmp_dir = ::tmp_env::create_temp_dir().expect("cannot create temp dir");
+            }),
+            Change::CurrentDir(name, path) => {
+                let path = match path {
+                    Some(path) => quote!(#path),
+                    None if has_tmp_dir => quote!(&*#tmp_dir),
+                    None => {
+                        return Err(Error::new(
+                            name.span(),
+                            "`current_dir` without path needs `temp_dir`",
+                        ))
+                    }
+                };
+                guards.push(quote! {
+                    let _guard = ::tmp_env::set_current_dir(#path)
+                        .expect("should set the new current_dir");
+                });
+            }
+        }
+    }
+
+    let inputs = std::mem::take(&mut function.sig.inputs);
+    let mut inputs = inputs.into_iter();
+    let argument = match (inputs.next(), inputs.next()) {
+        (None, _) => None,
+        (Some(FnArg::Typed(arg)), None) if has_tmp_dir => {
+            let (pat, ty) = (&arg.pat, &arg.ty);
+            Some(match &**ty {
+                Type::Reference(_) => quote!(let #pat: #ty = &#tmp_dir;),
+                _ => quote!(let #pat: #ty = #tmp_dir;),
+            })
+        }
+        (Some(arg), _) => {
+            return Err(Error::new_spanned(
+                arg,
+                "only a `&TmpDir` argument is supported, with `temp_dir`",
+            ))
+        }
+    };
+
+    let block = &function.block;
+    function.block = syn::parse2(quote! {{
+        let _lock = ::tmp_env::lock_current_dir();
+        #(#guards)*
+        #argument
+        #block
+    }})?;
+
+    Ok(function.into_token_stream())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let changes: Punctuated<Change, Token![,]> = syn::parse_quote!(
+            set(RUST_LOG = "debug", "OTHER" = 1.to_string()),
+            remove(HOME),
+            temp_dir,
+            current_dir
+        );
+        let function = syn::parse_quote! {
+            #[test]
+            fn my_test(tmp: &TmpDir) -> Result<(), ()> {
+                Ok(())
+            }
+        };
+        let expanded = expand(changes.into_iter().collect(), function)
+            .unwrap()
+            .to_string();
+        assert!(expanded.starts_with("# [test] fn my_test () -> Result < () , () >"));
+        for expected in &[
+            "set_var (\"RUST_LOG\" , \"debug\")",
+            "set_var (\"OTHER\" , 1 . to_string ())",
+            "remove_var (\"HOME\")",
+            "set_current_dir (& * __tmp_env_dir)",
+            "let tmp : & TmpDir = & __tmp_env_dir ;",
+        ] {
+            assert!(expanded.contains(expected), "{}", expanded);
+        }
+
+        let errors = [
+            syn::parse_str::<Change>("unknown").err(),
+            expand(
+                vec![syn::parse_quote!(current_dir)],
+                syn::parse_quote!(
+                    fn f() {}
+                ),
+            )
+            .err(),
+            expand(
+                Vec::new(),
+                syn::parse_quote!(
+                    fn f(tmp: &TmpDir) {}
+                ),
+            )
+            .err(),
+        ];
+        assert!(errors.iter().all(Option::is_some));
+    }
+}